@@ -1,35 +1,349 @@
 //! This application loads a larod model which takes an image as input and outputs values
 //! corresponding to the class, score and location of detected objects in the image.
 //!
+//! Frames are captured from VDO as NV12, converted to RGB and scaled to the input size of the
+//! model using larod, and fed to the model without copying them. Detections with a score above
+//! the threshold are logged and drawn as bounding boxes on the video using the Bounding Box API.
+//!
+//! The model is expected to be an SSD model ending in a `TFLite_Detection_PostProcess` layer,
+//! like the ones in the [TensorFlow model zoo](https://github.com/tensorflow/models/blob/master/research/object_detection/g3doc/tf1_detection_zoo.md).
+//! It must have four outputs:
+//!
+//! 1. Locations as `[top, left, bottom, right]`, normalized to the range 0 to 1.
+//! 2. Classes, as indices into the labels file.
+//! 3. Scores, in the range 0 to 1.
+//! 4. The number of valid detections.
+//!
+//! The model and labels are not included. The run options in `manifest.json` expect them to be
+//! named `model.tflite` and `labels.txt`; place them in `additional-files/` before building the
+//! app to package them with it, or change the run options to point to where they are installed.
+//!
 //! # Arguments
 //!
 //! 1. `MODEL`: a string describing the path to the model.
-//! 2. `WIDTH`: an integer for the input width. ß
+//! 2. `WIDTH`: an integer for the input width.
 //! 3. `HEIGHT`: an integer for the input height.
-//! 4. `QUALITY`: an integer for the desired jpeg quality.
+//! 4. `QUALITY`: an integer for the desired jpeg quality. Ignored since no images are saved.
 //! 5. `RAW_WIDTH`: an integer for camera width resolution.
 //! 6. `RAW_HEIGHT`: an integer for camera height resolution.
 //! 7. `THRESHOLD`: an integer ranging from 0 to 100 to select good detections.
 //! 8. `LABELSFILE`: a string describing the path to the label txt.
+//! 9. `DEVICE`: optional name of the larod device to run the model on, default `cpu-tflite`.
+
+use std::{
+    env,
+    ffi::CString,
+    fs::{self, File},
+    os::fd::AsFd,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use bbox::flex::{Bbox, Color};
+use larod::{Access, Connection, FdProps, JobRequest, Map, Model, TensorDataType};
+use log::{debug, info};
+use vdo::{Resolution, StreamBuilder, VdoFormat};
+
+/// The video channel to analyze and draw on.
+const CHANNEL: u32 = 1;
+
+struct Args {
+    model: PathBuf,
+    width: u32,
+    height: u32,
+    raw_width: u32,
+    raw_height: u32,
+    threshold: f32,
+    labels_file: PathBuf,
+    device: CString,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let model = next_arg(&mut args, "MODEL")?;
+        let width = next_arg(&mut args, "WIDTH")?;
+        let height = next_arg(&mut args, "HEIGHT")?;
+        let _quality: u32 = next_arg(&mut args, "QUALITY")?;
+        let raw_width = next_arg(&mut args, "RAW_WIDTH")?;
+        let raw_height = next_arg(&mut args, "RAW_HEIGHT")?;
+        let threshold: u32 = next_arg(&mut args, "THRESHOLD")?;
+        if threshold > 100 {
+            bail!("THRESHOLD must be in the range 0 to 100 but got {threshold}");
+        }
+        let labels_file = next_arg(&mut args, "LABELSFILE")?;
+        let device = match args.next() {
+            Some(device) => CString::new(device).context("DEVICE contains a nul byte")?,
+            None => CString::from(c"cpu-tflite"),
+        };
+        Ok(Self {
+            model,
+            width,
+            height,
+            raw_width,
+            raw_height,
+            threshold: threshold as f32 / 100.0,
+            labels_file,
+            device,
+        })
+    }
+}
+
+fn next_arg<T>(args: &mut impl Iterator<Item = String>, name: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let arg = args
+        .next()
+        .with_context(|| format!("Missing argument {name}"))?;
+    arg.parse()
+        .with_context(|| format!("Invalid argument {name}: {arg:?}"))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Detection {
+    class: usize,
+    score: f32,
+    top: f32,
+    left: f32,
+    bottom: f32,
+    right: f32,
+}
+
+/// Decodes the outputs of a `TFLite_Detection_PostProcess` layer.
+///
+/// Returns the detections with a score of at least `threshold`, with the locations clamped to the
+/// frame.
+fn decode_detections(
+    locations: &[f32],
+    classes: &[f32],
+    scores: &[f32],
+    count: f32,
+    threshold: f32,
+) -> Vec<Detection> {
+    let count = (count.max(0.0) as usize)
+        .min(locations.len() / 4)
+        .min(classes.len())
+        .min(scores.len());
+    (0..count)
+        .filter(|&i| scores[i] >= threshold)
+        .map(|i| {
+            let [top, left, bottom, right] =
+                [0, 1, 2, 3].map(|j| locations[4 * i + j].clamp(0.0, 1.0));
+            Detection {
+                class: classes[i].max(0.0) as usize,
+                score: scores[i],
+                top,
+                left,
+                bottom,
+                right,
+            }
+        })
+        .collect()
+}
+
+/// Interprets the bytes of a tensor as native endian `f32`s.
+fn floats(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|c| f32::from_ne_bytes(c.try_into().unwrap()))
+        .collect()
+}
+
+/// Parses a labels file with one label per line, in class order.
+fn parse_labels(text: &str) -> Vec<String> {
+    text.lines().map(|l| l.trim().to_string()).collect()
+}
 
-use log::{error, info};
+/// Loads a model that converts NV12 frames from VDO to the RGB input expected by the model.
+fn load_preprocessing(connection: &Connection, args: &Args) -> anyhow::Result<Model> {
+    let device = connection.device(c"cpu-proc", 0)?;
+    let mut params = Map::new()?;
+    params.set_string(c"image.input.format", c"nv12")?;
+    params.set_int_arr2(
+        c"image.input.size",
+        [args.raw_width.into(), args.raw_height.into()],
+    )?;
+    params.set_string(c"image.output.format", c"rgb-interleaved")?;
+    params.set_int_arr2(
+        c"image.output.size",
+        [args.width.into(), args.height.into()],
+    )?;
+    Ok(connection.load_model(
+        None,
+        &device,
+        Access::LAROD_ACCESS_PRIVATE,
+        c"preprocessing",
+        Some(&params),
+    )?)
+}
+
+fn draw(bbox: &mut Bbox, detections: &[Detection]) -> anyhow::Result<()> {
+    bbox.try_clear()?;
+    bbox.try_style_outline()?;
+    bbox.try_thickness_medium()?;
+    bbox.try_color(Color::from_rgb(0xff, 0, 0))?;
+    for d in detections {
+        bbox.try_rectangle(d.left, d.top, d.right, d.bottom)?;
+    }
+    bbox.try_commit(0)?;
+    Ok(())
+}
+
+fn run(args: Args) -> anyhow::Result<()> {
+    let labels = parse_labels(
+        &fs::read_to_string(&args.labels_file)
+            .with_context(|| format!("Could not read labels from {:?}", args.labels_file))?,
+    );
+
+    let connection = Connection::new().context("Could not connect to larod")?;
+    info!("Number of sessions: {}", connection.num_sessions()?);
+
+    let device = connection
+        .device(&args.device, 0)
+        .with_context(|| format!("Could not get device {:?}", args.device))?;
+    let file = File::open(&args.model)
+        .with_context(|| format!("Could not open model {:?}", args.model))?;
+    let model = connection
+        .load_model(
+            Some(file.as_fd()),
+            &device,
+            Access::LAROD_ACCESS_PRIVATE,
+            c"object_detection",
+            None,
+        )
+        .context("Could not load model")?;
+    if model.num_outputs()? < 4 {
+        bail!(
+            "Expected a model with at least 4 outputs but it has {}",
+            model.num_outputs()?
+        );
+    }
+    let preprocessing =
+        load_preprocessing(&connection, &args).context("Could not load preprocessing")?;
+
+    // The output of the preprocessing is the input of the model.
+    let rgb = connection.alloc_model_outputs(&preprocessing, FdProps::MAP, None)?;
+    let outputs = connection.alloc_model_outputs(&model, FdProps::MAP, None)?;
+    // The outputs are read as `f32`, which is what `TFLite_Detection_PostProcess` produces.
+    for (i, output) in outputs.iter().take(4).enumerate() {
+        let data_type = output.data_type()?;
+        if data_type != TensorDataType::LAROD_TENSOR_DATA_TYPE_FLOAT32 {
+            bail!("Expected output {i} to be of type FLOAT32 but it is {data_type:?}");
+        }
+    }
+    // The input of the preprocessing is bound to the VDO buffers, one frame at a time.
+    let mut conversion =
+        JobRequest::with_owned_inputs(&preprocessing, preprocessing.create_inputs()?, &rgb, None)?;
+    let inference = JobRequest::new(&model, &rgb, &outputs, None)?;
+
+    let mut bbox = Bbox::try_view_new(CHANNEL)?;
+    // VDO delivers YUV frames as NV12 by default.
+    let stream = StreamBuilder::new()
+        .channel(CHANNEL)
+        .format(VdoFormat::VDO_FORMAT_YUV)
+        .resolution(Resolution::Exact {
+            width: args.raw_width,
+            height: args.raw_height,
+        })
+        .build()
+        .context("Could not create stream")?
+        .start()?;
 
-fn main() {
+    info!("Running object detection");
+    loop {
+        let buffer = stream.next_buffer()?;
+        // The preprocessing maps the frame into memory rather than using it as a dma-buf.
+        let job = conversion.bind_vdo_buffer(0, &buffer, FdProps::MAP | FdProps::DMABUF)?;
+        connection.run_job(&job)?;
+        drop(job);
+        drop(buffer);
+
+        connection.run_job(&inference)?;
+        let detections = decode_detections(
+            &floats(&outputs[0].map()?),
+            &floats(&outputs[1].map()?),
+            &floats(&outputs[2].map()?),
+            floats(&outputs[3].map()?).first().copied().unwrap_or(0.0),
+            args.threshold,
+        );
+
+        for d in &detections {
+            let label = labels.get(d.class).map_or("unknown", String::as_str);
+            info!(
+                "Detected {label} with score {:.2} at top: {:.2}, left: {:.2}, bottom: {:.2}, right: {:.2}",
+                d.score, d.top, d.left, d.bottom, d.right
+            );
+        }
+        debug!("Found {} detections", detections.len());
+        draw(&mut bbox, &detections)?;
+    }
+}
+
+fn main() -> anyhow::Result<()> {
     acap_logging::init_logger();
-    let mut conn: *mut larod_sys::larodConnection = std::ptr::null_mut();
-    let mut error: *mut larod_sys::larodError = std::ptr::null_mut();
-    if unsafe { !larod_sys::larodConnect(&mut conn, &mut error) } {
-        error!("Could not connect to larod");
-        return;
+    let args = Args::parse(env::args().skip(1))?;
+    run(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detections_below_threshold_are_dropped() {
+        let locations = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8];
+        let detections = decode_detections(&locations, &[3.0, 7.0], &[0.9, 0.4], 2.0, 0.5);
+        assert_eq!(
+            detections,
+            vec![Detection {
+                class: 3,
+                score: 0.9,
+                top: 0.1,
+                left: 0.2,
+                bottom: 0.3,
+                right: 0.4,
+            }]
+        );
     }
-    assert!(error.is_null());
 
-    let mut num_sessions = u64::MAX;
-    if unsafe { !larod_sys::larodGetNumSessions(conn, &mut num_sessions, &mut error) } {
-        error!("Could not get the number of sessions");
-        return;
+    #[test]
+    fn count_and_locations_are_clamped() {
+        let detections = decode_detections(&[-0.5, 0.0, 1.0, 1.5], &[0.0], &[1.0], 10.0, 0.0);
+        assert_eq!(detections.len(), 1);
+        assert_eq!(detections[0].top, 0.0);
+        assert_eq!(detections[0].right, 1.0);
     }
 
-    info!("Number of sessions: {num_sessions}");
-    todo!("Implement the real example")
+    #[test]
+    fn args_are_parsed() {
+        let args = Args::parse(
+            [
+                "model.tflite",
+                "300",
+                "300",
+                "80",
+                "1920",
+                "1080",
+                "50",
+                "labels.txt",
+            ]
+            .into_iter()
+            .map(String::from),
+        )
+        .unwrap();
+        assert_eq!((args.raw_width, args.raw_height), (1920, 1080));
+        assert_eq!(args.threshold, 0.5);
+        assert_eq!(args.device.as_c_str(), c"cpu-tflite");
+        assert!(Args::parse(["model.tflite", "300"].into_iter().map(String::from)).is_err());
+    }
+
+    #[test]
+    fn labels_are_parsed_in_order() {
+        assert_eq!(
+            parse_labels("person\r\nbicycle\n"),
+            vec!["person", "bicycle"]
+        );
+    }
 }
@@ -0,0 +1,78 @@
+#![forbid(unsafe_code)]
+//! The event that the `send_event` example declares and sends.
+//!
+//! This is a library so that the `subscribe_to_event` example can be tested against the same
+//! declaration.
+
+use std::sync::Arc;
+
+use axevent::flex::{Declaration, Event, Handler, KeyValueSet};
+use log::info;
+
+struct AppData {
+    handler: Arc<Handler>,
+    event_id: Declaration,
+    value: f64,
+}
+
+/// Create the key-value set that the event is declared with.
+pub fn declaration_key_value_set(start_value: f64) -> anyhow::Result<KeyValueSet> {
+    let mut key_value_set = KeyValueSet::new();
+    key_value_set
+        .add_key_value(c"topic0", Some(c"tns1"), Some(c"Monitoring"))?
+        .add_key_value(c"topic1", Some(c"tns1"), Some(c"ProcessorUsage"))?
+        .add_key_value(c"Token", None, Some(0))?
+        .add_key_value(c"Value", None, Some(start_value))?
+        .mark_as_source(c"Token", None)?
+        .mark_as_user_defined(c"Token", None, c"wstype:tt:ReferenceToken")?
+        .mark_as_data(c"Value", None)?
+        .mark_as_user_defined(c"Value", None, c"wstype:xs:float")?;
+    Ok(key_value_set)
+}
+
+/// Create an event that updates the value of the declaration.
+pub fn value_event(value: f64) -> Event {
+    let mut key_value_set = KeyValueSet::new();
+    let _ = key_value_set.add_key_value(c"Value", None, Some(value));
+    Event::new2(key_value_set, None)
+}
+
+fn send_event(app_data: &mut AppData) -> glib::ControlFlow {
+    let event = value_event(app_data.value);
+    let _ = app_data.handler.send_event(event, &app_data.event_id);
+    info!("Send stateful event with value {}", app_data.value);
+    app_data.value = if app_data.value >= 100.0 {
+        0.0
+    } else {
+        app_data.value + 10.0
+    };
+    glib::ControlFlow::Continue
+}
+
+fn declaration_complete(declaration: Declaration, handler: Arc<Handler>, start_value: f64) {
+    let mut app_data = AppData {
+        handler,
+        event_id: declaration,
+        value: start_value,
+    };
+    glib::timeout_add_seconds(10, move || send_event(&mut app_data));
+}
+
+/// Declare the event and, once the declaration is complete, send it every 10 seconds.
+pub fn setup_declaration(handler: Handler, start_value: f64) -> anyhow::Result<Declaration> {
+    let handler = Arc::new(handler);
+    let key_value_set = declaration_key_value_set(start_value)?;
+    let declaration = handler.declare(
+        &key_value_set,
+        false,
+        Some({
+            let mut handler = Some(Arc::clone(&handler));
+            move |declaration| {
+                if let Some(handler) = handler.take() {
+                    declaration_complete(declaration, handler, start_value);
+                }
+            }
+        }),
+    )?;
+    Ok(declaration)
+}
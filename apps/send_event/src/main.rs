@@ -5,66 +5,8 @@
 //! that they wish to port to Rust; this is probably not the most idiomatic way to send an event
 //! in a greenfield Rust project.
 
-use std::sync::Arc;
-
-use axevent::flex::{Declaration, Event, Handler, KeyValueSet};
-use log::info;
-
-struct AppData {
-    handler: Arc<Handler>,
-    event_id: Declaration,
-    value: f64,
-}
-
-fn send_event(app_data: &mut AppData) -> glib::ControlFlow {
-    let mut key_value_set = KeyValueSet::new();
-    let _ = key_value_set.add_key_value(c"Value", None, Some(app_data.value));
-    let event = Event::new2(key_value_set, None);
-    let _ = app_data.handler.send_event(event, &app_data.event_id);
-    info!("Send stateful event with value {}", app_data.value);
-    app_data.value = if app_data.value >= 100.0 {
-        0.0
-    } else {
-        app_data.value + 10.0
-    };
-    glib::ControlFlow::Continue
-}
-
-fn declaration_complete(declaration: Declaration, handler: Arc<Handler>, start_value: f64) {
-    let mut app_data = AppData {
-        handler,
-        event_id: declaration,
-        value: start_value,
-    };
-    glib::timeout_add_seconds(10, move || send_event(&mut app_data));
-}
-
-fn setup_declaration(handler: Handler, start_value: f64) -> anyhow::Result<Declaration> {
-    let handler = Arc::new(handler);
-    let mut key_value_set = KeyValueSet::new();
-    key_value_set
-        .add_key_value(c"topic0", Some(c"tns1"), Some(c"Monitoring"))?
-        .add_key_value(c"topic1", Some(c"tns1"), Some(c"ProcessorUsage"))?
-        .add_key_value(c"Token", None, Some(0))?
-        .add_key_value(c"Value", None, Some(start_value))?
-        .mark_as_source(c"Token", None)?
-        .mark_as_user_defined(c"Token", None, c"wstype:tt:ReferenceToken")?
-        .mark_as_data(c"Value", None)?
-        .mark_as_user_defined(c"Value", None, c"wstype:xs:float")?;
-    let declaration = handler.declare(
-        &key_value_set,
-        false,
-        Some({
-            let mut handler = Some(Arc::clone(&handler));
-            move |declaration| {
-                if let Some(handler) = handler.take() {
-                    declaration_complete(declaration, handler, start_value);
-                }
-            }
-        }),
-    )?;
-    Ok(declaration)
-}
+use axevent::flex::Handler;
+use send_event::setup_declaration;
 
 fn main() {
     acap_logging::init_logger();
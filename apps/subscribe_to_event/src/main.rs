@@ -5,24 +5,41 @@
 use axevent::flex::{Handler, KeyValueSet, Subscription};
 use log::{error, info};
 
-fn onviftrigger_subscription(handler: &Handler, token: u32) -> anyhow::Result<Subscription> {
+fn topic() -> anyhow::Result<KeyValueSet> {
     let mut key_value_set = KeyValueSet::new();
 
     // Set keys and namespaces for the event to be subscribed
     key_value_set
         .add_key_value(c"topic0", Some(c"tns1"), Some(c"Monitoring"))?
         .add_key_value(c"topic1", Some(c"tns1"), Some(c"ProcessorUsage"))?;
+    Ok(key_value_set)
+}
 
-    let _subscription = handler.subscribe(key_value_set, |_subscription, event| {
-        match event.key_value_set().get_double(c"Value", None) {
-            Ok(value) => {
-                info!("Received event with value: {value:?}");
-            }
-            Err(e) => {
-                error!("Error {}", e);
+/// Subscribe to the events of the `send_event` example.
+///
+/// Every value received is logged and passed to `on_value`.
+fn onviftrigger_subscription<F>(
+    handler: &Handler,
+    token: u32,
+    mut on_value: F,
+) -> anyhow::Result<Subscription>
+where
+    F: FnMut(Option<f64>) + Send + 'static,
+{
+    let key_value_set = topic()?;
+
+    let _subscription =
+        handler.subscribe(key_value_set, move |_subscription, event| {
+            match event.key_value_set().get_double(c"Value", None) {
+                Ok(value) => {
+                    info!("Received event with value: {value:?}");
+                    on_value(value);
+                }
+                Err(e) => {
+                    error!("Error {}", e);
+                }
             }
-        }
-    })?;
+        })?;
 
     info!("And here is the token: {}", token);
 
@@ -34,8 +51,41 @@ fn main() {
 
     let handler = Handler::new();
     info!("Started logging from subscribe event application");
-    onviftrigger_subscription(&handler, 1234).unwrap();
+    onviftrigger_subscription(&handler, 1234, |_| {}).unwrap();
 
     let main_loop = glib::MainLoop::new(None, false);
     main_loop.run();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use axevent::ergo::{Declaration, MainLoop};
+
+    use super::*;
+
+    #[test]
+    fn receives_events_from_send_event() -> anyhow::Result<()> {
+        let main_loop = MainLoop::new();
+        let handler = Handler::new();
+
+        let (tx, rx) = mpsc::channel();
+        let subscription = onviftrigger_subscription(&handler, 1234, move |value| {
+            let _ = tx.send(value);
+        })?;
+        let declaration =
+            Declaration::try_new(send_event::declaration_key_value_set(0.0)?, false, &handler)?;
+        declaration.rx.recv_timeout(Duration::from_secs(5))?;
+        declaration.send_event(send_event::value_event(10.0))?;
+
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5))?, Some(10.0));
+
+        handler.unsubscribe(&subscription)?;
+        drop(declaration);
+        if let Err(e) = main_loop.quit_and_join() {
+            anyhow::bail!("Main loop exited with an error: {e:?}");
+        }
+        Ok(())
+    }
+}
@@ -6,13 +6,17 @@
 //! It tests:
 //! - Stream creation with different formats (YUV, JPEG, H.264)
 //! - Frame capture and metadata access
+//! - Parsing of the H.264 and H.265 parameter sets
 //! - Proper resource cleanup
 
 // These format tests run on the device as an ACAP application rather than as
 // unit tests because they require access to actual camera hardware via the VDO API.
 
 use log::{error, info};
-use vdo::{Error, Resolution, StreamBuilder, VdoFormat};
+use vdo::{
+    annexb::{Codec, ParameterSets},
+    Error, Resolution, StreamBuilder, VdoFormat,
+};
 
 fn capture_format(name: &str, format: VdoFormat, num_frames: usize) -> Result<(), Error> {
     info!("=== Testing {} format ===", name);
@@ -38,6 +42,8 @@ fn capture_format(name: &str, format: VdoFormat, num_frames: usize) -> Result<()
     let running = stream.start()?;
     info!("{}: Stream started", name);
 
+    let mut parameter_sets = Codec::from_format(format).map(ParameterSets::new);
+
     for i in 0..num_frames {
         let buffer = running.next_buffer()?;
         let size = buffer.size();
@@ -49,6 +55,23 @@ fn capture_format(name: &str, format: VdoFormat, num_frames: usize) -> Result<()
             name, i, size, seq, ts
         );
 
+        // For H.264 and H.265, report the codec parameters whenever they change
+        if let Some(parameter_sets) = parameter_sets.as_mut() {
+            if parameter_sets.update(buffer.data()?) {
+                match parameter_sets.parameters() {
+                    Ok(p) => info!(
+                        "{}: Frame {} has codec {} with resolution {}x{}",
+                        name,
+                        i,
+                        p.codec_string(),
+                        p.width,
+                        p.height
+                    ),
+                    Err(e) => error!("{}: Frame {} has an invalid SPS: {}", name, i, e),
+                }
+            }
+        }
+
         // For JPEG, verify magic bytes
         if format == VdoFormat::VDO_FORMAT_JPEG {
             let data = buffer.as_slice()?;
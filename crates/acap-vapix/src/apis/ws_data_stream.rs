@@ -11,6 +11,7 @@ use std::{collections::HashMap, fmt::Display};
 use anyhow::{bail, Context};
 use futures_util::{sink::SinkExt, TryStreamExt};
 use log::{trace, warn};
+pub use onvif_topic::{ParseTopicError, Topic, TopicPath};
 use reqwest_websocket::{Message, WebSocket};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -107,6 +108,12 @@ impl From<TopicFilter> for EventFilter {
     }
 }
 
+impl From<Topic> for EventFilter {
+    fn from(value: Topic) -> Self {
+        TopicFilter::from(value).into()
+    }
+}
+
 impl From<(ContentFilter, TopicFilter)> for EventFilter {
     fn from((content_filter, topic_filter): (ContentFilter, TopicFilter)) -> Self {
         Self {
@@ -137,6 +144,13 @@ pub struct Notification {
     pub message: NotificationMessage,
 }
 
+impl Notification {
+    /// Parses the topic of the notification.
+    pub fn topic_path(&self) -> Result<TopicPath, ParseTopicError> {
+        self.topic.parse()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct NotificationParams {
     notification: Notification,
@@ -188,6 +202,12 @@ impl TopicFilter {
     }
 }
 
+impl From<Topic> for TopicFilter {
+    fn from(value: Topic) -> Self {
+        Self(value.to_string())
+    }
+}
+
 /// Please see the VAPIX Library documentation for [client configuration request](https://www.axis.com/vapix-library/subjects/t10175981/section/t10195123/display?section=t10195123-t10195126).
 pub fn events_configure() -> EventsConfigureRequest {
     EventsConfigureRequest {
@@ -204,4 +224,24 @@ mod tests {
         let s = r#"{"apiVersion":"1.0","method":"events:notify","params":{"notification":{"topic":"tns1:Device/tnsaxis:IO/VirtualInput","timestamp":1722108150418,"message":{"source":{"port":"38"},"key":{},"data":{"active":"0"}}}}}"#;
         let _envelope: RequestEnvelope<NotificationParams> = serde_json::from_str(s).unwrap();
     }
+
+    #[test]
+    fn notification_topic_can_be_matched_by_filter() {
+        let s = r#"{"apiVersion":"1.0","method":"events:notify","params":{"notification":{"topic":"tns1:Device/tnsaxis:IO/VirtualInput","timestamp":1722108150418,"message":{"source":{"port":"38"},"key":{},"data":{"active":"0"}}}}}"#;
+        let envelope: RequestEnvelope<NotificationParams> = serde_json::from_str(s).unwrap();
+        let topic = envelope.params.notification.topic_path().unwrap();
+        let filter: Topic = "tns1:Device/tnsaxis:IO/*".parse().unwrap();
+        assert!(filter.matches(&topic));
+    }
+
+    #[test]
+    fn topic_filter_is_serialized_as_expression() {
+        let topic: Topic = "tns1:Device/tnsaxis:IO/VirtualInput|tns1:Device/Trigger//."
+            .parse()
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(EventFilter::from(topic)).unwrap(),
+            json!({"topicFilter": "tns1:Device/tnsaxis:IO/VirtualInput|tns1:Device/Trigger//."})
+        );
+    }
 }
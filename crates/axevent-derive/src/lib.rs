@@ -0,0 +1,440 @@
+//! Derive macro for declaring and sending `axevent` events described by Rust structs.
+//!
+//! This crate is not meant to be used directly; enable the `derive` feature of `axevent` and use
+//! [`axevent::template::AxEvent`](../axevent/template/trait.AxEvent.html) instead.
+use std::ffi::CString;
+
+use onvif_topic::{Level, Name, Topic};
+use proc_macro2::{Literal, Span, TokenStream};
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Field, Fields, Ident, LitStr,
+    Result,
+};
+
+/// Implements `axevent::template::AxEvent` for a struct with named fields.
+///
+/// The struct must be annotated with `#[ax_event(topic = "...", stateful | stateless)]` where the
+/// topic is written like `tns1:Monitoring/ProcessorUsage`. Levels without a namespace prefix
+/// inherit the namespace of the level before them. The nice name of the last level can be set
+/// with `nice_name = "..."` and defaults to the name of the struct, e.g. `Processor usage`.
+///
+/// Every field must be annotated with `#[ax_event(source)]` or `#[ax_event(data)]` and may
+/// additionally use:
+/// - `key = "..."` to use another key than the name of the field.
+/// - `namespace = "..."` to put the key in a namespace.
+/// - `nice_name = "..."` to use another nice name than the one derived from the name of the key.
+/// - `user_defined = "..."` to mark the key with a user defined tag, e.g. `wstype:xs:float`.
+#[proc_macro_derive(AxEvent, attributes(ax_event))]
+pub fn derive_ax_event(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream> {
+    let event = EventAttributes::parse(&input)?;
+    let Data::Struct(data) = &input.data else {
+        return Err(Error::new(
+            input.ident.span(),
+            "AxEvent can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(Error::new(
+            input.ident.span(),
+            "AxEvent can only be derived for structs with named fields",
+        ));
+    };
+    let fields = fields
+        .named
+        .iter()
+        .map(FieldAttributes::parse)
+        .collect::<Result<Vec<_>>>()?;
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let stateless = event.stateless;
+
+    let topic = event.topic.iter().enumerate().map(|(i, level)| {
+        let key = c_str(&format!("topic{i}"));
+        let namespace = c_str(level.namespace());
+        let value = c_str(level.name());
+        quote! {
+            key_value_set.add_key_value(#key, Some(#namespace), Some(#value))?;
+        }
+    });
+    let topic_nice_name = {
+        let key = c_str(&format!("topic{}", event.topic.len() - 1));
+        let namespace = c_str(event.topic[event.topic.len() - 1].namespace());
+        let nice_name = c_str(&event.nice_name);
+        quote! {
+            key_value_set.add_nice_names(#key, Some(#namespace), None, Some(#nice_name))?;
+        }
+    };
+
+    let declared = fields.iter().map(|field| {
+        let ident = &field.ident;
+        let key = c_str(&field.key);
+        let namespace = option_c_str(field.namespace.as_deref());
+        let nice_name = c_str(&field.nice_name);
+        let mark = match field.kind {
+            Kind::Source => quote! { key_value_set.mark_as_source(#key, #namespace)?; },
+            Kind::Data => quote! { key_value_set.mark_as_data(#key, #namespace)?; },
+        };
+        let user_defined = field.user_defined.as_deref().map(|tag| {
+            let tag = c_str(tag);
+            quote! { key_value_set.mark_as_user_defined(#key, #namespace, #tag)?; }
+        });
+        quote! {
+            ::axevent::template::FieldValue::add_to(
+                &self.#ident,
+                &mut key_value_set,
+                #key,
+                #namespace,
+            )?;
+            #mark
+            #user_defined
+            key_value_set.add_nice_names(#key, #namespace, Some(#nice_name), None)?;
+        }
+    });
+
+    let sent = fields
+        .iter()
+        .filter(|field| field.kind == Kind::Data)
+        .map(|field| {
+            let ident = &field.ident;
+            let key = c_str(&field.key);
+            let namespace = option_c_str(field.namespace.as_deref());
+            quote! {
+                ::axevent::template::FieldValue::add_to(
+                    &self.#ident,
+                    &mut key_value_set,
+                    #key,
+                    #namespace,
+                )?;
+            }
+        });
+
+    Ok(quote! {
+        impl #impl_generics ::axevent::template::AxEvent for #name #type_generics #where_clause {
+            const STATELESS: bool = #stateless;
+
+            fn topic() -> ::core::result::Result<
+                ::axevent::flex::KeyValueSet,
+                ::axevent::flex::Error,
+            > {
+                let mut key_value_set = ::axevent::flex::KeyValueSet::new();
+                #(#topic)*
+                Ok(key_value_set)
+            }
+
+            fn declaration(&self) -> ::core::result::Result<
+                ::axevent::flex::KeyValueSet,
+                ::axevent::flex::Error,
+            > {
+                let mut key_value_set = <Self as ::axevent::template::AxEvent>::topic()?;
+                #topic_nice_name
+                #(#declared)*
+                Ok(key_value_set)
+            }
+
+            fn key_value_set(&self) -> ::core::result::Result<
+                ::axevent::flex::KeyValueSet,
+                ::axevent::flex::Error,
+            > {
+                let mut key_value_set = ::axevent::flex::KeyValueSet::new();
+                #(#sent)*
+                Ok(key_value_set)
+            }
+        }
+    })
+}
+
+/// Parses a topic that names a single topic, i.e. without wildcards and alternatives.
+fn parse_topic(topic: &str) -> std::result::Result<Vec<Name>, String> {
+    let topic: Topic = topic
+        .parse()
+        .map_err(|e: onvif_topic::ParseTopicError| e.to_string())?;
+    let path = topic
+        .as_concrete()
+        .ok_or_else(|| format!("{topic} must not contain wildcards or alternatives"))?;
+    Ok(path
+        .levels()
+        .iter()
+        .filter_map(|level| match level {
+            Level::Named(name) => Some(name.clone()),
+            Level::Any => None,
+        })
+        .collect())
+}
+
+/// Converts an identifier such as `ProcessorUsage` or `processor_usage` to `Processor usage`.
+fn to_nice_name(ident: &str) -> String {
+    let mut words: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+    for c in ident.trim_start_matches("r#").chars() {
+        if c == '_' {
+            previous = None;
+            continue;
+        }
+        let starts_word = match previous {
+            None => true,
+            Some(p) => c.is_uppercase() && !p.is_uppercase(),
+        };
+        if starts_word {
+            words.push(String::new());
+        }
+        words.last_mut().unwrap().push(c);
+        previous = Some(c);
+    }
+    let mut words = words.into_iter().enumerate().map(|(i, word)| {
+        let acronym = word.chars().count() > 1 && word.chars().all(|c| !c.is_lowercase());
+        if acronym {
+            word
+        } else if i == 0 {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap();
+            first
+                .to_uppercase()
+                .chain(chars.flat_map(char::to_lowercase))
+                .collect()
+        } else {
+            word.to_lowercase()
+        }
+    });
+    let mut nice_name = words.next().unwrap_or_default();
+    for word in words {
+        nice_name.push(' ');
+        nice_name.push_str(&word);
+    }
+    nice_name
+}
+
+fn c_str(s: &str) -> Literal {
+    // Interior nul bytes are rejected when the attributes are parsed.
+    let s = CString::new(s).expect("string should not contain nul bytes");
+    let mut literal = Literal::c_string(&s);
+    literal.set_span(Span::call_site());
+    literal
+}
+
+fn option_c_str(s: Option<&str>) -> TokenStream {
+    match s {
+        None => quote! { None },
+        Some(s) => {
+            let s = c_str(s);
+            quote! { Some(#s) }
+        }
+    }
+}
+
+/// Returns the value of `lit` after verifying that it can be passed to C.
+fn c_compatible(lit: LitStr) -> Result<String> {
+    let value = lit.value();
+    if value.contains('\0') {
+        return Err(Error::new(lit.span(), "nul bytes are not allowed"));
+    }
+    Ok(value)
+}
+
+struct EventAttributes {
+    topic: Vec<Name>,
+    stateless: bool,
+    nice_name: String,
+}
+
+impl EventAttributes {
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut topic = None;
+        let mut stateless = None;
+        let mut nice_name = None;
+        for attr in input.attrs.iter().filter(|a| a.path().is_ident("ax_event")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("topic") {
+                    let lit: LitStr = meta.value()?.parse()?;
+                    let levels = parse_topic(&c_compatible(lit.clone())?)
+                        .map_err(|e| Error::new(lit.span(), e))?;
+                    topic = Some(levels);
+                } else if meta.path.is_ident("stateful") || meta.path.is_ident("stateless") {
+                    if stateless.is_some() {
+                        return Err(meta.error("expected only one of `stateful` and `stateless`"));
+                    }
+                    stateless = Some(meta.path.is_ident("stateless"));
+                } else if meta.path.is_ident("nice_name") {
+                    nice_name = Some(c_compatible(meta.value()?.parse()?)?);
+                } else {
+                    return Err(meta.error("unsupported attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        let span = input.ident.span();
+        Ok(Self {
+            topic: topic.ok_or_else(|| {
+                Error::new(span, "expected `#[ax_event(topic = \"...\")]` on the struct")
+            })?,
+            stateless: stateless.ok_or_else(|| {
+                Error::new(
+                    span,
+                    "expected either `#[ax_event(stateful)]` or `#[ax_event(stateless)]` on the struct",
+                )
+            })?,
+            nice_name: nice_name.unwrap_or_else(|| to_nice_name(&input.ident.to_string())),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    Source,
+    Data,
+}
+
+struct FieldAttributes {
+    ident: Ident,
+    kind: Kind,
+    key: String,
+    namespace: Option<String>,
+    nice_name: String,
+    user_defined: Option<String>,
+}
+
+impl FieldAttributes {
+    fn parse(field: &Field) -> Result<Self> {
+        let ident = field
+            .ident
+            .clone()
+            .ok_or_else(|| Error::new(field.span(), "expected a named field"))?;
+        let mut kind = None;
+        let mut key = None;
+        let mut namespace = None;
+        let mut nice_name = None;
+        let mut user_defined = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("ax_event")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("source") || meta.path.is_ident("data") {
+                    if kind.is_some() {
+                        return Err(meta.error("expected only one of `source` and `data`"));
+                    }
+                    kind = Some(if meta.path.is_ident("source") {
+                        Kind::Source
+                    } else {
+                        Kind::Data
+                    });
+                } else if meta.path.is_ident("key") {
+                    key = Some(c_compatible(meta.value()?.parse()?)?);
+                } else if meta.path.is_ident("namespace") {
+                    namespace = Some(c_compatible(meta.value()?.parse()?)?);
+                } else if meta.path.is_ident("nice_name") {
+                    nice_name = Some(c_compatible(meta.value()?.parse()?)?);
+                } else if meta.path.is_ident("user_defined") {
+                    user_defined = Some(c_compatible(meta.value()?.parse()?)?);
+                } else {
+                    return Err(meta.error("unsupported attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        let kind = kind.ok_or_else(|| {
+            Error::new(
+                ident.span(),
+                "expected either `#[ax_event(source)]` or `#[ax_event(data)]` on the field",
+            )
+        })?;
+        let key = key.unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_string());
+        Ok(Self {
+            kind,
+            nice_name: nice_name.unwrap_or_else(|| to_nice_name(&key)),
+            key,
+            namespace,
+            user_defined,
+            ident,
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn topics_must_be_concrete() {
+        assert_eq!(
+            parse_topic("tns1:Device/tnsaxis:IO/VirtualInput").unwrap(),
+            vec![
+                Name::new("tns1", "Device").unwrap(),
+                Name::new("tnsaxis", "IO").unwrap(),
+                Name::new("tnsaxis", "VirtualInput").unwrap(),
+            ]
+        );
+        for topic in [
+            "Monitoring/ProcessorUsage",
+            "tns1:Monitoring/*",
+            "tns1:Monitoring//.",
+            "tns1:Monitoring/ProcessorUsage|tns1:Device",
+        ] {
+            assert!(parse_topic(topic).is_err(), "{topic:?}");
+        }
+    }
+
+    #[test]
+    fn nice_names_are_derived_from_identifiers() {
+        assert_eq!(to_nice_name("ProcessorUsage"), "Processor usage");
+        assert_eq!(to_nice_name("processor_usage"), "Processor usage");
+        assert_eq!(to_nice_name("Value"), "Value");
+        assert_eq!(to_nice_name("token"), "Token");
+        assert_eq!(to_nice_name("VirtualIO"), "Virtual IO");
+        assert_eq!(to_nice_name("r#type"), "Type");
+    }
+
+    #[test]
+    fn derive_requires_a_kind_for_every_field() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[ax_event(topic = "tnsaxis:Example", stateless)]
+            struct Example {
+                #[ax_event(data)]
+                value: i32,
+                other: i32,
+            }
+        };
+        let error = expand(input).unwrap_err();
+        assert!(
+            error.to_string().contains("`#[ax_event(source)]`"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn derive_marks_keys() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[ax_event(topic = "tns1:Monitoring/ProcessorUsage", stateful)]
+            struct ProcessorUsage {
+                #[ax_event(source, key = "Token", user_defined = "wstype:tt:ReferenceToken")]
+                token: i32,
+                #[ax_event(data, key = "Value")]
+                value: f64,
+            }
+        };
+        let output = expand(input).unwrap().to_string();
+        assert!(
+            output.contains("const STATELESS : bool = false"),
+            "{output}"
+        );
+        assert!(
+            output.contains(r#"mark_as_source (c"Token" , None)"#),
+            "{output}"
+        );
+        assert!(
+            output.contains(r#"mark_as_data (c"Value" , None)"#),
+            "{output}"
+        );
+        assert!(
+            output.contains(
+                r#"add_nice_names (c"topic1" , Some (c"tns1") , None , Some (c"Processor usage"))"#
+            ),
+            "{output}"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! An in-memory implementation of the event system for testing on hosts without a device.
+//!
+//! The `fake` feature exports the `ax_event_*` symbols that [`flex`](crate::flex) calls, so
+//! [`ergo`](crate::ergo), [`nonblock`](crate::nonblock) and [`replay`](crate::replay) work
+//! without linking `libaxevent`. This lets apps that declare, send or subscribe to events be
+//! tested with `cargo test` on a development machine. All handlers in the process share one
+//! event bus:
+//!
+//! - Events are delivered to the subscriptions of every handler, not only the one that declared
+//!   them, with the keys of the declaration, such as the topic and source keys, merged with the
+//!   keys of the event. Stateful declarations remember the values of the last event sent.
+//! - A subscription receives an event if every key of the subscription is in the event and, if
+//!   the subscription gives it a value, has the same value. Topic levels that are left out, like
+//!   wildcard levels added by [`KeyValueSet::add_topic`](crate::flex::KeyValueSet::add_topic),
+//!   and keys without a value therefore match anything.
+//! - Declaration complete and subscription callbacks are called from the default main context,
+//!   so something must run it, e.g. [`MainLoop`](crate::ergo::MainLoop).
+//!
+//! Markings, user defined tags and nice names are validated but otherwise ignored, and element
+//! values are not supported.
+//!
+//! Apps on a device need the real event system to reach other apps and the event service, so
+//! building with the feature for anything but a host is a compile error.
+//!
+//! # Example
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use axevent::{
+//!     ergo::{Declaration, MainLoop, Subscription},
+//!     flex::{Event, Handler, KeyValueSet},
+//! };
+//!
+//! let main_loop = MainLoop::new();
+//! let handler = Handler::new();
+//!
+//! let mut topic = KeyValueSet::new();
+//! topic.add_key_value(c"topic0", Some(c"tns1"), Some(c"Monitoring"))?;
+//! let subscription = Subscription::try_new(topic, &handler)?;
+//!
+//! let mut declaration = KeyValueSet::new();
+//! declaration
+//!     .add_key_value(c"topic0", Some(c"tns1"), Some(c"Monitoring"))?
+//!     .add_key_value(c"topic1", Some(c"tns1"), Some(c"ProcessorUsage"))?
+//!     .add_key_value(c"Token", None, Some(0))?
+//!     .add_key_value(c"Value", None, Some(0.0))?
+//!     .mark_as_source(c"Token", None)?
+//!     .mark_as_data(c"Value", None)?;
+//! let declaration = Declaration::try_new(declaration, false, &handler)?;
+//! declaration.rx.recv_timeout(Duration::from_secs(1))?;
+//!
+//! let mut data = KeyValueSet::new();
+//! data.add_key_value(c"Value", None, Some(42.0))?;
+//! declaration.send_event(Event::new2(data, None))?;
+//!
+//! let event = subscription.rx.recv_timeout(Duration::from_secs(1))?;
+//! assert_eq!(event.key_value_set().get_integer(c"Token", None)?, Some(0));
+//! assert_eq!(event.key_value_set().get_double(c"Value", None)?, Some(42.0));
+//!
+//! drop(subscription);
+//! drop(declaration);
+//! main_loop.quit_and_join().unwrap();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::ffi::CString;
+
+use axevent_sys::AXEventErrorCode;
+use glib_sys::{gboolean, GError, GQuark, GFALSE, GTRUE};
+
+mod handler;
+mod key_value_set;
+
+/// The code and message of an error to report.
+type Failure = (AXEventErrorCode, String);
+
+#[no_mangle]
+extern "C" fn ax_event_error_quark() -> GQuark {
+    unsafe { glib_sys::g_quark_from_static_string(c"ax-event-error-quark".as_ptr()) }
+}
+
+/// Reports the outcome of `f` like `libaxevent` does.
+///
+/// # Safety
+///
+/// `error` must be null or point to a null `GError` pointer.
+unsafe fn report(error: *mut *mut GError, f: impl FnOnce() -> Result<(), Failure>) -> gboolean {
+    match f() {
+        Ok(()) => GTRUE,
+        Err((code, message)) => {
+            if !error.is_null() {
+                let message = CString::new(message).unwrap_or_default();
+                *error = glib_sys::g_error_new_literal(
+                    ax_event_error_quark(),
+                    code as i32,
+                    message.as_ptr(),
+                );
+            }
+            GFALSE
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use crate::{
+        ergo::MainLoop,
+        flex::{Event, Handler, KeyValueSet, ValueType},
+        topic::{subscription_key_value_sets, Topic, TopicPath},
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    // Every test uses its own topics since the bus is shared by all tests in the process.
+    fn declaration(topic: &str, port: i32) -> KeyValueSet {
+        let topic: TopicPath = topic.parse().unwrap();
+        let mut kvs = KeyValueSet::new();
+        kvs.add_topic(&topic)
+            .unwrap()
+            .add_key_value(c"port", None, Some(port))
+            .unwrap()
+            .add_key_value(c"active", None, Some(false))
+            .unwrap()
+            .mark_as_source(c"port", None)
+            .unwrap()
+            .mark_as_data(c"active", None)
+            .unwrap();
+        kvs
+    }
+
+    fn declare(handler: &Handler, kvs: &KeyValueSet, stateless: bool) -> crate::flex::Declaration {
+        let (tx, rx) = mpsc::channel();
+        let declaration = handler
+            .declare(
+                kvs,
+                stateless,
+                Some(move |_| {
+                    let _ = tx.send(());
+                }),
+            )
+            .unwrap();
+        rx.recv_timeout(TIMEOUT).unwrap();
+        declaration
+    }
+
+    fn subscribe(handler: &Handler, kvs: KeyValueSet) -> mpsc::Receiver<Event> {
+        let (tx, rx) = mpsc::channel();
+        handler
+            .subscribe(kvs, move |_, event| {
+                let _ = tx.send(event);
+            })
+            .unwrap();
+        rx
+    }
+
+    fn active(active: bool) -> Event {
+        let mut kvs = KeyValueSet::new();
+        kvs.add_key_value(c"active", None, Some(active)).unwrap();
+        Event::new2(kvs, None)
+    }
+
+    fn port(event: &Event) -> i32 {
+        event
+            .key_value_set()
+            .get_integer(c"port", None)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn key_value_sets_check_keys_and_types() {
+        let mut kvs = KeyValueSet::new();
+        kvs.add_key_value(c"name", Some(c"tnsaxis"), Some(c"value"))
+            .unwrap()
+            .add_key_value::<i32>(c"count", None, None)
+            .unwrap();
+        assert!(kvs.get_string(c"name", None).is_err());
+        assert!(kvs.get_integer(c"name", Some(c"tnsaxis")).is_err());
+        assert!(kvs.mark_as_data(c"missing", None).is_err());
+        assert_eq!(kvs.get_value_type(c"count", None).unwrap(), ValueType::Int);
+        assert_eq!(kvs.get_integer(c"count", None).unwrap(), None);
+
+        kvs.add_key_value(c"count", None, Some(3)).unwrap();
+        assert_eq!(kvs.get_integer(c"count", None).unwrap(), Some(3));
+        kvs.remove_key(c"count", None).unwrap();
+        assert!(kvs.get_value_type(c"count", None).is_err());
+        assert!(kvs.remove_key(c"count", None).is_err());
+    }
+
+    #[test]
+    fn subscriptions_match_present_keys_and_values() {
+        let main_loop = MainLoop::new();
+        let handler = Handler::new();
+
+        let topic: Topic = "tnsaxis:FakeMatching/*/Port".parse().unwrap();
+        let [wildcard] = subscription_key_value_sets(&topic)
+            .unwrap()
+            .try_into()
+            .ok()
+            .unwrap();
+        let wildcard = subscribe(&handler, wildcard);
+        let mut filter = KeyValueSet::new();
+        filter
+            .add_key_value(c"topic1", Some(c"tnsaxis"), Some(c"Match"))
+            .unwrap()
+            .add_key_value(c"port", None, Some(2))
+            .unwrap();
+        let filtered = subscribe(&handler, filter);
+
+        let declarations = [
+            ("tnsaxis:FakeMatching/Match/Port", 1),
+            ("tnsaxis:FakeMatching/Match/Port", 2),
+            ("tnsaxis:FakeMatching/Other/Port", 3),
+            ("tnsaxis:FakeMatching/Match/Relay", 2),
+        ]
+        .map(|(topic, port)| declare(&handler, &declaration(topic, port), true));
+        for declaration in &declarations {
+            handler.send_event(active(true), declaration).unwrap();
+        }
+        handler.send_event(active(false), &declarations[1]).unwrap();
+
+        // Events are delivered in order, so all events have been delivered once this has
+        // received the last one.
+        let filtered: Vec<_> = (0..3)
+            .map(|_| filtered.recv_timeout(TIMEOUT).unwrap())
+            .collect();
+        assert!(filtered.iter().all(|e| port(e) == 2));
+        let topics: Vec<_> = filtered
+            .iter()
+            .map(|e| e.key_value_set().topic().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            topics,
+            vec![
+                "tnsaxis:FakeMatching/Match/Port",
+                "tnsaxis:FakeMatching/Match/Relay",
+                "tnsaxis:FakeMatching/Match/Port",
+            ]
+        );
+        assert_eq!(
+            filtered[2]
+                .key_value_set()
+                .get_boolean(c"active", None)
+                .unwrap(),
+            Some(false)
+        );
+        let ports: Vec<_> = wildcard.try_iter().map(|e| port(&e)).collect();
+        assert_eq!(ports, vec![1, 2, 3, 2]);
+
+        drop(handler);
+        main_loop.quit_and_join().unwrap();
+    }
+
+    #[test]
+    fn stateful_events_keep_the_last_values() {
+        let main_loop = MainLoop::new();
+        let handler = Handler::new();
+
+        let mut topic = KeyValueSet::new();
+        topic
+            .add_key_value(c"topic0", Some(c"tnsaxis"), Some(c"FakeStateful"))
+            .unwrap();
+        let events = subscribe(&handler, topic);
+        let mut kvs = declaration("tnsaxis:FakeStateful/Port", 4);
+        kvs.add_key_value(c"level", None, Some(0.0)).unwrap();
+        let declaration = declare(&handler, &kvs, false);
+
+        handler.send_event(active(true), &declaration).unwrap();
+        let mut level = KeyValueSet::new();
+        level.add_key_value(c"level", None, Some(0.5)).unwrap();
+        handler
+            .send_event(Event::new2(level, None), &declaration)
+            .unwrap();
+
+        let first = events.recv_timeout(TIMEOUT).unwrap();
+        let first = first.key_value_set();
+        assert_eq!(first.get_boolean(c"active", None).unwrap(), Some(true));
+        assert_eq!(first.get_double(c"level", None).unwrap(), Some(0.0));
+        let second = events.recv_timeout(TIMEOUT).unwrap();
+        let second = second.key_value_set();
+        assert_eq!(second.get_boolean(c"active", None).unwrap(), Some(true));
+        assert_eq!(second.get_double(c"level", None).unwrap(), Some(0.5));
+
+        drop(handler);
+        main_loop.quit_and_join().unwrap();
+    }
+
+    #[test]
+    fn unregistered_ids_are_rejected() {
+        let handler = Handler::new();
+        let other = Handler::new();
+        let declaration = handler
+            .declare::<fn(crate::flex::Declaration)>(
+                &declaration("tnsaxis:FakeUndeclared", 5),
+                true,
+                None,
+            )
+            .unwrap();
+        assert!(other.undeclare(&declaration).is_err());
+        handler.undeclare(&declaration).unwrap();
+        let err = handler
+            .send_event(active(true), &declaration)
+            .expect_err("event should not be sent after undeclaring");
+        assert!(err.to_string().contains("does not exist"), "{err}");
+        assert!(handler.undeclare(&declaration).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+//! Handlers, and the event bus that they share.
+
+use std::{
+    collections::BTreeMap,
+    ffi::c_uint,
+    sync::{Condvar, Mutex, MutexGuard},
+    thread,
+    thread::ThreadId,
+};
+
+use axevent_sys::{
+    AXDeclarationCompleteCallback, AXEvent, AXEventErrorCode_AX_EVENT_ERROR_INVALID_ARGUMENT,
+    AXEventErrorCode_AX_EVENT_ERROR_SEND, AXEventErrorCode_AX_EVENT_ERROR_UNDECLARE,
+    AXEventErrorCode_AX_EVENT_ERROR_UNSUBSCRIBE, AXEventHandler, AXEventKeyValueSet,
+    AXSubscriptionCallback,
+};
+use glib_sys::{gboolean, gpointer, GError, GFALSE};
+
+use super::{
+    key_value_set::{event, key_value_set, KeyValueSet},
+    report, Failure,
+};
+
+/// The state of a handler, of which only the identity matters.
+struct Handler {
+    id: usize,
+}
+
+/// Data that is passed back to the callback it was registered with.
+#[derive(Clone, Copy)]
+struct UserData(gpointer);
+
+// SAFETY: The fake does not access the data, it only passes it to the callback that it belongs
+// to, which is called on the thread that dispatches the default main context like in
+// `libaxevent`.
+unsafe impl Send for UserData {}
+
+struct Declaration {
+    handler: usize,
+    key_value_set: KeyValueSet,
+    stateless: bool,
+}
+
+struct Subscription {
+    handler: usize,
+    key_value_set: KeyValueSet,
+    callback: unsafe extern "C" fn(c_uint, *mut AXEvent, gpointer),
+    user_data: UserData,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Callback {
+    Declaration(c_uint),
+    Subscription(c_uint),
+}
+
+/// A callback that is being called.
+struct Running {
+    thread: ThreadId,
+    handler: usize,
+    callback: Callback,
+}
+
+struct Bus {
+    next_handler: usize,
+    next_id: c_uint,
+    declarations: BTreeMap<c_uint, Declaration>,
+    subscriptions: BTreeMap<c_uint, Subscription>,
+    running: Vec<Running>,
+}
+
+impl Bus {
+    /// Returns the handler that `callback` belongs to, if it is still registered.
+    fn owner(&self, callback: Callback) -> Option<usize> {
+        match callback {
+            Callback::Declaration(id) => self.declarations.get(&id).map(|d| d.handler),
+            Callback::Subscription(id) => self.subscriptions.get(&id).map(|s| s.handler),
+        }
+    }
+}
+
+// All handlers share one bus so that events declared using one are delivered to subscriptions
+// of another, like in the event system.
+static BUS: Mutex<Bus> = Mutex::new(Bus {
+    next_handler: 0,
+    next_id: 0,
+    declarations: BTreeMap::new(),
+    subscriptions: BTreeMap::new(),
+    running: Vec::new(),
+});
+
+// Notified when a callback returns.
+static RETURNED: Condvar = Condvar::new();
+
+fn lock() -> MutexGuard<'static, Bus> {
+    BUS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Blocks until no callback for which `is_affected` returns `true` is being called on another
+/// thread.
+///
+/// This lets the functions that unregister callbacks guarantee, like `libaxevent`, that the
+/// callbacks are not in use once they return. Callbacks being called on the current thread
+/// cannot be waited for, so unregistering from within a callback is allowed.
+fn wait_for_callbacks(mut bus: MutexGuard<Bus>, is_affected: impl Fn(&Running) -> bool) {
+    let thread = thread::current().id();
+    while bus
+        .running
+        .iter()
+        .any(|r| r.thread != thread && is_affected(r))
+    {
+        bus = RETURNED.wait(bus).unwrap_or_else(|e| e.into_inner());
+    }
+}
+
+/// Calls `f` from the default main context unless `callback` is unregistered before then.
+fn dispatch(callback: Callback, f: impl FnOnce() + Send + 'static) {
+    glib::idle_add_once(move || {
+        let thread = thread::current().id();
+        {
+            let mut bus = lock();
+            let Some(handler) = bus.owner(callback) else {
+                return;
+            };
+            bus.running.push(Running {
+                thread,
+                handler,
+                callback,
+            });
+        }
+        f();
+        let mut bus = lock();
+        let i = bus
+            .running
+            .iter()
+            .position(|r| r.thread == thread && r.callback == callback)
+            .expect("running callbacks are only removed by the thread that added them");
+        bus.running.remove(i);
+        RETURNED.notify_all();
+    });
+}
+
+/// Returns the id of the handler behind `event_handler`.
+///
+/// # Safety
+///
+/// `event_handler` must be null or a live handler created by the fake.
+unsafe fn handler(event_handler: *mut AXEventHandler) -> Result<usize, Failure> {
+    (event_handler as *const Handler)
+        .as_ref()
+        .map(|h| h.id)
+        .ok_or_else(|| {
+            (
+                AXEventErrorCode_AX_EVENT_ERROR_INVALID_ARGUMENT,
+                "event_handler must not be NULL".to_string(),
+            )
+        })
+}
+
+#[no_mangle]
+extern "C" fn ax_event_handler_new() -> *mut AXEventHandler {
+    let mut bus = lock();
+    bus.next_handler += 1;
+    Box::into_raw(Box::new(Handler {
+        id: bus.next_handler,
+    }))
+    .cast()
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_handler_free(event_handler: *mut AXEventHandler) {
+    let Ok(id) = handler(event_handler) else {
+        return;
+    };
+    let mut bus = lock();
+    bus.declarations.retain(|_, d| d.handler != id);
+    bus.subscriptions.retain(|_, s| s.handler != id);
+    wait_for_callbacks(bus, |r| r.handler == id);
+    drop(Box::from_raw(event_handler as *mut Handler));
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_handler_declare(
+    event_handler: *mut AXEventHandler,
+    key_value_set: *mut AXEventKeyValueSet,
+    stateless: gboolean,
+    declaration: *mut c_uint,
+    callback: AXDeclarationCompleteCallback,
+    user_data: gpointer,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let handler = handler(event_handler)?;
+        let key_value_set = self::key_value_set(key_value_set)?.clone();
+        let mut bus = lock();
+        bus.next_id += 1;
+        let id = bus.next_id;
+        bus.declarations.insert(
+            id,
+            Declaration {
+                handler,
+                key_value_set,
+                stateless: stateless != GFALSE,
+            },
+        );
+        *declaration = id;
+        if let Some(callback) = callback {
+            let user_data = UserData(user_data);
+            dispatch(Callback::Declaration(id), move || {
+                // Captures all of `user_data` instead of only the pointer, which is not `Send`.
+                let user_data = user_data;
+                callback(id, user_data.0)
+            });
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_handler_undeclare(
+    event_handler: *mut AXEventHandler,
+    declaration: c_uint,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let handler = handler(event_handler)?;
+        let mut bus = lock();
+        if bus.declarations.get(&declaration).map(|d| d.handler) != Some(handler) {
+            return Err((
+                AXEventErrorCode_AX_EVENT_ERROR_UNDECLARE,
+                format!("declaration {declaration} does not exist"),
+            ));
+        }
+        bus.declarations.remove(&declaration);
+        wait_for_callbacks(bus, |r| r.callback == Callback::Declaration(declaration));
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_handler_send_event(
+    event_handler: *mut AXEventHandler,
+    declaration: c_uint,
+    event: *mut AXEvent,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let handler = handler(event_handler)?;
+        let event = self::event(event)?;
+        let mut bus = lock();
+        let Some(declared) = bus
+            .declarations
+            .get_mut(&declaration)
+            .filter(|d| d.handler == handler)
+        else {
+            return Err((
+                AXEventErrorCode_AX_EVENT_ERROR_SEND,
+                format!("declaration {declaration} does not exist"),
+            ));
+        };
+        // Subscribers receive the keys of the declaration, such as the topic and source keys,
+        // with the values of the event taking precedence.
+        let mut key_value_set = declared.key_value_set.clone();
+        key_value_set.merge(event.key_value_set());
+        if !declared.stateless {
+            declared.key_value_set = key_value_set.clone();
+        }
+        for (&id, subscription) in &bus.subscriptions {
+            if !key_value_set.matches(&subscription.key_value_set) {
+                continue;
+            }
+            let callback = subscription.callback;
+            let user_data = subscription.user_data;
+            let event = event.with_key_value_set(key_value_set.clone());
+            dispatch(Callback::Subscription(id), move || {
+                let user_data = user_data;
+                // The subscriber takes over the event.
+                callback(id, event.into_raw(), user_data.0)
+            });
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_handler_subscribe(
+    event_handler: *mut AXEventHandler,
+    key_value_set: *mut AXEventKeyValueSet,
+    subscription: *mut c_uint,
+    callback: AXSubscriptionCallback,
+    user_data: gpointer,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let handler = handler(event_handler)?;
+        let key_value_set = self::key_value_set(key_value_set)?.clone();
+        let callback = callback.ok_or_else(|| {
+            (
+                AXEventErrorCode_AX_EVENT_ERROR_INVALID_ARGUMENT,
+                "callback must not be NULL".to_string(),
+            )
+        })?;
+        let mut bus = lock();
+        bus.next_id += 1;
+        let id = bus.next_id;
+        bus.subscriptions.insert(
+            id,
+            Subscription {
+                handler,
+                key_value_set,
+                callback,
+                user_data: UserData(user_data),
+            },
+        );
+        *subscription = id;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_handler_unsubscribe(
+    event_handler: *mut AXEventHandler,
+    subscription: c_uint,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let handler = handler(event_handler)?;
+        let mut bus = lock();
+        if bus.subscriptions.get(&subscription).map(|s| s.handler) != Some(handler) {
+            return Err((
+                AXEventErrorCode_AX_EVENT_ERROR_UNSUBSCRIBE,
+                format!("subscription {subscription} does not exist"),
+            ));
+        }
+        bus.subscriptions.remove(&subscription);
+        wait_for_callbacks(bus, |r| r.callback == Callback::Subscription(subscription));
+        Ok(())
+    })
+}
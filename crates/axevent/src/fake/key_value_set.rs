@@ -0,0 +1,480 @@
+#![allow(non_upper_case_globals)]
+//! Key-value sets and events, stored as plain Rust values.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+
+use axevent_sys::{
+    AXEvent, AXEventErrorCode_AX_EVENT_ERROR_INCOMPATIBLE_VALUE,
+    AXEventErrorCode_AX_EVENT_ERROR_INVALID_ARGUMENT,
+    AXEventErrorCode_AX_EVENT_ERROR_KEY_NOT_FOUND, AXEventKeyValueSet, AXEventValueType,
+    AXEventValueType_AX_VALUE_TYPE_BOOL, AXEventValueType_AX_VALUE_TYPE_DOUBLE,
+    AXEventValueType_AX_VALUE_TYPE_INT, AXEventValueType_AX_VALUE_TYPE_STRING,
+};
+use glib_sys::{gboolean, gconstpointer, GDateTime, GError};
+
+use super::{report, Failure};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Int(i32),
+    // Booleans are stored as given, like in `libaxevent`.
+    Bool(gboolean),
+    Double(f64),
+    String(CString),
+}
+
+impl Value {
+    /// Returns `true` if a subscription for `self` should receive `other`.
+    fn matches(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => (*a != 0) == (*b != 0),
+            (a, b) => a == b,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    namespace: Option<CString>,
+    key: CString,
+    value_type: AXEventValueType,
+    value: Option<Value>,
+}
+
+/// The keys of a key-value set in the order they were first added.
+///
+/// Markings, user defined tags and nice names describe declarations to other consumers of the
+/// event system, which the fake does not have, so they are validated but not stored.
+#[derive(Clone, Debug, Default)]
+pub(super) struct KeyValueSet {
+    entries: Vec<Entry>,
+}
+
+impl KeyValueSet {
+    fn get(&self, key: &CStr, namespace: Option<&CStr>) -> Result<&Entry, Failure> {
+        self.entries
+            .iter()
+            .find(|e| e.key.as_c_str() == key && e.namespace.as_deref() == namespace)
+            .ok_or_else(|| {
+                (
+                    AXEventErrorCode_AX_EVENT_ERROR_KEY_NOT_FOUND,
+                    format!("key {key:?} in namespace {namespace:?} not found"),
+                )
+            })
+    }
+
+    fn set(&mut self, entry: Entry) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|e| e.key == entry.key && e.namespace == entry.namespace)
+        {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Sets the keys of `other` in `self`, replacing the values of keys in both.
+    pub(super) fn merge(&mut self, other: &KeyValueSet) {
+        for entry in &other.entries {
+            self.set(entry.clone());
+        }
+    }
+
+    /// Returns `true` if an event with `self` should be delivered to a subscription with
+    /// `subscription`.
+    ///
+    /// Every key of the subscription must be present in the event and, if the subscription has
+    /// a value for the key, have the same value. Keys without a value, and keys that are left
+    /// out, such as the levels of a topic, therefore act as wildcards.
+    pub(super) fn matches(&self, subscription: &KeyValueSet) -> bool {
+        subscription.entries.iter().all(|wanted| {
+            let Ok(actual) = self.get(&wanted.key, wanted.namespace.as_deref()) else {
+                return false;
+            };
+            match (&wanted.value, &actual.value) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(wanted), Some(actual)) => wanted.matches(actual),
+            }
+        })
+    }
+}
+
+/// Returns the key-value set behind `key_value_set`.
+///
+/// # Safety
+///
+/// `key_value_set` must be null or a live key-value set created by the fake, and the returned
+/// reference must not outlive it.
+pub(super) unsafe fn key_value_set<'a>(
+    key_value_set: *const AXEventKeyValueSet,
+) -> Result<&'a KeyValueSet, Failure> {
+    (key_value_set as *const KeyValueSet)
+        .as_ref()
+        .ok_or_else(|| invalid("key_value_set must not be NULL"))
+}
+
+unsafe fn key_value_set_mut<'a>(
+    key_value_set: *mut AXEventKeyValueSet,
+) -> Result<&'a mut KeyValueSet, Failure> {
+    (key_value_set as *mut KeyValueSet)
+        .as_mut()
+        .ok_or_else(|| invalid("key_value_set must not be NULL"))
+}
+
+fn invalid(message: &str) -> Failure {
+    (
+        AXEventErrorCode_AX_EVENT_ERROR_INVALID_ARGUMENT,
+        message.to_string(),
+    )
+}
+
+unsafe fn required<'a>(s: *const c_char, name: &str) -> Result<&'a CStr, Failure> {
+    optional(s).ok_or_else(|| invalid(&format!("{name} must not be NULL")))
+}
+
+unsafe fn optional<'a>(s: *const c_char) -> Option<&'a CStr> {
+    if s.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(s))
+    }
+}
+
+/// Looks up the entry of `key` in `namespace` with the arguments of a getter.
+unsafe fn entry<'a>(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+) -> Result<&'a Entry, Failure> {
+    self::key_value_set(key_value_set)?.get(required(key, "key")?, optional(name_space))
+}
+
+/// Looks up the entry of `key` in `namespace`, failing if it does not have the type `expected`.
+unsafe fn typed_entry<'a>(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    expected: AXEventValueType,
+) -> Result<&'a Entry, Failure> {
+    let entry = entry(key_value_set, key, name_space)?;
+    if entry.value_type != expected {
+        return Err((
+            AXEventErrorCode_AX_EVENT_ERROR_INCOMPATIBLE_VALUE,
+            format!(
+                "key {:?} has type {} but {} was requested",
+                entry.key, entry.value_type, expected
+            ),
+        ));
+    }
+    Ok(entry)
+}
+
+#[no_mangle]
+extern "C" fn ax_event_key_value_set_new() -> *mut AXEventKeyValueSet {
+    Box::into_raw(Box::<KeyValueSet>::default()).cast()
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_free(key_value_set: *mut AXEventKeyValueSet) {
+    if !key_value_set.is_null() {
+        drop(Box::from_raw(key_value_set as *mut KeyValueSet));
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_add_key_value(
+    key_value_set: *mut AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    value: gconstpointer,
+    value_type: AXEventValueType,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let key_value_set = key_value_set_mut(key_value_set)?;
+        let key = required(key, "key")?;
+        let value = match value_type {
+            _ if value.is_null() => None,
+            AXEventValueType_AX_VALUE_TYPE_INT => Some(Value::Int(*(value as *const c_int))),
+            AXEventValueType_AX_VALUE_TYPE_BOOL => Some(Value::Bool(*(value as *const gboolean))),
+            AXEventValueType_AX_VALUE_TYPE_DOUBLE => Some(Value::Double(*(value as *const f64))),
+            AXEventValueType_AX_VALUE_TYPE_STRING => {
+                Some(Value::String(CStr::from_ptr(value as *const c_char).into()))
+            }
+            _ => {
+                return Err(invalid(
+                    "only int, bool, double and string values are supported",
+                ))
+            }
+        };
+        key_value_set.set(Entry {
+            namespace: optional(name_space).map(CString::from),
+            key: key.into(),
+            value_type,
+            value,
+        });
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_mark_as_source(
+    key_value_set: *mut AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || entry(key_value_set, key, name_space).map(drop))
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_mark_as_data(
+    key_value_set: *mut AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || entry(key_value_set, key, name_space).map(drop))
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_mark_as_user_defined(
+    key_value_set: *mut AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    user_tag: *const c_char,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        required(user_tag, "user_tag")?;
+        entry(key_value_set, key, name_space).map(drop)
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_add_nice_names(
+    key_value_set: *mut AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    _key_nice_name: *const c_char,
+    _value_nice_name: *const c_char,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || entry(key_value_set, key, name_space).map(drop))
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_get_value_type(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    value_type: *mut AXEventValueType,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        *value_type = entry(key_value_set, key, name_space)?.value_type;
+        Ok(())
+    })
+}
+
+// Like `libaxevent`, the getters leave the output untouched if the key has no value.
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_get_integer(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    value: *mut c_int,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let entry = typed_entry(
+            key_value_set,
+            key,
+            name_space,
+            AXEventValueType_AX_VALUE_TYPE_INT,
+        )?;
+        if let Some(Value::Int(v)) = entry.value {
+            *value = v;
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_get_boolean(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    value: *mut gboolean,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let entry = typed_entry(
+            key_value_set,
+            key,
+            name_space,
+            AXEventValueType_AX_VALUE_TYPE_BOOL,
+        )?;
+        if let Some(Value::Bool(v)) = entry.value {
+            *value = v;
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_get_double(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    value: *mut f64,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let entry = typed_entry(
+            key_value_set,
+            key,
+            name_space,
+            AXEventValueType_AX_VALUE_TYPE_DOUBLE,
+        )?;
+        if let Some(Value::Double(v)) = entry.value {
+            *value = v;
+        }
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_get_string(
+    key_value_set: *const AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    value: *mut *mut c_char,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let entry = typed_entry(
+            key_value_set,
+            key,
+            name_space,
+            AXEventValueType_AX_VALUE_TYPE_STRING,
+        )?;
+        *value = match &entry.value {
+            Some(Value::String(v)) => glib_sys::g_strdup(v.as_ptr()),
+            _ => std::ptr::null_mut(),
+        };
+        Ok(())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_key_value_set_remove_key(
+    key_value_set: *mut AXEventKeyValueSet,
+    key: *const c_char,
+    name_space: *const c_char,
+    error: *mut *mut GError,
+) -> gboolean {
+    report(error, || {
+        let key_value_set = key_value_set_mut(key_value_set)?;
+        let key = required(key, "key")?;
+        let namespace = optional(name_space);
+        key_value_set.get(key, namespace)?;
+        key_value_set
+            .entries
+            .retain(|e| !(e.key.as_c_str() == key && e.namespace.as_deref() == namespace));
+        Ok(())
+    })
+}
+
+/// An event, which owns a copy of its key-value set and a reference to its timestamp.
+pub(super) struct Event {
+    key_value_set: KeyValueSet,
+    time_stamp: *mut GDateTime,
+}
+
+// SAFETY: `GDateTime` is immutable and its reference counting is thread safe.
+unsafe impl Send for Event {}
+
+impl Event {
+    /// Returns an event with the timestamp of `self` and `key_value_set`.
+    pub(super) fn with_key_value_set(&self, key_value_set: KeyValueSet) -> Self {
+        Self {
+            key_value_set,
+            time_stamp: unsafe { glib_sys::g_date_time_ref(self.time_stamp) },
+        }
+    }
+
+    pub(super) fn key_value_set(&self) -> &KeyValueSet {
+        &self.key_value_set
+    }
+
+    /// Transfers the event to the caller.
+    pub(super) fn into_raw(self) -> *mut AXEvent {
+        Box::into_raw(Box::new(self)).cast()
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        unsafe { glib_sys::g_date_time_unref(self.time_stamp) };
+    }
+}
+
+/// Returns the event behind `event`.
+///
+/// # Safety
+///
+/// `event` must be null or a live event created by the fake, and the returned reference must
+/// not outlive it.
+pub(super) unsafe fn event<'a>(event: *const AXEvent) -> Result<&'a Event, Failure> {
+    (event as *const Event)
+        .as_ref()
+        .ok_or_else(|| invalid("event must not be NULL"))
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_new2(
+    key_value_set: *mut AXEventKeyValueSet,
+    time_stamp: *mut GDateTime,
+) -> *mut AXEvent {
+    let Ok(key_value_set) = self::key_value_set(key_value_set) else {
+        return std::ptr::null_mut();
+    };
+    // The key-value set is copied, but the reference to the timestamp is taken over.
+    let time_stamp = if time_stamp.is_null() {
+        glib_sys::g_date_time_new_now_utc()
+    } else {
+        time_stamp
+    };
+    Event {
+        key_value_set: key_value_set.clone(),
+        time_stamp,
+    }
+    .into_raw()
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_free(event: *mut AXEvent) {
+    if !event.is_null() {
+        drop(Box::from_raw(event as *mut Event));
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_get_key_value_set(event: *mut AXEvent) -> *const AXEventKeyValueSet {
+    match self::event(event) {
+        Ok(event) => (&event.key_value_set as *const KeyValueSet).cast(),
+        Err(_) => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn ax_event_get_time_stamp2(event: *mut AXEvent) -> *mut GDateTime {
+    match self::event(event) {
+        Ok(event) => event.time_stamp,
+        Err(_) => std::ptr::null_mut(),
+    }
+}
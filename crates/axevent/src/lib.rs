@@ -1,10 +1,37 @@
 //! Bindings for the [Event API](https://axiscommunications.github.io/acap-documentation/docs/api/src/api/axevent/html/index.html).
 //!
-//! This crate provide two APIs with different goals:
-//! - [`ergo`] strives to enable all but the most exotic use cases in an easy and idiomatic way.
-//! - [`flex`] strives to facilitate transitioning from C.
+//! This crate provides the following modules:
+//! - [`ergo`] is an API that strives to enable all but the most exotic use cases in an easy and
+//!   idiomatic way.
+//! - [`flex`] is an API that strives to facilitate transitioning from C.
+//! - [`fake`] provides an in-process implementation of the event system that allows
+//!   applications to be tested on hosts without a device. Requires the `fake` feature to be
+//!   active.
 //! - [`nonblock`] provides an async API. Requires the `async` feature to be active
+//! - [`notification`] provides a serializable representation of events. Requires the `serde`
+//!   feature to be active.
+//! - [`replay`] records events and replays them using a handler. Requires the `serde`
+//!   feature to be active.
+//! - [`topic`] converts between ONVIF topic expressions and key-value sets.
+//! - [`template`] describes events using Rust types. `#[derive(AxEvent)]` requires the `derive`
+//!   feature to be active.
+#[cfg(all(
+    feature = "fake",
+    not(any(target_arch = "x86_64", target_os = "macos"))
+))]
+compile_error!("The `fake` feature is for host tests; events on a device need the event service");
+// Allows the code generated by `#[derive(AxEvent)]` to be used in this crate.
+extern crate self as axevent;
+
 pub mod ergo;
+#[cfg(feature = "fake")]
+pub mod fake;
 pub mod flex;
 #[cfg(feature = "async")]
 pub mod nonblock;
+#[cfg(feature = "serde")]
+pub mod notification;
+#[cfg(feature = "serde")]
+pub mod replay;
+pub mod template;
+pub mod topic;
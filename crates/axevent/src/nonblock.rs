@@ -1,26 +1,321 @@
 //! Async wrapper around axevent
+//!
+//! The futures and streams do not depend on a specific runtime, but the callbacks from axevent
+//! are dispatched by the default glib main context. Apps that do not otherwise run a glib main
+//! loop, such as tokio apps, can run one in the background using
+//! [`MainLoop`](crate::ergo::MainLoop).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use axevent::{
+//!     ergo::MainLoop,
+//!     flex::{Event, Handler, KeyValueSet},
+//!     nonblock,
+//! };
+//!
+//! let main_loop = MainLoop::new();
+//! let handler = Arc::new(Handler::new());
+//! futures_lite::future::block_on(async {
+//!     let mut key_value_set = KeyValueSet::new();
+//!     key_value_set
+//!         .add_key_value(c"topic0", Some(c"tnsaxis"), Some(c"CameraApplicationPlatform"))?
+//!         .add_key_value(c"topic1", Some(c"tnsaxis"), Some(c"HelloAXEvent"))?
+//!         .add_key_value::<&std::ffi::CStr>(c"Greeting", None, None)?
+//!         .mark_as_data(c"Greeting", None)?;
+//!     let declaration = nonblock::declare(Arc::clone(&handler), key_value_set, true).await?;
+//!
+//!     let mut key_value_set = KeyValueSet::new();
+//!     key_value_set.add_key_value(c"Greeting", None, Some(c"Hello"))?;
+//!     declaration.send_event(Event::new2(key_value_set, None))?;
+//!     Ok::<(), axevent::flex::Error>(())
+//! })?;
+//! main_loop.quit_and_join().unwrap();
+//! # Ok::<(), axevent::flex::Error>(())
+//! ```
 use std::{
+    collections::VecDeque,
     pin::Pin,
-    process,
-    task::{Context, Poll},
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
 };
 
-use async_channel::Receiver;
 use futures_lite::Stream;
-use log::{error, warn};
+use log::{debug, error, warn};
 
-use crate::flex::{Event, Handler, KeyValueSet};
+use crate::flex::{Error, Event, Handler, KeyValueSet};
 
-/// Represents an event subscription that can be iterated asynchronously
+/// Declares a new event and waits until it has been registered with the event system.
+///
+/// Like [`Handler::declare`] but resolves when the declaration is complete, which is when
+/// events sent for it start being delivered to subscribers. The event is undeclared when the
+/// returned [`Declaration`], and all its clones, are dropped, or when this future is dropped
+/// before it resolves.
+pub async fn declare(
+    handler: Arc<Handler>,
+    key_value_set: KeyValueSet,
+    stateless: bool,
+) -> Result<Declaration, Error> {
+    let (tx, rx) = async_channel::bounded(1);
+    let id = handler.declare(
+        &key_value_set,
+        stateless,
+        Some(move |_| match tx.try_send(()) {
+            Ok(()) => debug!("Declaration complete sent"),
+            // The declaration was dropped before it completed, or it completed twice.
+            Err(_) => debug!("Declaration complete not sent"),
+        }),
+    )?;
+    drop(key_value_set);
+    let declaration = Declaration {
+        registration: Arc::new(Registration { handler, id }),
+    };
+    // The callback, and thereby the sender, is kept by the handler until the event is
+    // undeclared, which does not happen before `declaration` is dropped.
+    rx.recv()
+        .await
+        .expect("callback is kept until the event is undeclared");
+    Ok(declaration)
+}
+
+struct Registration {
+    handler: Arc<Handler>,
+    id: crate::flex::Declaration,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Err(e) = self.handler.undeclare(&self.id) {
+            error!("Could not undeclare because {e:?}")
+        }
+    }
+}
+
+/// A completed declaration that events can be sent for.
 ///
-/// # Panics
+/// This is cheap to clone and can be moved between threads and tasks; the event is undeclared
+/// when the last clone is dropped.
+#[derive(Clone)]
+pub struct Declaration {
+    registration: Arc<Registration>,
+}
+
+impl Declaration {
+    /// Send an event without blocking.
+    pub fn send_event(&self, event: Event) -> Result<(), Error> {
+        self.registration
+            .handler
+            .send_event(event, &self.registration.id)
+    }
+
+    pub fn id(&self) -> crate::flex::Declaration {
+        self.registration.id
+    }
+}
+
+/// Error yielded by a [`LaggingSubscription`] in place of events that were dropped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, thiserror::Error)]
+#[error("subscription lagged behind and {0} events were dropped")]
+pub struct Lagged(pub u64);
+
+/// What to do with an event that arrives when the queue of a [`Subscription`] is full.
 ///
-/// The callback sent to the event system will panic if the internal queue fills up. To prevent
-/// this happening, users must ensure that they regularly poll the stream to pull values out of
-/// the queue.
+/// Use [`SubscriptionBuilder::try_build_lagging()`] to be told about dropped events instead.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Overflow {
+    /// Drop the oldest queued event to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Drop the new event.
+    DropNewest,
+}
+
+/// How a queue handles events that do not fit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Policy {
+    Drop(Overflow),
+    /// Drop the new event and queue a lag report before the next event that fits.
+    Lag,
+}
+
+enum Item {
+    Event(Event),
+    Lagged(u64),
+}
+
+struct Queue {
+    items: VecDeque<Item>,
+    // The number of `Item::Event` in `items`; lag reports do not count towards the capacity.
+    events: usize,
+    capacity: usize,
+    policy: Policy,
+    dropped: u64,
+    waker: Option<Waker>,
+}
+
+impl Queue {
+    fn push(&mut self, event: Event) {
+        if self.events < self.capacity {
+            self.items.push_back(Item::Event(event));
+            self.events += 1;
+            return;
+        }
+        self.dropped += 1;
+        match self.policy {
+            Policy::Drop(Overflow::DropOldest) => {
+                // The queue is full, so it contains at least one event.
+                let oldest = self
+                    .items
+                    .iter()
+                    .position(|item| matches!(item, Item::Event(_)))
+                    .unwrap();
+                self.items.remove(oldest);
+                self.items.push_back(Item::Event(event));
+            }
+            Policy::Drop(Overflow::DropNewest) => {}
+            Policy::Lag => match self.items.back_mut() {
+                Some(Item::Lagged(count)) => *count += 1,
+                _ => self.items.push_back(Item::Lagged(1)),
+            },
+        }
+        if self.dropped.is_power_of_two() {
+            warn!(
+                "Dropped {} events because the subscription is not keeping up",
+                self.dropped
+            );
+        }
+    }
+
+    fn pop(&mut self) -> Option<Result<Event, Lagged>> {
+        match self.items.pop_front()? {
+            Item::Event(event) => {
+                self.events -= 1;
+                Some(Ok(event))
+            }
+            Item::Lagged(count) => Some(Err(Lagged(count))),
+        }
+    }
+
+    fn poll_pop(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<Event, Lagged>>> {
+        match self.pop() {
+            Some(item) => Poll::Ready(Some(item)),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Builder for a [`Subscription`] with a custom queue.
+#[derive(Clone, Debug)]
+pub struct SubscriptionBuilder {
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl Default for SubscriptionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubscriptionBuilder {
+    pub fn new() -> Self {
+        Self {
+            capacity: 16,
+            overflow: Overflow::default(),
+        }
+    }
+
+    /// Set the number of events that can be queued while the stream is not polled.
+    ///
+    /// Defaults to 16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        self.capacity = capacity;
+        self
+    }
+
+    /// Set what to do with events that arrive when the queue is full.
+    ///
+    /// Defaults to [`Overflow::DropOldest`].
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn try_build(
+        self,
+        handler: &Handler,
+        subscription_specification: KeyValueSet,
+    ) -> Result<Subscription<'_>, crate::flex::Error> {
+        let policy = Policy::Drop(self.overflow);
+        self.subscribe(handler, subscription_specification, policy)
+    }
+
+    /// Like [`Self::try_build`] but reports dropped events instead of silently dropping them.
+    ///
+    /// Events that arrive when the queue is full are dropped, regardless of the
+    /// [overflow policy](Self::overflow), and a [`Lagged`] error with the number of dropped
+    /// events is yielded before the next event that fits in the queue.
+    pub fn try_build_lagging(
+        self,
+        handler: &Handler,
+        subscription_specification: KeyValueSet,
+    ) -> Result<LaggingSubscription<'_>, crate::flex::Error> {
+        self.subscribe(handler, subscription_specification, Policy::Lag)
+            .map(LaggingSubscription)
+    }
+
+    fn subscribe(
+        self,
+        handler: &Handler,
+        subscription_specification: KeyValueSet,
+        policy: Policy,
+    ) -> Result<Subscription<'_>, crate::flex::Error> {
+        let queue = Arc::new(Mutex::new(Queue {
+            items: VecDeque::with_capacity(self.capacity),
+            events: 0,
+            capacity: self.capacity,
+            policy,
+            dropped: 0,
+            waker: None,
+        }));
+        let subscription = handler.subscribe(subscription_specification, {
+            let queue = Arc::clone(&queue);
+            move |_, evt| {
+                let waker = {
+                    let mut queue = queue.lock().unwrap();
+                    queue.push(evt);
+                    queue.waker.take()
+                };
+                if let Some(waker) = waker {
+                    waker.wake();
+                }
+            }
+        })?;
+        Ok(Subscription {
+            handler,
+            queue,
+            subscription,
+        })
+    }
+}
+
+/// Represents an event subscription that can be iterated asynchronously
+///
+/// Events are queued until the stream is polled. When the queue is full, events are dropped
+/// according to the [`Overflow`] policy of the subscription.
 pub struct Subscription<'a> {
     handler: &'a Handler,
-    rx: Pin<Box<Receiver<Event>>>,
+    queue: Arc<Mutex<Queue>>,
     subscription: crate::flex::Subscription,
 }
 
@@ -31,38 +326,105 @@ impl Drop for Subscription<'_> {
 }
 
 impl<'a> Subscription<'a> {
+    /// Subscribe using the default settings of [`SubscriptionBuilder`].
     pub fn try_new(
         handler: &'a Handler,
         subscription_specification: KeyValueSet,
     ) -> Result<Self, crate::flex::Error> {
-        let (tx, rx) = async_channel::bounded::<Event>(16);
-        let subscription = handler.subscribe(subscription_specification, move |_, evt| {
-            if let Err(e) = tx.try_send(evt) {
-                match e {
-                    async_channel::TrySendError::Full(_) => {
-                        error!("Event queue is full. This is most likely due to this stream not being polled.
-                                Users of this API should ensure that they regularly try to pull
-                                values out of the stream to prevent the queue from filling up.");
-                        process::abort();
-                    },
-                    async_channel::TrySendError::Closed(_) => {
-                        warn!("Event queue was closed unexpectedly, no more events will be delivered.");
-                    },
-                };
-            }
-        })?;
-        Ok(Self {
-            handler,
-            rx: Box::pin(rx),
-            subscription,
-        })
+        SubscriptionBuilder::new().try_build(handler, subscription_specification)
     }
 }
 
 impl Stream for Subscription<'_> {
     type Item = Event;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.rx.as_mut().poll_next(cx)
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.queue.lock().unwrap().poll_pop(cx).map(|item| {
+            item.map(|item| item.expect("only lagging subscriptions queue lag reports"))
+        })
+    }
+}
+
+/// A [`Subscription`] that reports events that were dropped because the queue was full.
+///
+/// Created using [`SubscriptionBuilder::try_build_lagging()`].
+pub struct LaggingSubscription<'a>(Subscription<'a>);
+
+impl Stream for LaggingSubscription<'_> {
+    type Item = Result<Event, Lagged>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.queue.lock().unwrap().poll_pop(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(capacity: usize, policy: Policy) -> Queue {
+        Queue {
+            items: VecDeque::new(),
+            events: 0,
+            capacity,
+            policy,
+            dropped: 0,
+            waker: None,
+        }
+    }
+
+    fn event(value: i32) -> Event {
+        let mut kvs = KeyValueSet::new();
+        kvs.add_key_value(c"Value", None, Some(value)).unwrap();
+        Event::new2(kvs, None)
+    }
+
+    fn drain(queue: &mut Queue) -> Vec<Result<i32, Lagged>> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|item| {
+                item.map(|e| {
+                    e.key_value_set()
+                        .get_integer(c"Value", None)
+                        .unwrap()
+                        .unwrap()
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn declarations_can_be_spawned() {
+        fn assert_send<T: Send + 'static>(_: &T) {}
+        let future = declare(Arc::new(Handler::new()), KeyValueSet::new(), true);
+        assert_send(&future);
+    }
+
+    #[test]
+    fn overflow_policies_drop_the_expected_events() {
+        for (policy, expected) in [
+            (Policy::Drop(Overflow::DropOldest), vec![Ok(2), Ok(3)]),
+            (Policy::Drop(Overflow::DropNewest), vec![Ok(0), Ok(1)]),
+            (Policy::Lag, vec![Ok(0), Ok(1), Err(Lagged(2))]),
+        ] {
+            let mut queue = queue(2, policy);
+            for i in 0..4 {
+                queue.push(event(i));
+            }
+            assert_eq!(drain(&mut queue), expected, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn lag_is_reported_in_order() {
+        let mut queue = queue(1, Policy::Lag);
+        queue.push(event(0));
+        queue.push(event(1));
+        assert_eq!(queue.pop().unwrap().map(|_| ()), Ok(()));
+        queue.push(event(2));
+        queue.push(event(3));
+        assert_eq!(
+            drain(&mut queue),
+            vec![Err(Lagged(1)), Ok(2), Err(Lagged(1))]
+        );
     }
 }
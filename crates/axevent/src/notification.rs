@@ -0,0 +1,322 @@
+//! Serializable representation of events.
+//!
+//! [`Notification`] has the same shape as the notifications of the event stream in VAPIX, see
+//! `acap_vapix::ws_data_stream::Notification`, so that events received locally and remotely can
+//! be logged, forwarded and asserted on in the same way. Unlike VAPIX it keeps the types of the
+//! values, which allows converting it back into a [`KeyValueSet`] or an [`Event`].
+//!
+//! The event system provides no way to list the keys of a key-value set, or to tell which of
+//! them are source or data keys. Converting into a notification therefore requires a [`Schema`]
+//! naming the keys to read.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use axevent::{
+//!     flex::{Handler, KeyValueSet},
+//!     notification::{Notification, Schema},
+//! };
+//!
+//! let schema = Schema::new().source(None, c"port").data(None, c"state");
+//! let mut key_value_set = KeyValueSet::new();
+//! key_value_set
+//!     .add_key_value(c"topic0", Some(c"tns1"), Some(c"Device"))?
+//!     .add_key_value(c"topic1", Some(c"tnsaxis"), Some(c"IO"))?
+//!     .add_key_value(c"topic2", Some(c"tnsaxis"), Some(c"VirtualPort"))?;
+//! let handler = Handler::new();
+//! handler.subscribe(key_value_set, move |_, event| {
+//!     match Notification::from_event(&event, &schema) {
+//!         Ok(notification) => println!("{}", serde_json::to_string(&notification).unwrap()),
+//!         Err(e) => eprintln!("Could not convert event: {e}"),
+//!     }
+//! })?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
+};
+
+use glib::{DateTime, TimeSpan};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    flex::{Event, KeyValueSet, ValueType},
+    topic::TopicPath,
+};
+
+/// Error returned when converting between notifications and key-value sets.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Axevent(#[from] crate::flex::Error),
+    #[error("invalid notification: {0}")]
+    Invalid(String),
+}
+
+/// The value of a key.
+///
+/// Serialized as the corresponding JSON type.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Int(i32),
+    Double(f64),
+    String(String),
+}
+
+/// The keys of a notification, in the form `prefix:name` if they have a namespace.
+///
+/// Keys without a value map to `None`.
+pub type Keys = BTreeMap<String, Option<Value>>;
+
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationMessage {
+    #[serde(default)]
+    pub source: Keys,
+    /// Keys that are neither source nor data keys.
+    ///
+    /// The event system has no counterpart to this, so it is empty for converted events, and
+    /// these keys are added without a marking when converted to a key-value set.
+    #[serde(default)]
+    pub key: Keys,
+    #[serde(default)]
+    pub data: Keys,
+}
+
+#[non_exhaustive]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    /// The topic, such as `tns1:Device/tnsaxis:IO/VirtualInput`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    /// Milliseconds since the UNIX epoch.
+    pub timestamp: Option<u64>,
+    pub message: NotificationMessage,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Role {
+    Source,
+    Data,
+}
+
+/// The keys to read when converting a key-value set into a [`Notification`].
+#[derive(Clone, Debug, Default)]
+pub struct Schema {
+    keys: Vec<(Role, Option<CString>, CString)>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a source key.
+    pub fn source(mut self, namespace: Option<&CStr>, key: &CStr) -> Self {
+        self.keys
+            .push((Role::Source, namespace.map(CString::from), key.into()));
+        self
+    }
+
+    /// Add a data key.
+    pub fn data(mut self, namespace: Option<&CStr>, key: &CStr) -> Self {
+        self.keys
+            .push((Role::Data, namespace.map(CString::from), key.into()));
+        self
+    }
+}
+
+fn lossy(s: &CStr) -> String {
+    s.to_string_lossy().into_owned()
+}
+
+fn qualified_key(namespace: Option<&CStr>, key: &CStr) -> String {
+    match namespace {
+        None => lossy(key),
+        Some(namespace) => format!("{}:{}", lossy(namespace), lossy(key)),
+    }
+}
+
+fn split_key(key: &str) -> Result<(Option<CString>, CString), Error> {
+    let c_string = |s: &str| {
+        CString::new(s).map_err(|_| Error::Invalid(format!("key {key:?} contains a nul byte")))
+    };
+    match key.split_once(':') {
+        None => Ok((None, c_string(key)?)),
+        Some((namespace, key)) => Ok((Some(c_string(namespace)?), c_string(key)?)),
+    }
+}
+
+fn read_value(
+    key_value_set: &KeyValueSet,
+    namespace: Option<&CStr>,
+    key: &CStr,
+) -> Result<Option<Option<Value>>, Error> {
+    // Absent keys are reported as errors, so this also tells if the key exists.
+    let Ok(value_type) = key_value_set.get_value_type(key, namespace) else {
+        return Ok(None);
+    };
+    let value = match value_type {
+        ValueType::Int => key_value_set.get_integer(key, namespace)?.map(Value::Int),
+        ValueType::Bool => key_value_set.get_boolean(key, namespace)?.map(Value::Bool),
+        ValueType::Double => key_value_set.get_double(key, namespace)?.map(Value::Double),
+        ValueType::String => key_value_set
+            .get_string(key, namespace)?
+            .map(|v| Value::String(lossy(v.as_c_str()))),
+        ValueType::Element => {
+            return Err(Error::Invalid(format!(
+                "element values, like the one of {}, are not supported",
+                qualified_key(namespace, key)
+            )))
+        }
+    };
+    Ok(Some(value))
+}
+
+fn write_value(
+    key_value_set: &mut KeyValueSet,
+    namespace: Option<&CStr>,
+    key: &CStr,
+    value: &Option<Value>,
+) -> Result<(), Error> {
+    match value {
+        None => key_value_set.add_key_value::<&CStr>(key, namespace, None)?,
+        Some(Value::Bool(v)) => key_value_set.add_key_value(key, namespace, Some(*v))?,
+        Some(Value::Int(v)) => key_value_set.add_key_value(key, namespace, Some(*v))?,
+        Some(Value::Double(v)) => key_value_set.add_key_value(key, namespace, Some(*v))?,
+        Some(Value::String(v)) => {
+            let v = CString::new(v.as_str())
+                .map_err(|_| Error::Invalid(format!("value {v:?} contains a nul byte")))?;
+            key_value_set.add_key_value(key, namespace, Some(v.as_c_str()))?
+        }
+    };
+    Ok(())
+}
+
+impl Notification {
+    /// Read the topic and the keys in `schema` from `key_value_set`.
+    ///
+    /// Keys in `schema` that are not in `key_value_set` are left out, and strings that are not
+    /// valid UTF-8 are converted lossily.
+    pub fn from_key_value_set(key_value_set: &KeyValueSet, schema: &Schema) -> Result<Self, Error> {
+        let mut message = NotificationMessage::default();
+        for (role, namespace, key) in &schema.keys {
+            let namespace = namespace.as_deref();
+            if let Some(value) = read_value(key_value_set, namespace, key)? {
+                let keys = match role {
+                    Role::Source => &mut message.source,
+                    Role::Data => &mut message.data,
+                };
+                keys.insert(qualified_key(namespace, key), value);
+            }
+        }
+        Ok(Self {
+            topic: key_value_set.topic().map(|t| t.to_string()),
+            timestamp: None,
+            message,
+        })
+    }
+
+    /// Like [`Self::from_key_value_set`] but also reads the timestamp of `event`.
+    pub fn from_event(event: &Event, schema: &Schema) -> Result<Self, Error> {
+        let mut notification = Self::from_key_value_set(event.key_value_set(), schema)?;
+        let time_stamp = event.time_stamp2();
+        let timestamp = time_stamp.to_unix() * 1000 + i64::from(time_stamp.microsecond() / 1000);
+        notification.timestamp = u64::try_from(timestamp).ok();
+        Ok(notification)
+    }
+
+    /// Create a key-value set with the topic and keys of the notification.
+    ///
+    /// Source and data keys are marked as such.
+    pub fn to_key_value_set(&self) -> Result<KeyValueSet, Error> {
+        let mut key_value_set = KeyValueSet::new();
+        if let Some(topic) = &self.topic {
+            let topic: TopicPath = topic.parse().map_err(|e| Error::Invalid(format!("{e}")))?;
+            key_value_set.add_topic(&topic)?;
+        }
+        for (role, keys) in [
+            (None, &self.message.key),
+            (Some(Role::Source), &self.message.source),
+            (Some(Role::Data), &self.message.data),
+        ] {
+            for (key, value) in keys {
+                let (namespace, key) = split_key(key)?;
+                let namespace = namespace.as_deref();
+                write_value(&mut key_value_set, namespace, &key, value)?;
+                match role {
+                    None => {}
+                    Some(Role::Source) => {
+                        key_value_set.mark_as_source(&key, namespace)?;
+                    }
+                    Some(Role::Data) => {
+                        key_value_set.mark_as_data(&key, namespace)?;
+                    }
+                }
+            }
+        }
+        Ok(key_value_set)
+    }
+
+    /// Create an event with the topic, keys and timestamp of the notification.
+    pub fn to_event(&self) -> Result<Event, Error> {
+        let time_stamp = match self.timestamp {
+            None => None,
+            Some(timestamp) => Some(
+                i64::try_from(timestamp)
+                    .ok()
+                    .and_then(|t| {
+                        DateTime::from_unix_utc(t / 1000)
+                            .and_then(|d| d.add(TimeSpan::from_milliseconds(t % 1000)))
+                            .ok()
+                    })
+                    .ok_or_else(|| {
+                        Error::Invalid(format!("timestamp {timestamp} is out of range"))
+                    })?,
+            ),
+        };
+        Ok(Event::new2(self.to_key_value_set()?, time_stamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_vapix_notification() {
+        let s = r#"{"topic":"tns1:Device/tnsaxis:IO/VirtualInput","timestamp":1722108150418,"message":{"source":{"port":"38"},"key":{},"data":{"active":"0"}}}"#;
+        let notification: Notification = serde_json::from_str(s).unwrap();
+        assert_eq!(
+            notification.message.source["port"],
+            Some(Value::String("38".to_string()))
+        );
+    }
+
+    #[test]
+    fn notification_round_trips_through_event() {
+        let notification: Notification = serde_json::from_value(serde_json::json!({
+            "topic": "tns1:Device/tnsaxis:IO/VirtualInput",
+            "timestamp": 1722108150418u64,
+            "message": {
+                "source": {"port": 38},
+                "data": {"active": true, "tnsaxis:level": 0.5, "comment": null}
+            }
+        }))
+        .unwrap();
+        let schema = Schema::new()
+            .source(None, c"port")
+            .data(None, c"active")
+            .data(Some(c"tnsaxis"), c"level")
+            .data(None, c"comment")
+            .data(None, c"missing");
+        let event = notification.to_event().unwrap();
+        assert_eq!(
+            Notification::from_event(&event, &schema).unwrap(),
+            notification
+        );
+    }
+}
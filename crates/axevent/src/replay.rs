@@ -0,0 +1,299 @@
+//! Recording events and replaying them without the hardware that triggered them.
+//!
+//! A [`Recorder`] subscribes to events and writes them, together with when they were received,
+//! to a file with one JSON object per line. A [`Replay`] declares the recorded events on a
+//! [`Handler`] and sends them at the recorded pace or faster, so subscriptions receive them like
+//! any other event. Together with the [`fake`](crate::fake) backend this makes it possible to
+//! reproduce the input to subscription logic, such as motion or input port events, in unit
+//! tests on a host.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufReader, time::Duration};
+//!
+//! use axevent::{
+//!     ergo::{MainLoop, Subscription},
+//!     flex::{Handler, KeyValueSet},
+//!     replay::{read_recording, Replay},
+//! };
+//!
+//! let events = read_recording(BufReader::new(File::open("virtual_input.jsonl")?))?;
+//! let main_loop = MainLoop::new();
+//! let handler = Handler::new();
+//! let mut topic = KeyValueSet::new();
+//! topic
+//!     .add_key_value(c"topic0", Some(c"tns1"), Some(c"Device"))?
+//!     .add_key_value(c"topic1", Some(c"tnsaxis"), Some(c"IO"))?
+//!     .add_key_value(c"topic2", Some(c"tnsaxis"), Some(c"VirtualInput"))?;
+//! let subscription = Subscription::try_new(topic, &handler)?;
+//! Replay::new(events).speed(f64::INFINITY).run(&handler)?;
+//! let event = subscription.rx.recv_timeout(Duration::from_secs(1))?;
+//! println!("{:?}", event.key_value_set().get_boolean(c"active", None)?);
+//! drop(subscription);
+//! main_loop.quit_and_join().unwrap();
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::{
+    io,
+    io::{BufRead, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ergo::Declaration,
+    flex::{Handler, KeyValueSet},
+    notification::{Error, Notification, Schema},
+};
+
+/// An event together with when it was received.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedEvent {
+    /// Milliseconds between the start of the recording and when the event was received.
+    pub elapsed_ms: u64,
+    /// `true` if the event was declared as stateless, otherwise `false`.
+    ///
+    /// Defaults to `true` when missing from a recording.
+    #[serde(default = "stateless_by_default")]
+    pub stateless: bool,
+    pub notification: Notification,
+}
+
+fn stateless_by_default() -> bool {
+    true
+}
+
+/// Writes the events matching a subscription to a file while it is alive.
+pub struct Recorder<'a> {
+    handler: &'a Handler,
+    subscription: crate::flex::Subscription,
+}
+
+impl Drop for Recorder<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.handler.unsubscribe(&self.subscription) {
+            error!("Could not unsubscribe because {e:?}")
+        }
+    }
+}
+
+impl<'a> Recorder<'a> {
+    /// Start recording the events matching `subscription_specification`.
+    ///
+    /// Each event is converted using `schema` and written to `writer` as a line of JSON. The
+    /// writer is flushed after every event so that a recording survives the app being killed.
+    ///
+    /// Subscribers cannot tell whether an event was declared as stateless, so this is recorded
+    /// as `stateless` for every event.
+    pub fn start<W>(
+        handler: &'a Handler,
+        subscription_specification: KeyValueSet,
+        schema: Schema,
+        stateless: bool,
+        mut writer: W,
+    ) -> Result<Self, crate::flex::Error>
+    where
+        W: Write + Send + 'static,
+    {
+        let start = Instant::now();
+        let subscription = handler.subscribe(subscription_specification, move |_, event| {
+            let notification = match Notification::from_event(&event, &schema) {
+                Ok(n) => n,
+                Err(e) => {
+                    warn!("Could not record event because {e}");
+                    return;
+                }
+            };
+            let recorded = RecordedEvent {
+                elapsed_ms: start.elapsed().as_millis() as u64,
+                stateless,
+                notification,
+            };
+            if let Err(e) = write_event(&mut writer, &recorded) {
+                error!("Could not write event because {e}");
+            }
+        })?;
+        Ok(Self {
+            handler,
+            subscription,
+        })
+    }
+}
+
+fn write_event(writer: &mut impl Write, event: &RecordedEvent) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, event)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// Read a recording written by a [`Recorder`].
+///
+/// Blank lines are ignored.
+pub fn read_recording(reader: impl BufRead) -> io::Result<Vec<RecordedEvent>> {
+    let mut events = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        events.push(serde_json::from_str(&line)?);
+    }
+    Ok(events)
+}
+
+/// Sends recorded events using a [`Handler`].
+pub struct Replay {
+    events: Vec<RecordedEvent>,
+    speed: f64,
+}
+
+impl Replay {
+    pub fn new(events: Vec<RecordedEvent>) -> Self {
+        Self { events, speed: 1.0 }
+    }
+
+    /// Set how many times faster than recorded the events are sent.
+    ///
+    /// Defaults to `1.0`. Use `f64::INFINITY` to send the events without waiting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is not positive.
+    pub fn speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "speed must be positive");
+        self.speed = speed;
+        self
+    }
+
+    /// Send the events in order, blocking until all have been sent.
+    ///
+    /// Every combination of topic and source keys in the recording is declared before the first
+    /// event is sent and undeclared when this returns. The first event with a combination
+    /// determines whether its declaration is stateless and the types of its data keys.
+    ///
+    /// Blocks until the declarations are complete, so the default main context must be running,
+    /// e.g. using [`MainLoop`](crate::ergo::MainLoop).
+    pub fn run(&self, handler: &Handler) -> Result<(), Error> {
+        let mut declarations: Vec<(&Notification, Declaration)> = Vec::new();
+        for event in &self.events {
+            let notification = &event.notification;
+            if !declarations
+                .iter()
+                .any(|(declared, _)| same_declaration(declared, notification))
+            {
+                let declaration = Declaration::try_new(
+                    notification.to_key_value_set()?,
+                    event.stateless,
+                    handler,
+                )?;
+                // The sender lives as long as the declaration, so this only returns once the
+                // declaration is complete.
+                let _ = declaration.rx.recv();
+                declarations.push((notification, declaration));
+            }
+        }
+
+        let start = Instant::now();
+        for event in &self.events {
+            let due = Duration::from_millis(event.elapsed_ms).div_f64(self.speed);
+            if let Some(remaining) = due.checked_sub(start.elapsed()) {
+                thread::sleep(remaining);
+            }
+            let (_, declaration) = declarations
+                .iter()
+                .find(|(declared, _)| same_declaration(declared, &event.notification))
+                .expect("every event has been declared");
+            // The declaration provides the topic and source keys.
+            let mut data = event.notification.clone();
+            data.topic = None;
+            data.message.source.clear();
+            declaration.send_event(data.to_event()?)?;
+        }
+        Ok(())
+    }
+}
+
+fn same_declaration(a: &Notification, b: &Notification) -> bool {
+    a.topic == b.topic && a.message.source == b.message.source
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        ergo::MainLoop,
+        topic::{subscription_key_value_sets, Topic},
+    };
+
+    const RECORDING: &str = r#"
+{"elapsedMs":0,"stateless":false,"notification":{"topic":"tns1:Device/tnsaxis:IO/VirtualInput","timestamp":1722108150418,"message":{"source":{"port":38},"data":{"active":true}}}}
+{"elapsedMs":200,"notification":{"topic":"tns1:Device/tnsaxis:IO/Port","timestamp":1722108150618,"message":{"source":{"port":1},"data":{"state":false}}}}
+{"elapsedMs":400,"stateless":false,"notification":{"topic":"tns1:Device/tnsaxis:IO/VirtualInput","timestamp":1722108150818,"message":{"source":{"port":38},"data":{"active":false}}}}
+"#;
+
+    fn recording() -> Vec<RecordedEvent> {
+        read_recording(RECORDING.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let events = recording();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events.iter().map(|e| e.stateless).collect::<Vec<_>>(),
+            vec![false, true, false]
+        );
+        let mut written = Vec::new();
+        for event in &events {
+            write_event(&mut written, event).unwrap();
+        }
+        assert_eq!(read_recording(written.as_slice()).unwrap(), events);
+    }
+
+    #[test]
+    fn replay_delivers_matching_events_in_order() {
+        let main_loop = MainLoop::new();
+        let handler = Handler::new();
+        let topic: Topic = "tns1:Device/tnsaxis:IO/VirtualInput".parse().unwrap();
+        let [kvs] = subscription_key_value_sets(&topic)
+            .unwrap()
+            .try_into()
+            .ok()
+            .unwrap();
+        let (tx, rx) = mpsc::channel();
+        handler
+            .subscribe(kvs, move |_, event| {
+                let _ = tx.send(event);
+            })
+            .unwrap();
+
+        let start = Instant::now();
+        Replay::new(recording()).speed(10.0).run(&handler).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        let received: Vec<_> = (0..2)
+            .map(|_| rx.recv_timeout(Duration::from_secs(1)).unwrap())
+            .map(|event| {
+                let kvs = event.key_value_set();
+                (
+                    kvs.get_integer(c"port", None).unwrap(),
+                    kvs.get_boolean(c"active", None).unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            received,
+            vec![(Some(38), Some(true)), (Some(38), Some(false))]
+        );
+
+        drop(handler);
+        main_loop.quit_and_join().unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+}
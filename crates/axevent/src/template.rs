@@ -0,0 +1,209 @@
+//! Events described by Rust types.
+//!
+//! Declaring an event by hand involves adding every topic level, source and data key to a
+//! [`KeyValueSet`] and marking and naming each of them. [`AxEvent`] captures this in a type so
+//! that the declaration and the events sent for it cannot disagree.
+//!
+//! With the `derive` feature active, `#[derive(AxEvent)]` implements the trait for a struct,
+//! generating the topic, nice names and stateful or stateless flag at compile time. Every field
+//! is either a source or data key and its type must implement [`FieldValue`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # #[cfg(feature = "derive")]
+//! # fn main() -> Result<(), axevent::flex::Error> {
+//! use axevent::{flex::Handler, template::AxEvent};
+//!
+//! #[derive(AxEvent)]
+//! #[ax_event(topic = "tns1:Monitoring/ProcessorUsage", stateful)]
+//! struct ProcessorUsage {
+//!     #[ax_event(source, key = "Token", user_defined = "wstype:tt:ReferenceToken")]
+//!     token: i32,
+//!     #[ax_event(data, key = "Value", user_defined = "wstype:xs:float")]
+//!     value: f64,
+//! }
+//!
+//! let handler = Handler::new();
+//! let mut usage = ProcessorUsage {
+//!     token: 0,
+//!     value: 0.0,
+//! };
+//! let declaration = usage.declare(&handler)?;
+//! usage.value = 42.0;
+//! declaration.send_event(usage.event(None)?)?;
+//! # Ok(())
+//! # }
+//! # #[cfg(not(feature = "derive"))]
+//! # fn main() {}
+//! ```
+use std::ffi::{CStr, CString};
+
+#[cfg(feature = "derive")]
+pub use axevent_derive::AxEvent;
+use glib::DateTime;
+
+use crate::{
+    ergo::Declaration,
+    flex::{Error, Event, Handler, KeyValueSet},
+};
+
+/// An event with a fixed topic and a fixed set of source and data keys.
+///
+/// Usually implemented using `#[derive(AxEvent)]`.
+pub trait AxEvent {
+    /// `true` if the event is stateless, otherwise `false`.
+    const STATELESS: bool;
+
+    /// Returns a key-value set with only the topic of the event.
+    ///
+    /// This is suitable for subscribing to events of this type.
+    fn topic() -> Result<KeyValueSet, Error>;
+
+    /// Returns a key-value set declaring the event.
+    ///
+    /// The values of `self` are used as the values of the source keys and as the initial values
+    /// of the data keys.
+    fn declaration(&self) -> Result<KeyValueSet, Error>;
+
+    /// Returns a key-value set with the data keys of `self`, to be sent for a declaration.
+    fn key_value_set(&self) -> Result<KeyValueSet, Error>;
+
+    /// Declares the event using [`declaration`](Self::declaration).
+    fn declare<'a>(&self, handler: &'a Handler) -> Result<Declaration<'a>, Error> {
+        Declaration::try_new(self.declaration()?, Self::STATELESS, handler)
+    }
+
+    /// Creates an event with the data keys of `self`.
+    fn event(&self, time_stamp: Option<DateTime>) -> Result<Event, Error> {
+        Ok(Event::new2(self.key_value_set()?, time_stamp))
+    }
+}
+
+/// A type that can be the value of a key in an [`AxEvent`].
+pub trait FieldValue {
+    /// Adds `self` as the value of `key` to `key_value_set`.
+    fn add_to(
+        &self,
+        key_value_set: &mut KeyValueSet,
+        key: &CStr,
+        namespace: Option<&CStr>,
+    ) -> Result<(), Error>;
+
+    /// Adds `key` without a value to `key_value_set`.
+    ///
+    /// This is used for the `None` variant of `Option<Self>`.
+    fn add_none_to(
+        key_value_set: &mut KeyValueSet,
+        key: &CStr,
+        namespace: Option<&CStr>,
+    ) -> Result<(), Error>;
+}
+
+macro_rules! impl_field_value {
+    ($t:ty, $typed:ty, $c:expr) => {
+        impl FieldValue for $t {
+            fn add_to(
+                &self,
+                key_value_set: &mut KeyValueSet,
+                key: &CStr,
+                namespace: Option<&CStr>,
+            ) -> Result<(), Error> {
+                key_value_set.add_key_value::<$typed>(key, namespace, Some($c(self)))?;
+                Ok(())
+            }
+
+            fn add_none_to(
+                key_value_set: &mut KeyValueSet,
+                key: &CStr,
+                namespace: Option<&CStr>,
+            ) -> Result<(), Error> {
+                key_value_set.add_key_value::<$typed>(key, namespace, None)?;
+                Ok(())
+            }
+        }
+    };
+}
+impl_field_value!(bool, bool, |v: &bool| *v);
+impl_field_value!(f64, f64, |v: &f64| *v);
+impl_field_value!(i32, i32, |v: &i32| *v);
+impl_field_value!(CString, &CStr, CString::as_c_str);
+
+impl<T: FieldValue> FieldValue for Option<T> {
+    fn add_to(
+        &self,
+        key_value_set: &mut KeyValueSet,
+        key: &CStr,
+        namespace: Option<&CStr>,
+    ) -> Result<(), Error> {
+        match self {
+            Some(v) => v.add_to(key_value_set, key, namespace),
+            None => T::add_none_to(key_value_set, key, namespace),
+        }
+    }
+
+    fn add_none_to(
+        key_value_set: &mut KeyValueSet,
+        key: &CStr,
+        namespace: Option<&CStr>,
+    ) -> Result<(), Error> {
+        T::add_none_to(key_value_set, key, namespace)
+    }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use super::*;
+
+    #[derive(AxEvent)]
+    #[ax_event(topic = "tnsaxis:CameraApplicationPlatform/HelloAXEvent", stateless)]
+    struct Hello {
+        #[ax_event(source, key = "Token")]
+        token: i32,
+        #[ax_event(data, key = "Greeting")]
+        greeting: Option<CString>,
+        #[ax_event(data, key = "Active", namespace = "tnsaxis")]
+        active: bool,
+    }
+
+    #[test]
+    fn derived_declaration_has_topic_and_keys() {
+        let hello = Hello {
+            token: 3,
+            greeting: None,
+            active: true,
+        };
+        let kvs = hello.declaration().unwrap();
+        assert_eq!(
+            kvs.get_string(c"topic1", Some(c"tnsaxis"))
+                .unwrap()
+                .unwrap()
+                .as_c_str(),
+            c"HelloAXEvent"
+        );
+        assert_eq!(kvs.get_integer(c"Token", None).unwrap(), Some(3));
+        assert!(kvs.get_string(c"Greeting", None).unwrap().is_none());
+        assert_eq!(
+            kvs.get_boolean(c"Active", Some(c"tnsaxis")).unwrap(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn derived_events_have_only_data_keys() {
+        let hello = Hello {
+            token: 3,
+            greeting: Some(c"Hello".into()),
+            active: false,
+        };
+        let kvs = hello.key_value_set().unwrap();
+        assert!(kvs.get_integer(c"Token", None).is_err());
+        assert_eq!(
+            kvs.get_string(c"Greeting", None)
+                .unwrap()
+                .unwrap()
+                .as_c_str(),
+            c"Hello"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Conversion between [`Topic`]s and the topic keys of key-value sets.
+//!
+//! The event system stores the levels of a topic as the keys `topic0`, `topic1` and so on, each
+//! in the namespace of its level.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use axevent::{flex::Handler, topic, topic::Topic};
+//!
+//! let handler = Handler::new();
+//! let topic: Topic = "tns1:Device/tnsaxis:IO/VirtualInput|tns1:Device/Trigger//.".parse()?;
+//! for key_value_set in topic::subscription_key_value_sets(&topic)? {
+//!     handler.subscribe(key_value_set, |_, event| {
+//!         println!("{:?}", event.key_value_set().topic());
+//!     })?;
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::ffi::{CStr, CString};
+
+pub use onvif_topic::{Level, Name, ParseTopicError, Topic, TopicPath};
+
+use crate::flex::{Error, KeyValueSet};
+
+/// The namespaces in which [`KeyValueSet::topic`] looks for the levels of a topic.
+pub const TOPIC_NAMESPACES: [&CStr; 2] = [c"tns1", c"tnsaxis"];
+
+fn topic_key(level: usize) -> CString {
+    CString::new(format!("topic{level}")).expect("formatted key has no nul bytes")
+}
+
+impl KeyValueSet {
+    /// Adds the levels of `path` as topic keys.
+    ///
+    /// Wildcard levels are left out, which makes a subscription match any value at that level.
+    /// Note that the event system matches topics below a subscribed topic whether
+    /// [`TopicPath::includes_descendants`] is set or not.
+    pub fn add_topic(&mut self, path: &TopicPath) -> Result<&mut Self, Error> {
+        for (i, level) in path.levels().iter().enumerate() {
+            if let Level::Named(name) = level {
+                // Names are validated and cannot contain nul bytes.
+                let namespace = CString::new(name.namespace()).unwrap();
+                let value = CString::new(name.name()).unwrap();
+                self.add_key_value(&topic_key(i), Some(&namespace), Some(value.as_c_str()))?;
+            }
+        }
+        Ok(self)
+    }
+
+    /// Returns the topic described by the topic keys, if any.
+    ///
+    /// The levels are looked up in the [`TOPIC_NAMESPACES`] and the first missing level ends the
+    /// topic. Returns `None` if there is no `topic0` or if a level is not a valid name.
+    pub fn topic(&self) -> Option<TopicPath> {
+        let mut levels = Vec::new();
+        'levels: for i in 0.. {
+            let key = topic_key(i);
+            for namespace in TOPIC_NAMESPACES {
+                if let Ok(Some(value)) = self.get_string(&key, Some(namespace)) {
+                    let namespace = namespace.to_str().expect("namespaces are ASCII");
+                    levels.push(Level::named(namespace, value.as_c_str().to_str().ok()?).ok()?);
+                    continue 'levels;
+                }
+            }
+            break;
+        }
+        TopicPath::new(levels).ok()
+    }
+}
+
+/// Returns one key-value set for each alternative of `topic`.
+///
+/// Subscribing to all of them is equivalent to subscribing to `topic`.
+pub fn subscription_key_value_sets(topic: &Topic) -> Result<Vec<KeyValueSet>, Error> {
+    topic
+        .paths()
+        .iter()
+        .map(|path| {
+            let mut key_value_set = KeyValueSet::new();
+            key_value_set.add_topic(path)?;
+            Ok(key_value_set)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn topic_round_trips_through_key_value_set() {
+        let path: TopicPath = "tns1:Device/tnsaxis:IO/VirtualInput".parse().unwrap();
+        let mut kvs = KeyValueSet::new();
+        kvs.add_topic(&path).unwrap();
+        assert_eq!(kvs.topic(), Some(path));
+    }
+
+    #[test]
+    fn wildcard_levels_are_left_out() {
+        let topic: Topic = "tns1:Device/*/tnsaxis:VirtualInput|tns1:Monitoring"
+            .parse()
+            .unwrap();
+        let kvss = subscription_key_value_sets(&topic).unwrap();
+        assert_eq!(kvss.len(), 2);
+        assert!(kvss[0].get_string(c"topic1", Some(c"tns1")).is_err());
+        assert!(kvss[0].get_string(c"topic1", Some(c"tnsaxis")).is_err());
+        assert_eq!(
+            kvss[0]
+                .get_string(c"topic2", Some(c"tnsaxis"))
+                .unwrap()
+                .unwrap()
+                .as_c_str(),
+            c"VirtualInput"
+        );
+        assert_eq!(kvss[1].topic(), Some("tns1:Monitoring".parse().unwrap()));
+    }
+}
@@ -0,0 +1,1329 @@
+//! Safe Rust bindings for the [Machine Learning API (larod)](https://axiscommunications.github.io/acap-documentation/docs/api/src/api/larod/html/index.html).
+//!
+//! larod runs inference on the accelerators available on Axis devices. A typical application:
+//!
+//! 1. Connects to the larod service using [`Connection::new()`].
+//! 2. Picks a [`Device`] using [`Connection::device()`] or [`Connection::devices()`].
+//! 3. Loads a [`Model`] using [`Connection::load_model()`].
+//! 4. Allocates input and output [`Tensors`] using [`Connection::alloc_model_inputs()`] and
+//!    [`Connection::alloc_model_outputs()`].
+//! 5. Creates a [`JobRequest`] and runs it using [`Connection::run_job()`] once per frame.
+//!
+//! [`nonblock`] provides async versions of the functions that may block for a long time.
+//! Requires the `async` feature to be active.
+//!
+//! Frames from the `vdo` crate can be used as input without copying them, see
+//! `JobRequest::bind_vdo_buffer()`. Requires the `vdo` feature to be active.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::{fs::File, os::fd::AsFd};
+//!
+//! use larod::{Access, Connection, FdProps, JobRequest};
+//!
+//! let connection = Connection::new()?;
+//! let device = connection.device(c"cpu-tflite", 0)?;
+//! let file = File::open("model.tflite").expect("Failed to open model");
+//! let model = connection.load_model(
+//!     Some(file.as_fd()),
+//!     &device,
+//!     Access::LAROD_ACCESS_PRIVATE,
+//!     c"model",
+//!     None,
+//! )?;
+//! let mut inputs = connection.alloc_model_inputs(&model, FdProps::MAP, None)?;
+//! let outputs = connection.alloc_model_outputs(&model, FdProps::MAP, None)?;
+//!
+//! inputs[0].map_mut()?.fill(0);
+//! let job = JobRequest::new(&model, &inputs, &outputs, None)?;
+//! connection.run_job(&job)?;
+//! println!("Output: {:?}", &outputs[0].map()?[..4]);
+//! # Ok::<(), larod::Error>(())
+//! ```
+
+/// Macro for calling larod functions that take a `larodError**` parameter.
+/// Returns a tuple of `(result, Option<Error>)`.
+macro_rules! try_func {
+    ($func:path $(, $arg:expr)* $(,)?) => {{
+        let mut error: *mut larod_sys::larodError = std::ptr::null_mut();
+        let result = $func($( $arg, )* &mut error);
+        if error.is_null() {
+            (result, None)
+        } else {
+            (
+                result,
+                Some($crate::Error::Larod($crate::LarodError::from_raw(error))),
+            )
+        }
+    }};
+}
+
+mod map;
+#[cfg(feature = "async")]
+pub mod nonblock;
+#[cfg(feature = "vdo")]
+mod vdo_input;
+
+use std::{
+    ffi::{c_void, CStr},
+    fmt::{Debug, Display},
+    marker::PhantomData,
+    ops::{BitOr, Deref, DerefMut, Index, IndexMut},
+    os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    ptr, slice,
+};
+
+pub use larod_sys::{
+    larodAccess as Access, larodErrorCode as ErrorCode, larodTensorDataType as TensorDataType,
+    larodTensorLayout as TensorLayout,
+};
+use larod_sys::{larodConnection, larodDevice, larodJobRequest, larodModel, larodTensor};
+use log::error;
+pub use map::Map;
+#[cfg(feature = "vdo")]
+pub use vdo_input::VdoJobRequest;
+
+/// Error type for larod operations.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Larod(#[from] LarodError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("larod returned an unexpected null pointer")]
+    NullPointer,
+    #[error("Missing error data from larod library")]
+    MissingLarodError,
+    #[error("Tensor has no file descriptor")]
+    MissingFd,
+    #[error("Job request does not own an input at index {0}")]
+    MissingInput(usize),
+}
+
+/// Error from the larod library.
+pub struct LarodError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl LarodError {
+    /// Copies the code and message, then frees `error`.
+    ///
+    /// # Safety
+    ///
+    /// `error` must be a valid `larodError` pointer that was allocated by larod and that has no
+    /// other users.
+    pub(crate) unsafe fn from_raw(mut error: *mut larod_sys::larodError) -> Self {
+        let this = Self::copy_from(error);
+        larod_sys::larodClearError(&mut error);
+        this
+    }
+
+    /// Copies the code and message, leaving `error` untouched.
+    ///
+    /// # Safety
+    ///
+    /// `error` must be a valid `larodError` pointer.
+    pub(crate) unsafe fn copy_from(error: *const larod_sys::larodError) -> Self {
+        debug_assert!(!error.is_null());
+        let larod_sys::larodError { code, msg } = *error;
+        let message = if msg.is_null() {
+            String::from("Unknown error")
+        } else {
+            CStr::from_ptr(msg).to_string_lossy().into_owned()
+        };
+        Self { code, message }
+    }
+
+    /// Returns a human-readable name for the larod error code.
+    ///
+    /// Positive codes are `errno` values and map to `LAROD_ERROR_ERRNO`.
+    pub fn code_name(&self) -> &'static str {
+        match self.code {
+            ErrorCode::LAROD_ERROR_NONE => "LAROD_ERROR_NONE",
+            ErrorCode::LAROD_ERROR_JOB => "LAROD_ERROR_JOB",
+            ErrorCode::LAROD_ERROR_LOAD_MODEL => "LAROD_ERROR_LOAD_MODEL",
+            ErrorCode::LAROD_ERROR_FD => "LAROD_ERROR_FD",
+            ErrorCode::LAROD_ERROR_MODEL_NOT_FOUND => "LAROD_ERROR_MODEL_NOT_FOUND",
+            ErrorCode::LAROD_ERROR_PERMISSION => "LAROD_ERROR_PERMISSION",
+            ErrorCode::LAROD_ERROR_CONNECTION => "LAROD_ERROR_CONNECTION",
+            ErrorCode::LAROD_ERROR_CREATE_SESSION => "LAROD_ERROR_CREATE_SESSION",
+            ErrorCode::LAROD_ERROR_KILL_SESSION => "LAROD_ERROR_KILL_SESSION",
+            ErrorCode::LAROD_ERROR_INVALID_CHIP_ID => "LAROD_ERROR_INVALID_CHIP_ID",
+            ErrorCode::LAROD_ERROR_INVALID_ACCESS => "LAROD_ERROR_INVALID_ACCESS",
+            ErrorCode::LAROD_ERROR_DELETE_MODEL => "LAROD_ERROR_DELETE_MODEL",
+            ErrorCode::LAROD_ERROR_TENSOR_MISMATCH => "LAROD_ERROR_TENSOR_MISMATCH",
+            ErrorCode::LAROD_ERROR_VERSION_MISMATCH => "LAROD_ERROR_VERSION_MISMATCH",
+            ErrorCode::LAROD_ERROR_ALLOC => "LAROD_ERROR_ALLOC",
+            ErrorCode::LAROD_ERROR_POWER_NOT_AVAILABLE => "LAROD_ERROR_POWER_NOT_AVAILABLE",
+            ErrorCode(c) if 0 < c && c <= ErrorCode::LAROD_ERROR_MAX_ERRNO.0 => "LAROD_ERROR_ERRNO",
+            _ => "LAROD_ERROR_UNKNOWN",
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for LarodError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}): {}",
+            self.code_name(),
+            self.code.0,
+            self.message
+        )
+    }
+}
+
+impl Debug for LarodError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LarodError")
+            .field("code", &self.code.0)
+            .field("code_name", &self.code_name())
+            .field("message", &self.message)
+            .finish()
+    }
+}
+
+impl std::error::Error for LarodError {}
+
+/// Treats the presence of an error as failure, for functions that signal failure only that way.
+fn into_result<T>(value: T, maybe_error: Option<Error>) -> Result<T, Error> {
+    match maybe_error {
+        None => Ok(value),
+        Some(e) => Err(e),
+    }
+}
+
+pub(crate) fn into_unit(success: bool, maybe_error: Option<Error>) -> Result<(), Error> {
+    if success {
+        debug_assert!(maybe_error.is_none(), "larod reported success AND an error");
+        Ok(())
+    } else {
+        Err(maybe_error.unwrap_or(Error::MissingLarodError))
+    }
+}
+
+/// Properties of the file descriptor backing a tensor.
+///
+/// Used when allocating tensors to tell larod how the application intends to access them.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FdProps(pub u32);
+
+impl FdProps {
+    /// The fd can be accessed using `read()` and `write()`.
+    pub const READWRITE: Self = Self(1 << 0);
+    /// The fd can be memory mapped, see [`Tensor::map()`].
+    pub const MAP: Self = Self(1 << 1);
+    /// The fd is a dma-buf.
+    pub const DMABUF: Self = Self(1 << 2);
+}
+
+impl BitOr for FdProps {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Converts a vmem file descriptor, as used by VDO on some devices, to a dma-buf.
+///
+/// `offset` is the offset of the data from the start of `fd`. The returned descriptor can be
+/// bound to a tensor using [`Tensor::set_fd()`] together with [`FdProps::DMABUF`].
+pub fn convert_vmem_fd_to_dmabuf(fd: BorrowedFd<'_>, offset: i64) -> Result<OwnedFd, Error> {
+    let (dmabuf, maybe_error) = unsafe {
+        try_func!(
+            larod_sys::larodConvertVmemFdToDmabuf,
+            fd.as_raw_fd(),
+            offset
+        )
+    };
+    if dmabuf < 0 {
+        return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+    }
+    debug_assert!(
+        maybe_error.is_none(),
+        "larodConvertVmemFdToDmabuf returned a file descriptor AND an error"
+    );
+    // SAFETY: The caller of larodConvertVmemFdToDmabuf takes ownership of the new descriptor.
+    Ok(unsafe { OwnedFd::from_raw_fd(dmabuf) })
+}
+
+/// Value used by larod for tensors that have no file descriptor.
+const INVALID_FD: RawFd = RawFd::MIN;
+
+/// The maximum number of dimensions a tensor can have.
+pub const TENSOR_MAX_LEN: usize = 12;
+
+/// A connection to the larod service.
+///
+/// Private models loaded through a connection are unloaded when the connection is dropped.
+pub struct Connection {
+    raw: *mut larodConnection,
+}
+
+// SAFETY: We hold exclusive ownership of the raw pointer and the connection is not tied to the
+// thread that created it.
+unsafe impl Send for Connection {}
+// SAFETY: The larod documentation states that the library is thread safe.
+unsafe impl Sync for Connection {}
+
+impl Connection {
+    pub fn new() -> Result<Self, Error> {
+        let mut raw = ptr::null_mut();
+        let (success, maybe_error) = unsafe { try_func!(larod_sys::larodConnect, &mut raw) };
+        into_unit(success, maybe_error)?;
+        if raw.is_null() {
+            return Err(Error::NullPointer);
+        }
+        Ok(Self { raw })
+    }
+
+    /// Returns the number of sessions, i.e. connections, currently open on the service.
+    pub fn num_sessions(&self) -> Result<u64, Error> {
+        let mut num_sessions = 0;
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetNumSessions, self.raw, &mut num_sessions) };
+        into_unit(success, maybe_error)?;
+        Ok(num_sessions)
+    }
+
+    /// Returns the device with the given `name` and `instance`, e.g. `("cpu-tflite", 0)`.
+    pub fn device(&self, name: &CStr, instance: u32) -> Result<Device<'_>, Error> {
+        let (raw, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetDevice, self.raw, name.as_ptr(), instance) };
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        Ok(Device {
+            raw,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns all devices available on the system.
+    pub fn devices(&self) -> Result<Vec<Device<'_>>, Error> {
+        let mut num_devices = 0;
+        let (raw, maybe_error) =
+            unsafe { try_func!(larod_sys::larodListDevices, self.raw, &mut num_devices) };
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The array is allocated by larod, has `num_devices` elements and must be freed
+        // by the caller. The devices themselves are owned by the connection.
+        let devices = unsafe { slice::from_raw_parts(raw, num_devices) }
+            .iter()
+            .map(|&raw| Device {
+                raw,
+                _marker: PhantomData,
+            })
+            .collect();
+        unsafe { libc::free(raw as *mut c_void) };
+        Ok(devices)
+    }
+
+    /// Loads a model onto `device`.
+    ///
+    /// `fd` is the model file, which may be omitted for devices that do not use one such as
+    /// the preprocessing devices.
+    pub fn load_model(
+        &self,
+        fd: Option<BorrowedFd<'_>>,
+        device: &Device<'_>,
+        access: Access,
+        name: &CStr,
+        params: Option<&Map>,
+    ) -> Result<Model, Error> {
+        let (raw, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodLoadModel,
+                self.raw,
+                fd.map_or(INVALID_FD, |fd| fd.as_raw_fd()),
+                device.raw,
+                access,
+                name.as_ptr(),
+                params.map_or(ptr::null(), |p| p.as_ptr() as *const _),
+            )
+        };
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        Ok(Model { raw })
+    }
+
+    /// Unloads `model` from the service.
+    pub fn delete_model(&self, model: &Model) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodDeleteModel, self.raw, model.raw) };
+        into_unit(success, maybe_error)
+    }
+
+    /// Allocates tensors matching the inputs of `model`.
+    ///
+    /// `fd_props` describes the required properties of the backing memory; pass
+    /// [`FdProps::MAP`] to be able to [`Tensor::map()`] the tensors.
+    pub fn alloc_model_inputs(
+        &self,
+        model: &Model,
+        fd_props: FdProps,
+        params: Option<&Map>,
+    ) -> Result<Tensors<'_>, Error> {
+        let mut len = 0;
+        let (raw, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodAllocModelInputs,
+                self.raw,
+                model.raw,
+                fd_props.0,
+                &mut len,
+                params.map_or(ptr::null_mut(), Map::as_ptr),
+            )
+        };
+        // SAFETY: The tensors were allocated by larod on this connection.
+        unsafe { Tensors::from_raw(raw, len, self.raw, maybe_error) }
+    }
+
+    /// Allocates tensors matching the outputs of `model`.
+    ///
+    /// See [`Connection::alloc_model_inputs()`].
+    pub fn alloc_model_outputs(
+        &self,
+        model: &Model,
+        fd_props: FdProps,
+        params: Option<&Map>,
+    ) -> Result<Tensors<'_>, Error> {
+        let mut len = 0;
+        let (raw, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodAllocModelOutputs,
+                self.raw,
+                model.raw,
+                fd_props.0,
+                &mut len,
+                params.map_or(ptr::null_mut(), Map::as_ptr),
+            )
+        };
+        // SAFETY: The tensors were allocated by larod on this connection.
+        unsafe { Tensors::from_raw(raw, len, self.raw, maybe_error) }
+    }
+
+    /// Runs `job` and blocks until it has completed.
+    pub fn run_job(&self, job: &JobRequest<'_>) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodRunJob, self.raw, job.raw) };
+        into_unit(success, maybe_error)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodDisconnect, &mut self.raw) };
+        if let Err(e) = into_unit(success, maybe_error) {
+            error!("Failed to disconnect from larod: {e}");
+        }
+    }
+}
+
+impl Debug for Connection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connection")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+/// A device, such as a CPU, GPU or DLPU, that can run models.
+///
+/// Devices are owned by the [`Connection`] that listed them.
+#[derive(Clone, Copy)]
+pub struct Device<'a> {
+    raw: *const larodDevice,
+    _marker: PhantomData<&'a Connection>,
+}
+
+impl Device<'_> {
+    pub fn name(&self) -> Result<&CStr, Error> {
+        let (name, maybe_error) = unsafe { try_func!(larod_sys::larodGetDeviceName, self.raw) };
+        if name.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The name is owned by the device, which outlives `&self`.
+        Ok(unsafe { CStr::from_ptr(name) })
+    }
+
+    pub fn instance(&self) -> Result<u32, Error> {
+        let mut instance = 0;
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetDeviceInstance, self.raw, &mut instance) };
+        into_unit(success, maybe_error)?;
+        Ok(instance)
+    }
+}
+
+impl Debug for Device<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Device")
+            .field("name", &self.name().ok())
+            .field("instance", &self.instance().ok())
+            .finish()
+    }
+}
+
+/// A handle to a model loaded on the service.
+///
+/// Dropping the handle does not unload the model; see [`Connection::delete_model()`].
+pub struct Model {
+    raw: *mut larodModel,
+}
+
+// SAFETY: We hold exclusive ownership of the handle and it is not tied to a specific thread.
+unsafe impl Send for Model {}
+// SAFETY: All methods taking `&self` only read from the handle.
+unsafe impl Sync for Model {}
+
+impl Model {
+    pub fn id(&self) -> Result<u64, Error> {
+        let (id, maybe_error) = unsafe { try_func!(larod_sys::larodGetModelId, self.raw) };
+        into_result(id, maybe_error)
+    }
+
+    pub fn name(&self) -> Result<&CStr, Error> {
+        let (name, maybe_error) = unsafe { try_func!(larod_sys::larodGetModelName, self.raw) };
+        if name.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The name is owned by the model handle, which outlives `&self`.
+        Ok(unsafe { CStr::from_ptr(name) })
+    }
+
+    /// Returns the size of the model in bytes.
+    pub fn size(&self) -> Result<usize, Error> {
+        let (size, maybe_error) = unsafe { try_func!(larod_sys::larodGetModelSize, self.raw) };
+        into_result(size, maybe_error)
+    }
+
+    pub fn access(&self) -> Result<Access, Error> {
+        let (access, maybe_error) = unsafe { try_func!(larod_sys::larodGetModelAccess, self.raw) };
+        into_result(access, maybe_error)
+    }
+
+    pub fn num_inputs(&self) -> Result<usize, Error> {
+        let (num, maybe_error) = unsafe { try_func!(larod_sys::larodGetModelNumInputs, self.raw) };
+        into_result(num, maybe_error)
+    }
+
+    pub fn num_outputs(&self) -> Result<usize, Error> {
+        let (num, maybe_error) = unsafe { try_func!(larod_sys::larodGetModelNumOutputs, self.raw) };
+        into_result(num, maybe_error)
+    }
+
+    /// Returns the size in bytes of each input tensor.
+    pub fn input_byte_sizes(&self) -> Result<Vec<usize>, Error> {
+        let mut len = 0;
+        let (raw, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetModelInputByteSizes, self.raw, &mut len) };
+        // SAFETY: The array is allocated by larod, has `len` elements and must be freed by the
+        // caller.
+        unsafe { take_array(raw, len, maybe_error) }
+    }
+
+    /// Returns the size in bytes of each output tensor.
+    pub fn output_byte_sizes(&self) -> Result<Vec<usize>, Error> {
+        let mut len = 0;
+        let (raw, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetModelOutputByteSizes, self.raw, &mut len) };
+        // SAFETY: The array is allocated by larod, has `len` elements and must be freed by the
+        // caller.
+        unsafe { take_array(raw, len, maybe_error) }
+    }
+
+    /// Creates input tensors with metadata matching the model but without backing memory.
+    ///
+    /// Use [`Tensor::set_fd()`] to provide the memory, or use
+    /// [`Connection::alloc_model_inputs()`] to let larod allocate it.
+    pub fn create_inputs(&self) -> Result<Tensors<'static>, Error> {
+        let mut len = 0;
+        let (raw, maybe_error) =
+            unsafe { try_func!(larod_sys::larodCreateModelInputs, self.raw, &mut len) };
+        // SAFETY: The tensors were created without a connection.
+        unsafe { Tensors::from_raw(raw, len, ptr::null_mut(), maybe_error) }
+    }
+
+    /// Creates output tensors with metadata matching the model but without backing memory.
+    ///
+    /// See [`Model::create_inputs()`].
+    pub fn create_outputs(&self) -> Result<Tensors<'static>, Error> {
+        let mut len = 0;
+        let (raw, maybe_error) =
+            unsafe { try_func!(larod_sys::larodCreateModelOutputs, self.raw, &mut len) };
+        // SAFETY: The tensors were created without a connection.
+        unsafe { Tensors::from_raw(raw, len, ptr::null_mut(), maybe_error) }
+    }
+}
+
+impl Drop for Model {
+    fn drop(&mut self) {
+        unsafe { larod_sys::larodDestroyModel(&mut self.raw) }
+    }
+}
+
+impl Debug for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Model")
+            .field("id", &self.id().ok())
+            .field("name", &self.name().ok())
+            .finish()
+    }
+}
+
+/// Copies and frees an array allocated by larod with `malloc`.
+///
+/// # Safety
+///
+/// If `raw` is not null it must point to `len` initialized elements allocated with `malloc`, and
+/// there must be no other users of the array.
+unsafe fn take_array<T: Copy>(
+    raw: *mut T,
+    len: usize,
+    maybe_error: Option<Error>,
+) -> Result<Vec<T>, Error> {
+    if raw.is_null() {
+        return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+    }
+    let values = slice::from_raw_parts(raw, len).to_vec();
+    libc::free(raw as *mut c_void);
+    Ok(values)
+}
+
+/// An owned array of tensors.
+///
+/// Tensors allocated by a [`Connection`] borrow it because they must be destroyed using the same
+/// connection. Individual tensors are accessed by indexing.
+pub struct Tensors<'a> {
+    raw: *mut *mut larodTensor,
+    len: usize,
+    conn: *mut larodConnection,
+    _marker: PhantomData<&'a Connection>,
+}
+
+// SAFETY: We hold exclusive ownership of the tensors and they are not tied to a specific thread.
+unsafe impl Send for Tensors<'_> {}
+// SAFETY: Tensors can only be modified through `&mut self`.
+unsafe impl Sync for Tensors<'_> {}
+
+impl Tensors<'static> {
+    /// Creates `len` tensors without any metadata or backing memory.
+    pub fn new(len: usize) -> Result<Self, Error> {
+        let (raw, maybe_error) = unsafe { try_func!(larod_sys::larodCreateTensors, len) };
+        // SAFETY: The tensors were created without a connection.
+        unsafe { Tensors::from_raw(raw, len, ptr::null_mut(), maybe_error) }
+    }
+}
+
+impl Tensors<'_> {
+    /// # Safety
+    ///
+    /// If `raw` is not null it must point to `len` valid tensors that must be destroyed using
+    /// `conn`, and there must be no other users of them.
+    unsafe fn from_raw<'a>(
+        raw: *mut *mut larodTensor,
+        len: usize,
+        conn: *mut larodConnection,
+        maybe_error: Option<Error>,
+    ) -> Result<Tensors<'a>, Error> {
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        debug_assert!(
+            maybe_error.is_none(),
+            "larod returned a tensor pointer AND an error"
+        );
+        Ok(Tensors {
+            raw,
+            len,
+            conn,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Tensor> {
+        // SAFETY: Every element is a valid tensor pointer that lives as long as `self`.
+        (index < self.len).then(|| unsafe { Tensor::from_ptr(*self.raw.add(index)) })
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Tensor> {
+        // SAFETY: Every element is a valid tensor pointer that lives as long as `self` and the
+        // exclusive borrow of `self` ensures that no other reference to it exists.
+        (index < self.len).then(|| unsafe { Tensor::from_ptr_mut(*self.raw.add(index)) })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tensor> {
+        (0..self.len).map(|i| &self[i])
+    }
+
+    // Returns *mut because the C API takes *mut even for read-only operations.
+    pub(crate) fn as_ptr(&self) -> *mut *mut larodTensor {
+        self.raw
+    }
+}
+
+impl Index<usize> for Tensors<'_> {
+    type Output = Tensor;
+
+    fn index(&self, index: usize) -> &Tensor {
+        self.get(index).expect("tensor index out of bounds")
+    }
+}
+
+impl IndexMut<usize> for Tensors<'_> {
+    fn index_mut(&mut self, index: usize) -> &mut Tensor {
+        self.get_mut(index).expect("tensor index out of bounds")
+    }
+}
+
+impl Debug for Tensors<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Drop for Tensors<'_> {
+    fn drop(&mut self) {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodDestroyTensors,
+                self.conn,
+                &mut self.raw,
+                self.len
+            )
+        };
+        if let Err(e) = into_unit(success, maybe_error) {
+            error!("Failed to destroy tensors: {e}");
+        }
+    }
+}
+
+/// A tensor owned by [`Tensors`].
+///
+/// Describes the shape, data type and layout of the data, and the file descriptor holding it.
+// This is an opaque type that is only ever used behind a reference created from a
+// `*mut larodTensor`.
+#[repr(transparent)]
+pub struct Tensor(larodTensor);
+
+impl Tensor {
+    /// # Safety
+    ///
+    /// `ptr` must be a valid tensor pointer that outlives `'a`.
+    unsafe fn from_ptr<'a>(ptr: *mut larodTensor) -> &'a Self {
+        &*(ptr as *const Self)
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must be a valid tensor pointer that outlives `'a` and that is not aliased.
+    unsafe fn from_ptr_mut<'a>(ptr: *mut larodTensor) -> &'a mut Self {
+        &mut *(ptr as *mut Self)
+    }
+
+    fn as_ptr(&self) -> *const larodTensor {
+        self as *const Self as *const larodTensor
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut larodTensor {
+        self as *mut Self as *mut larodTensor
+    }
+
+    pub fn name(&self) -> Result<&CStr, Error> {
+        let (name, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorName, self.as_ptr()) };
+        if name.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The name is owned by the tensor, which outlives `&self`.
+        Ok(unsafe { CStr::from_ptr(name) })
+    }
+
+    pub fn dims(&self) -> Result<Vec<usize>, Error> {
+        let (dims, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorDims, self.as_ptr()) };
+        if dims.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The dimensions are owned by the tensor, which outlives this function.
+        let dims = unsafe { &*dims };
+        Ok(dims.dims[..dims.len].to_vec())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `dims.len() > TENSOR_MAX_LEN`.
+    pub fn set_dims(&mut self, dims: &[usize]) -> Result<(), Error> {
+        assert!(
+            dims.len() <= TENSOR_MAX_LEN,
+            "Expected at most {TENSOR_MAX_LEN} dimensions, got {}",
+            dims.len()
+        );
+        let mut raw = larod_sys::larodTensorDims {
+            dims: [0; TENSOR_MAX_LEN],
+            len: dims.len(),
+        };
+        raw.dims[..dims.len()].copy_from_slice(dims);
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorDims, self.as_mut_ptr(), &raw) };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn pitches(&self) -> Result<Vec<usize>, Error> {
+        let (pitches, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorPitches, self.as_ptr()) };
+        if pitches.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The pitches are owned by the tensor, which outlives this function.
+        let pitches = unsafe { &*pitches };
+        Ok(pitches.pitches[..pitches.len].to_vec())
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `pitches.len() > TENSOR_MAX_LEN`.
+    pub fn set_pitches(&mut self, pitches: &[usize]) -> Result<(), Error> {
+        assert!(
+            pitches.len() <= TENSOR_MAX_LEN,
+            "Expected at most {TENSOR_MAX_LEN} pitches, got {}",
+            pitches.len()
+        );
+        let mut raw = larod_sys::larodTensorPitches {
+            pitches: [0; TENSOR_MAX_LEN],
+            len: pitches.len(),
+        };
+        raw.pitches[..pitches.len()].copy_from_slice(pitches);
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorPitches, self.as_mut_ptr(), &raw) };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn data_type(&self) -> Result<TensorDataType, Error> {
+        let (data_type, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorDataType, self.as_ptr()) };
+        into_result(data_type, maybe_error)
+    }
+
+    pub fn set_data_type(&mut self, data_type: TensorDataType) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodSetTensorDataType,
+                self.as_mut_ptr(),
+                data_type
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn layout(&self) -> Result<TensorLayout, Error> {
+        let (layout, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorLayout, self.as_ptr()) };
+        into_result(layout, maybe_error)
+    }
+
+    pub fn set_layout(&mut self, layout: TensorLayout) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorLayout, self.as_mut_ptr(), layout) };
+        into_unit(success, maybe_error)
+    }
+
+    /// Returns the size of the tensor data in bytes.
+    pub fn byte_size(&self) -> Result<usize, Error> {
+        let mut size = 0;
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorByteSize, self.as_ptr(), &mut size) };
+        into_unit(success, maybe_error)?;
+        Ok(size)
+    }
+
+    /// Returns the file descriptor holding the tensor data, or `None` if it has not been set.
+    pub fn fd(&self) -> Result<Option<RawFd>, Error> {
+        let (fd, maybe_error) = unsafe { try_func!(larod_sys::larodGetTensorFd, self.as_ptr()) };
+        match maybe_error {
+            Some(e) => Err(e),
+            None if fd == INVALID_FD => Ok(None),
+            None => Ok(Some(fd)),
+        }
+    }
+
+    /// Sets the file descriptor holding the tensor data.
+    ///
+    /// The tensor does not take ownership of `fd`; it must be kept open for as long as jobs
+    /// using this tensor are run.
+    pub fn set_fd(&mut self, fd: BorrowedFd<'_>) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodSetTensorFd,
+                self.as_mut_ptr(),
+                fd.as_raw_fd()
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+
+    /// Resets the file descriptor to the initial, invalid, value.
+    #[cfg(feature = "vdo")]
+    pub(crate) fn unset_fd(&mut self) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorFd, self.as_mut_ptr(), INVALID_FD) };
+        into_unit(success, maybe_error)
+    }
+
+    /// Returns the size of the file descriptor, or 0 if it is not known.
+    pub fn fd_size(&self) -> Result<usize, Error> {
+        let mut size = 0;
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorFdSize, self.as_ptr(), &mut size) };
+        into_unit(success, maybe_error)?;
+        Ok(size)
+    }
+
+    pub fn set_fd_size(&mut self, size: usize) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorFdSize, self.as_mut_ptr(), size) };
+        into_unit(success, maybe_error)
+    }
+
+    /// Returns the offset of the tensor data from the start of the file descriptor.
+    pub fn fd_offset(&self) -> Result<i64, Error> {
+        let (offset, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorFdOffset, self.as_ptr()) };
+        into_result(offset, maybe_error)
+    }
+
+    pub fn set_fd_offset(&mut self, offset: i64) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorFdOffset, self.as_mut_ptr(), offset) };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn fd_props(&self) -> Result<FdProps, Error> {
+        let mut props = 0;
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodGetTensorFdProps, self.as_ptr(), &mut props) };
+        into_unit(success, maybe_error)?;
+        Ok(FdProps(props))
+    }
+
+    pub fn set_fd_props(&mut self, props: FdProps) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetTensorFdProps, self.as_mut_ptr(), props.0) };
+        into_unit(success, maybe_error)
+    }
+
+    /// Maps the tensor data into memory for reading.
+    ///
+    /// Requires that the file descriptor has been set and that it supports [`FdProps::MAP`].
+    /// Outputs of completed jobs are visible through the mapping.
+    pub fn map(&self) -> Result<MappedTensor<'_>, Error> {
+        Ok(MappedTensor {
+            mapping: self.mmap(libc::PROT_READ)?,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Maps the tensor data into memory for reading and writing.
+    ///
+    /// Like [`Tensor::map()`] but writes through the mapping are visible to subsequent jobs.
+    pub fn map_mut(&mut self) -> Result<MappedTensorMut<'_>, Error> {
+        Ok(MappedTensorMut {
+            mapping: self.mmap(libc::PROT_READ | libc::PROT_WRITE)?,
+            _marker: PhantomData,
+        })
+    }
+
+    fn mmap(&self, prot: libc::c_int) -> Result<Mapping, Error> {
+        let fd = self.fd()?.ok_or(Error::MissingFd)?;
+        let size = self.byte_size()?;
+        let offset = self.fd_offset()?;
+        // SAFETY: `sysconf` has no preconditions.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as i64;
+        let aligned_offset = offset - offset % page_size;
+        let delta = (offset - aligned_offset) as usize;
+        // SAFETY: The arguments describe a new shared mapping and the result is checked below.
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size + delta,
+                prot,
+                libc::MAP_SHARED,
+                fd,
+                aligned_offset as libc::off_t,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(Error::Io(std::io::Error::last_os_error()));
+        }
+        Ok(Mapping { base, delta, size })
+    }
+}
+
+impl Debug for Tensor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tensor")
+            .field("name", &self.name().ok())
+            .field("dims", &self.dims().ok())
+            .field("data_type", &self.data_type().ok())
+            .field("layout", &self.layout().ok())
+            .finish()
+    }
+}
+
+struct Mapping {
+    base: *mut c_void,
+    delta: usize,
+    size: usize,
+}
+
+impl Mapping {
+    fn as_ptr(&self) -> *mut u8 {
+        // SAFETY: The mapping covers `delta + size` bytes starting at `base`.
+        unsafe { (self.base as *mut u8).add(self.delta) }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if unsafe { libc::munmap(self.base, self.size + self.delta) } != 0 {
+            error!(
+                "Failed to unmap tensor: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// Read-only memory mapped tensor data, unmapped when dropped.
+///
+/// Created using [`Tensor::map()`].
+pub struct MappedTensor<'a> {
+    mapping: Mapping,
+    _marker: PhantomData<&'a Tensor>,
+}
+
+impl Deref for MappedTensor<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: The mapping is readable and `size` bytes long.
+        unsafe { slice::from_raw_parts(self.mapping.as_ptr(), self.mapping.size) }
+    }
+}
+
+/// Writable memory mapped tensor data, unmapped when dropped.
+///
+/// Created using [`Tensor::map_mut()`].
+pub struct MappedTensorMut<'a> {
+    mapping: Mapping,
+    _marker: PhantomData<&'a mut Tensor>,
+}
+
+impl Deref for MappedTensorMut<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: The mapping is readable and `size` bytes long.
+        unsafe { slice::from_raw_parts(self.mapping.as_ptr(), self.mapping.size) }
+    }
+}
+
+impl DerefMut for MappedTensorMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The mapping is writable and `size` bytes long, and the exclusive borrow of both
+        // `self` and the tensor ensures that no other reference to it exists.
+        unsafe { slice::from_raw_parts_mut(self.mapping.as_ptr(), self.mapping.size) }
+    }
+}
+
+/// A request to run a model on a set of input tensors, writing the result to a set of output
+/// tensors.
+///
+/// The request borrows the model and the tensors because larod reads them each time the job is
+/// run. A request can be run any number of times. To change the inputs between runs, e.g. to bind
+/// them to new frames, let the request own them using [`JobRequest::with_owned_inputs()`].
+pub struct JobRequest<'a> {
+    raw: *mut larodJobRequest,
+    // Dropped after `Drop::drop` has destroyed the request that refers to them.
+    owned_inputs: Option<Tensors<'a>>,
+    _marker: PhantomData<(&'a Model, &'a Tensors<'a>)>,
+}
+
+// SAFETY: We hold exclusive ownership of the raw pointer and it is not tied to a specific thread.
+unsafe impl Send for JobRequest<'_> {}
+// SAFETY: The request can only be modified through `&mut self`.
+unsafe impl Sync for JobRequest<'_> {}
+
+impl<'a> JobRequest<'a> {
+    pub fn new(
+        model: &'a Model,
+        inputs: &'a Tensors<'_>,
+        outputs: &'a Tensors<'_>,
+        params: Option<&Map>,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            raw: Self::create(model, inputs, outputs, params)?,
+            owned_inputs: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Like [`JobRequest::new()`] but takes ownership of `inputs`.
+    ///
+    /// The inputs can then be modified between runs using [`JobRequest::input_mut()`].
+    pub fn with_owned_inputs(
+        model: &'a Model,
+        inputs: Tensors<'a>,
+        outputs: &'a Tensors<'_>,
+        params: Option<&Map>,
+    ) -> Result<Self, Error> {
+        // The tensors stay at the same address when `inputs` is moved since they are allocated
+        // by larod.
+        Ok(Self {
+            raw: Self::create(model, &inputs, outputs, params)?,
+            owned_inputs: Some(inputs),
+            _marker: PhantomData,
+        })
+    }
+
+    fn create(
+        model: &Model,
+        inputs: &Tensors<'_>,
+        outputs: &Tensors<'_>,
+        params: Option<&Map>,
+    ) -> Result<*mut larodJobRequest, Error> {
+        // `larodCreateJobRequest` copies the arrays of tensor pointers.
+        let (raw, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodCreateJobRequest,
+                model.raw,
+                inputs.as_ptr(),
+                inputs.len(),
+                outputs.as_ptr(),
+                outputs.len(),
+                params.map_or(ptr::null_mut(), Map::as_ptr),
+            )
+        };
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        Ok(raw)
+    }
+
+    /// Returns the input at `index` if the inputs are owned by the request.
+    pub fn input_mut(&mut self, index: usize) -> Option<&mut Tensor> {
+        self.owned_inputs.as_mut()?.get_mut(index)
+    }
+
+    pub fn set_model(&mut self, model: &'a Model) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetJobRequestModel, self.raw, model.raw) };
+        into_unit(success, maybe_error)
+    }
+
+    /// Replaces the inputs, dropping any inputs owned by the request.
+    pub fn set_inputs(&mut self, inputs: &'a Tensors<'_>) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodSetJobRequestInputs,
+                self.raw,
+                inputs.as_ptr(),
+                inputs.len()
+            )
+        };
+        into_unit(success, maybe_error)?;
+        self.owned_inputs = None;
+        Ok(())
+    }
+
+    pub fn set_outputs(&mut self, outputs: &'a Tensors<'_>) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodSetJobRequestOutputs,
+                self.raw,
+                outputs.as_ptr(),
+                outputs.len()
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+
+    /// Higher values are scheduled first. Default: 50, valid range: 0 to 100.
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodSetJobRequestPriority, self.raw, priority) };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn set_params(&mut self, params: &Map) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodSetJobRequestParams,
+                self.raw,
+                params.as_ptr()
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+}
+
+impl Drop for JobRequest<'_> {
+    fn drop(&mut self) {
+        unsafe { larod_sys::larodDestroyJobRequest(&mut self.raw) }
+    }
+}
+
+impl Debug for JobRequest<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobRequest")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    fn larod_error(code: ErrorCode) -> LarodError {
+        LarodError {
+            code,
+            message: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn error_code_names() {
+        expect!["LAROD_ERROR_LOAD_MODEL"]
+            .assert_eq(larod_error(ErrorCode::LAROD_ERROR_LOAD_MODEL).code_name());
+        expect!["LAROD_ERROR_POWER_NOT_AVAILABLE"]
+            .assert_eq(larod_error(ErrorCode::LAROD_ERROR_POWER_NOT_AVAILABLE).code_name());
+        expect!["LAROD_ERROR_ERRNO"].assert_eq(larod_error(ErrorCode(libc::ENOENT)).code_name());
+        expect!["LAROD_ERROR_UNKNOWN"].assert_eq(larod_error(ErrorCode(-9999)).code_name());
+        expect!["LAROD_ERROR_UNKNOWN"].assert_eq(larod_error(ErrorCode(9999)).code_name());
+    }
+
+    #[test]
+    fn error_display() {
+        expect!["LAROD_ERROR_TENSOR_MISMATCH (-12): test"].assert_eq(&format!(
+            "{}",
+            Error::from(larod_error(ErrorCode::LAROD_ERROR_TENSOR_MISMATCH))
+        ));
+        expect!["larod returned an unexpected null pointer"]
+            .assert_eq(&format!("{}", Error::NullPointer));
+        expect!["Missing error data from larod library"]
+            .assert_eq(&format!("{}", Error::MissingLarodError));
+    }
+
+    #[test]
+    fn fd_props_combine() {
+        assert_eq!(FdProps::MAP | FdProps::DMABUF, FdProps(0b110));
+        assert_eq!(FdProps::default(), FdProps(0));
+    }
+
+    #[test]
+    fn error_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Error>();
+    }
+}
+
+// These tests require the larod service and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init_logger() {
+        let _ = env_logger::builder().is_test(true).try_init();
+    }
+
+    #[test]
+    fn connection_reports_sessions() -> Result<(), Error> {
+        init_logger();
+        let connection = Connection::new()?;
+        assert!(connection.num_sessions()? >= 1);
+        Ok(())
+    }
+
+    #[test]
+    fn devices_are_listed() -> Result<(), Error> {
+        init_logger();
+        let connection = Connection::new()?;
+        let devices = connection.devices()?;
+        assert!(!devices.is_empty());
+        for device in devices {
+            log::info!("{:?} {}", device.name()?, device.instance()?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_device_returns_error() {
+        init_logger();
+        let connection = Connection::new().unwrap();
+        let err = connection
+            .device(c"no-such-device", 0)
+            .expect_err("Device should not exist");
+        log::info!("Expected error for unknown device: {err}");
+    }
+
+    #[test]
+    fn map_get_set_operations() -> Result<(), Error> {
+        init_logger();
+        let mut map = Map::new()?;
+        map.set_string(c"str", c"hello")?;
+        map.set_int(c"int", 42)?;
+        map.set_int_arr2(c"arr2", [1, 2])?;
+        map.set_int_arr4(c"arr4", [1, 2, 3, 4])?;
+        assert_eq!(map.get_string(c"str")?, c"hello");
+        assert_eq!(map.get_int(c"int")?, 42);
+        assert_eq!(map.get_int_arr2(c"arr2")?, [1, 2]);
+        assert_eq!(map.get_int_arr4(c"arr4")?, [1, 2, 3, 4]);
+        assert!(map.get_int(c"missing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn tensor_metadata_round_trips() -> Result<(), Error> {
+        init_logger();
+        let mut tensors = Tensors::new(1)?;
+        let tensor = &mut tensors[0];
+        tensor.set_dims(&[1, 300, 300, 3])?;
+        tensor.set_data_type(TensorDataType::LAROD_TENSOR_DATA_TYPE_UINT8)?;
+        tensor.set_layout(TensorLayout::LAROD_TENSOR_LAYOUT_NHWC)?;
+        assert_eq!(tensor.dims()?, vec![1, 300, 300, 3]);
+        assert_eq!(
+            tensor.data_type()?,
+            TensorDataType::LAROD_TENSOR_DATA_TYPE_UINT8
+        );
+        assert_eq!(tensor.layout()?, TensorLayout::LAROD_TENSOR_LAYOUT_NHWC);
+        assert_eq!(tensor.fd()?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Key-value map for larod parameters.
+
+use std::{ffi::CStr, fmt, ptr};
+
+use larod_sys::larodMap;
+
+use crate::{into_unit, Error};
+
+/// A key-value map of parameters for models, tensor allocation and job requests.
+///
+/// Which keys are recognized depends on the device; see the larod documentation for details.
+/// All methods assume `self.raw` is a valid `larodMap` pointer, which is guaranteed by the
+/// constructor.
+pub struct Map {
+    raw: *mut larodMap,
+}
+
+impl Map {
+    pub fn new() -> Result<Self, Error> {
+        let (raw, maybe_error) = unsafe { try_func!(larod_sys::larodCreateMap) };
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        debug_assert!(
+            maybe_error.is_none(),
+            "larodCreateMap returned a map pointer AND an error"
+        );
+        Ok(Self { raw })
+    }
+
+    pub fn set_string(&mut self, key: &CStr, value: &CStr) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodMapSetStr,
+                self.raw,
+                key.as_ptr(),
+                value.as_ptr()
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn set_int(&mut self, key: &CStr, value: i64) -> Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(larod_sys::larodMapSetInt, self.raw, key.as_ptr(), value) };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn set_int_arr2(&mut self, key: &CStr, value: [i64; 2]) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodMapSetIntArr2,
+                self.raw,
+                key.as_ptr(),
+                value[0],
+                value[1]
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+
+    pub fn set_int_arr4(&mut self, key: &CStr, value: [i64; 4]) -> Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodMapSetIntArr4,
+                self.raw,
+                key.as_ptr(),
+                value[0],
+                value[1],
+                value[2],
+                value[3]
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+
+    /// Returns the string associated with `key`.
+    ///
+    /// The returned string borrows from the map and is invalidated when the key is updated.
+    pub fn get_string(&self, key: &CStr) -> Result<&CStr, Error> {
+        let (value, maybe_error) =
+            unsafe { try_func!(larod_sys::larodMapGetStr, self.raw, key.as_ptr()) };
+        if value.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: The string is owned by the map and lives at least as long as `&self` because
+        // it can only be replaced through `&mut self`.
+        Ok(unsafe { CStr::from_ptr(value) })
+    }
+
+    pub fn get_int(&self, key: &CStr) -> Result<i64, Error> {
+        let mut value = 0;
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodMapGetInt,
+                self.raw,
+                key.as_ptr(),
+                &mut value
+            )
+        };
+        into_unit(success, maybe_error)?;
+        Ok(value)
+    }
+
+    pub fn get_int_arr2(&self, key: &CStr) -> Result<[i64; 2], Error> {
+        let (value, maybe_error) =
+            unsafe { try_func!(larod_sys::larodMapGetIntArr2, self.raw, key.as_ptr()) };
+        if value.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: A non-null return value points to an array of two elements owned by the map.
+        Ok(unsafe { ptr::read(value as *const [i64; 2]) })
+    }
+
+    pub fn get_int_arr4(&self, key: &CStr) -> Result<[i64; 4], Error> {
+        let (value, maybe_error) =
+            unsafe { try_func!(larod_sys::larodMapGetIntArr4, self.raw, key.as_ptr()) };
+        if value.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingLarodError));
+        }
+        // SAFETY: A non-null return value points to an array of four elements owned by the map.
+        Ok(unsafe { ptr::read(value as *const [i64; 4]) })
+    }
+
+    // Returns *mut because the C API takes *mut even for read-only operations.
+    pub(crate) fn as_ptr(&self) -> *mut larodMap {
+        self.raw
+    }
+}
+
+impl fmt::Debug for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Map").field("raw", &self.raw).finish()
+    }
+}
+
+// SAFETY: We hold exclusive ownership of the raw pointer and `larodMap` is a plain data structure
+// that does not require access from a specific thread.
+unsafe impl Send for Map {}
+
+impl Drop for Map {
+    fn drop(&mut self) {
+        unsafe { larod_sys::larodDestroyMap(&mut self.raw) }
+    }
+}
@@ -0,0 +1,405 @@
+//! Async wrapper around larod
+//!
+//! The futures do not depend on a specific runtime; the callbacks from larod wake the task that
+//! is awaiting them.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::{fs::File, os::fd::AsFd, sync::Arc};
+//!
+//! use larod::{nonblock::OwnedJobRequest, Access, Connection, FdProps};
+//!
+//! futures_lite::future::block_on(async {
+//!     let connection = Arc::new(Connection::new()?);
+//!     let device = connection.device(c"cpu-tflite", 0)?;
+//!     let file = File::open("model.tflite")?;
+//!     let model = connection
+//!         .load_model_async(
+//!             Some(file.as_fd()),
+//!             &device,
+//!             Access::LAROD_ACCESS_PRIVATE,
+//!             c"model",
+//!             None,
+//!         )?
+//!         .await?;
+//!     let mut job = OwnedJobRequest::alloc(&connection, Arc::new(model), FdProps::MAP, None)?;
+//!     job.input_mut(0).expect("model has an input").map_mut()?.fill(0);
+//!     let (job, result) = connection.run_job_async(job).await;
+//!     result?;
+//!     println!("Output: {:?}", &job.outputs()[0].map()?[..4]);
+//!     Ok::<(), larod::Error>(())
+//! })?;
+//! # Ok::<(), larod::Error>(())
+//! ```
+use std::{
+    ffi::{c_void, CStr},
+    fmt::Debug,
+    future::Future,
+    marker::PhantomData,
+    mem,
+    os::fd::{AsRawFd, BorrowedFd},
+    pin::Pin,
+    ptr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use async_channel::{Receiver, Sender};
+use futures_lite::Stream;
+use log::{error, warn};
+
+use crate::{
+    into_unit, Access, Connection, Device, Error, FdProps, JobRequest, LarodError, Map, Model,
+    Tensor, Tensors, INVALID_FD,
+};
+
+impl Connection {
+    /// Starts loading a model without blocking.
+    ///
+    /// Like [`Connection::load_model()`] but returns a future that resolves when the model has
+    /// been loaded. The file descriptor is only used during this call.
+    pub fn load_model_async(
+        &self,
+        fd: Option<BorrowedFd<'_>>,
+        device: &Device<'_>,
+        access: Access,
+        name: &CStr,
+        params: Option<&Map>,
+    ) -> Result<LoadModel, Error> {
+        let (tx, rx) = async_channel::bounded::<Result<Model, Error>>(1);
+        let user_data = Box::into_raw(Box::new(tx));
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodLoadModelAsync,
+                self.raw,
+                fd.map_or(INVALID_FD, |fd| fd.as_raw_fd()),
+                device.raw,
+                access,
+                name.as_ptr(),
+                params.map_or(ptr::null(), |p| p.as_ptr() as *const _),
+                Some(LoadModel::handle_callback),
+                user_data as *mut c_void,
+            )
+        };
+        if let Err(e) = into_unit(success, maybe_error) {
+            // SAFETY: The callback will not be called when the function fails, so we are the
+            // only user of the sender.
+            drop(unsafe { Box::from_raw(user_data) });
+            return Err(e);
+        }
+        Ok(LoadModel { rx: Box::pin(rx) })
+    }
+
+    /// Starts running `job` without blocking.
+    ///
+    /// Like [`Connection::run_job()`] but returns a future that resolves when the job has
+    /// completed. larod uses the request, its model and its tensors until then, so they are moved
+    /// into the job and handed back by the future together with the result.
+    ///
+    /// Dropping the future does not cancel the job. The request, and the last reference to this
+    /// connection if all others have been dropped, are then dropped when the job completes.
+    pub fn run_job_async(self: &Arc<Self>, job: OwnedJobRequest) -> RunJob {
+        let (tx, rx) = async_channel::bounded(1);
+        let raw = job.job.raw;
+        let user_data = Box::into_raw(Box::new(RunningJob {
+            tx,
+            job,
+            _connection: Arc::clone(self),
+        }));
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                larod_sys::larodRunJobAsync,
+                self.raw,
+                raw,
+                Some(RunJob::handle_callback),
+                user_data as *mut c_void,
+            )
+        };
+        if let Err(e) = into_unit(success, maybe_error) {
+            // SAFETY: The callback will not be called when the function fails, so we are the
+            // only user of the data.
+            unsafe { Box::from_raw(user_data) }.complete(Err(e));
+        }
+        RunJob { rx: Box::pin(rx) }
+    }
+}
+
+/// A job request that owns its model and tensors.
+///
+/// Unlike [`JobRequest`] this does not borrow anything, which lets [`Connection::run_job_async()`]
+/// keep everything that larod uses alive until the job has completed.
+pub struct OwnedJobRequest {
+    // Fields are dropped in declaration order, so the request is destroyed before the tensors
+    // and the model, and the tensors before the connection that they were allocated on.
+    job: JobRequest<'static>,
+    inputs: Tensors<'static>,
+    outputs: Tensors<'static>,
+    model: Arc<Model>,
+    _connection: Option<Arc<Connection>>,
+}
+
+impl OwnedJobRequest {
+    /// Creates a request for `model` with tensors that are not tied to a connection, e.g. those
+    /// created using [`Model::create_inputs()`].
+    pub fn new(
+        model: Arc<Model>,
+        inputs: Tensors<'static>,
+        outputs: Tensors<'static>,
+        params: Option<&Map>,
+    ) -> Result<Self, Error> {
+        Self::create(model, inputs, outputs, params, None)
+    }
+
+    /// Creates a request for `model` with inputs and outputs allocated on `connection`.
+    ///
+    /// See [`Connection::alloc_model_inputs()`] for the meaning of `fd_props`.
+    pub fn alloc(
+        connection: &Arc<Connection>,
+        model: Arc<Model>,
+        fd_props: FdProps,
+        params: Option<&Map>,
+    ) -> Result<Self, Error> {
+        let inputs = connection.alloc_model_inputs(&model, fd_props, None)?;
+        let outputs = connection.alloc_model_outputs(&model, fd_props, None)?;
+        // SAFETY: The tensors borrow the connection, which the request keeps alive until after
+        // they have been dropped.
+        let (inputs, outputs) = unsafe {
+            (
+                mem::transmute::<Tensors<'_>, Tensors<'static>>(inputs),
+                mem::transmute::<Tensors<'_>, Tensors<'static>>(outputs),
+            )
+        };
+        Self::create(model, inputs, outputs, params, Some(Arc::clone(connection)))
+    }
+
+    fn create(
+        model: Arc<Model>,
+        inputs: Tensors<'static>,
+        outputs: Tensors<'static>,
+        params: Option<&Map>,
+        connection: Option<Arc<Connection>>,
+    ) -> Result<Self, Error> {
+        // The tensors and the model stay at the same address when moved since they are
+        // allocated by larod.
+        let job = JobRequest {
+            raw: JobRequest::create(&model, &inputs, &outputs, params)?,
+            owned_inputs: None,
+            _marker: PhantomData,
+        };
+        Ok(Self {
+            job,
+            inputs,
+            outputs,
+            model,
+            _connection: connection,
+        })
+    }
+
+    pub fn model(&self) -> &Arc<Model> {
+        &self.model
+    }
+
+    pub fn inputs(&self) -> &Tensors<'static> {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &Tensors<'static> {
+        &self.outputs
+    }
+
+    pub fn input_mut(&mut self, index: usize) -> Option<&mut Tensor> {
+        self.inputs.get_mut(index)
+    }
+
+    pub fn output_mut(&mut self, index: usize) -> Option<&mut Tensor> {
+        self.outputs.get_mut(index)
+    }
+
+    /// Higher values are scheduled first. Default: 50, valid range: 0 to 100.
+    pub fn set_priority(&mut self, priority: u8) -> Result<(), Error> {
+        self.job.set_priority(priority)
+    }
+
+    pub fn set_params(&mut self, params: &Map) -> Result<(), Error> {
+        self.job.set_params(params)
+    }
+}
+
+impl Debug for OwnedJobRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OwnedJobRequest")
+            .field("job", &self.job)
+            .field("inputs", &self.inputs)
+            .field("outputs", &self.outputs)
+            .finish()
+    }
+}
+
+/// A request that has been run, together with the result of running it.
+type Completed = (OwnedJobRequest, Result<(), Error>);
+
+/// Everything that larod uses while running a job, passed to the callback as user data.
+struct RunningJob {
+    tx: Sender<Completed>,
+    job: OwnedJobRequest,
+    _connection: Arc<Connection>,
+}
+
+impl RunningJob {
+    fn complete(self, result: Result<(), Error>) {
+        if self.tx.try_send((self.job, result)).is_err() {
+            warn!("Result of larod job was dropped because nobody is waiting for it.");
+        }
+    }
+}
+
+/// Sends `value` on the sender behind `user_data` and drops the sender.
+///
+/// # Safety
+///
+/// `user_data` must have been created from a `Box<Sender<T>>` and must not be used again.
+unsafe fn complete<T>(user_data: *mut c_void, value: T) {
+    let tx = Box::from_raw(user_data as *mut Sender<T>);
+    if tx.try_send(value).is_err() {
+        warn!("Result of larod operation was dropped because nobody is waiting for it.");
+    }
+}
+
+/// Copies an error that is owned, and freed, by larod.
+///
+/// # Safety
+///
+/// `error` must be null or point to a valid `larodError`.
+unsafe fn copy_error(error: *const larod_sys::larodError) -> Option<Error> {
+    if error.is_null() {
+        None
+    } else {
+        Some(Error::Larod(LarodError::copy_from(error)))
+    }
+}
+
+macro_rules! abort_unwind {
+    ($f:expr) => {
+        std::panic::catch_unwind($f).unwrap_or_else(|_| {
+            error!("Caught panic in larod callback");
+            std::process::abort();
+        })
+    };
+}
+
+/// A future that resolves to a [`Model`] once it has been loaded.
+///
+/// Created using [`Connection::load_model_async()`]. Dropping it does not cancel the loading;
+/// the model handle is then dropped when it becomes available.
+pub struct LoadModel {
+    rx: Pin<Box<Receiver<Result<Model, Error>>>>,
+}
+
+impl LoadModel {
+    unsafe extern "C" fn handle_callback(
+        model: *mut larod_sys::larodModel,
+        user_data: *mut c_void,
+        error: *mut larod_sys::larodError,
+    ) {
+        abort_unwind!(|| {
+            // SAFETY: The error is owned by larod and only valid until the callback returns.
+            let result = match copy_error(error) {
+                Some(e) => {
+                    if !model.is_null() {
+                        let mut model = model;
+                        larod_sys::larodDestroyModel(&mut model);
+                    }
+                    Err(e)
+                }
+                None if model.is_null() => Err(Error::NullPointer),
+                // SAFETY: Ownership of the model handle is transferred to the callback.
+                None => Ok(Model { raw: model }),
+            };
+            complete(user_data, result);
+        });
+    }
+}
+
+impl Future for LoadModel {
+    type Output = Result<Model, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.rx.as_mut().poll_next(cx) {
+            Poll::Ready(Some(result)) => Poll::Ready(result),
+            // The callback always sends a value before dropping the sender.
+            Poll::Ready(None) => Poll::Ready(Err(Error::MissingLarodError)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A future that resolves once a job has completed.
+///
+/// Created using [`Connection::run_job_async()`]. Resolves to the request that was run and the
+/// result of running it. Dropping it does not cancel the job.
+pub struct RunJob {
+    rx: Pin<Box<Receiver<Completed>>>,
+}
+
+impl RunJob {
+    unsafe extern "C" fn handle_callback(
+        user_data: *mut c_void,
+        error: *mut larod_sys::larodError,
+    ) {
+        abort_unwind!(|| {
+            // SAFETY: The error is owned by larod and only valid until the callback returns.
+            let result = match copy_error(error) {
+                Some(e) => Err(e),
+                None => Ok(()),
+            };
+            // SAFETY: The data was created by `run_job_async` and the callback is called once.
+            Box::from_raw(user_data as *mut RunningJob).complete(result);
+        });
+    }
+}
+
+impl Future for RunJob {
+    type Output = Completed;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.rx.as_mut().poll_next(cx) {
+            Poll::Ready(Some(output)) => Poll::Ready(output),
+            Poll::Ready(None) => {
+                unreachable!("the request is always sent back before the sender is dropped")
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// These tests require the larod service and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, os::fd::AsFd};
+
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    #[test]
+    fn invalid_model_fails_asynchronously() -> Result<(), Error> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let connection = Connection::new()?;
+        let device = connection.device(c"cpu-tflite", 0)?;
+        let file = File::open("/dev/null")?;
+        let result = connection
+            .load_model_async(
+                Some(file.as_fd()),
+                &device,
+                Access::LAROD_ACCESS_PRIVATE,
+                c"invalid",
+                None,
+            )
+            .and_then(block_on);
+        let err = result.expect_err("An empty file is not a valid model");
+        log::info!("Expected error for invalid model: {err}");
+        Ok(())
+    }
+}
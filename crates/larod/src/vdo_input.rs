@@ -0,0 +1,111 @@
+//! Zero-copy input from VDO.
+//!
+//! Frames captured with the `vdo` crate are backed by file descriptors that larod can read
+//! directly. Binding them to input tensors avoids copying every frame through the CPU.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use larod::{Connection, FdProps, JobRequest, Model};
+//!
+//! fn infer(
+//!     connection: &Connection,
+//!     model: &Model,
+//!     stream: &vdo::RunningStream,
+//! ) -> anyhow::Result<()> {
+//!     let outputs = connection.alloc_model_outputs(model, FdProps::MAP, None)?;
+//!     let mut job = JobRequest::with_owned_inputs(model, model.create_inputs()?, &outputs, None)?;
+//!     loop {
+//!         let buffer = stream.next_buffer()?;
+//!         let job = job.bind_vdo_buffer(0, &buffer, FdProps::DMABUF)?;
+//!         connection.run_job(&job)?;
+//!     }
+//! }
+//! ```
+
+use std::{marker::PhantomData, ops::Deref};
+
+use log::error;
+
+use crate::{Error, FdProps, JobRequest, Tensor};
+
+impl<'a> JobRequest<'a> {
+    /// Uses the frame in `buffer` as the data of input `index` without copying it.
+    ///
+    /// Sets the file descriptor and offset of the input to those of the buffer and its
+    /// properties to `props`. The descriptor of a VDO buffer is a [dma-buf](FdProps::DMABUF);
+    /// add [`FdProps::MAP`] for devices that read the frame through a mapping, such as
+    /// `cpu-proc`.
+    ///
+    /// The returned guard borrows both the request and `buffer`, so the job can only be run
+    /// using the guard and the buffer cannot be returned to VDO while the frame is bound. The
+    /// descriptor is unset when the guard is dropped.
+    ///
+    /// Requires that the request owns its inputs, see [`JobRequest::with_owned_inputs()`], and
+    /// fails with [`Error::MissingInput`] if it does not own an input at `index`.
+    ///
+    /// On devices where VDO hands out vmem descriptors, such as ARTPEC-7, convert the
+    /// descriptor using [`convert_vmem_fd_to_dmabuf()`](crate::convert_vmem_fd_to_dmabuf) and
+    /// bind it using [`JobRequest::input_mut()`] instead.
+    pub fn bind_vdo_buffer<'j, 'b>(
+        &'j mut self,
+        index: usize,
+        buffer: &'b vdo::StreamBuffer<'_>,
+        props: FdProps,
+    ) -> Result<VdoJobRequest<'j, 'a, 'b>, Error> {
+        let fd = buffer.fd().ok_or(Error::MissingFd)?;
+        let tensor = self.input_mut(index).ok_or(Error::MissingInput(index))?;
+        tensor.set_fd(fd)?;
+        // Without a guard nothing else unsets the descriptor, which the buffer outlives.
+        if let Err(e) = describe_frame(tensor, buffer, props) {
+            if let Err(e) = tensor.unset_fd() {
+                error!("Failed to unset the file descriptor of a VDO frame: {e}");
+            }
+            return Err(e);
+        }
+        Ok(VdoJobRequest {
+            job: self,
+            index,
+            _buffer: PhantomData,
+        })
+    }
+}
+
+fn describe_frame(
+    tensor: &mut Tensor,
+    buffer: &vdo::StreamBuffer<'_>,
+    props: FdProps,
+) -> Result<(), Error> {
+    tensor.set_fd_offset(buffer.offset())?;
+    // Let larod determine the size since the descriptor may hold more than this frame.
+    tensor.set_fd_size(0)?;
+    tensor.set_fd_props(props)
+}
+
+/// A [`JobRequest`] with a VDO frame bound to one of its inputs.
+///
+/// Created using [`JobRequest::bind_vdo_buffer()`].
+pub struct VdoJobRequest<'j, 'a, 'b> {
+    job: &'j mut JobRequest<'a>,
+    index: usize,
+    _buffer: PhantomData<&'b vdo::StreamBuffer<'b>>,
+}
+
+impl<'a> Deref for VdoJobRequest<'_, 'a, '_> {
+    type Target = JobRequest<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.job
+    }
+}
+
+impl Drop for VdoJobRequest<'_, '_, '_> {
+    fn drop(&mut self) {
+        // The guard was created from an owned input at `index`, which it keeps borrowed.
+        if let Some(tensor) = self.job.input_mut(self.index) {
+            if let Err(e) = tensor.unset_fd() {
+                error!("Failed to unset the file descriptor of a VDO frame: {e}");
+            }
+        }
+    }
+}
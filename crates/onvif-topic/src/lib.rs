@@ -0,0 +1,410 @@
+//! ONVIF topic expressions.
+//!
+//! The event system on the device and the event APIs of VAPIX name topics differently. The former
+//! uses one key per level, `topic0`, `topic1` and so on, with the namespace of each level stored
+//! next to it. The latter uses strings like `tns1:Device/tnsaxis:IO/VirtualInput`. [`Topic`]
+//! represents both so that the same expression can be used to subscribe to local and remote
+//! events.
+//!
+//! The string form supports a subset of the ONVIF topic expression dialect:
+//! - Levels are separated by `/` and prefixed by their namespace, e.g. `tns1:Device`. Levels
+//!   without a prefix belong to the same namespace as the level before them.
+//! - `*` matches any one level.
+//! - A trailing `//.` matches the topic and all topics below it.
+//! - `|` separates alternatives.
+//!
+//! # Example
+//!
+//! ```
+//! use onvif_topic::Topic;
+//!
+//! let filter: Topic = "tns1:Device/tnsaxis:IO/*|tns1:Device/Trigger//.".parse()?;
+//! let topic: Topic = "tns1:Device/tnsaxis:IO/VirtualInput".parse()?;
+//! let concrete = topic.as_concrete().expect("topic has no wildcards or alternatives");
+//! assert!(filter.matches(concrete));
+//! assert_eq!(concrete.levels()[2].name(), Some(("tnsaxis", "VirtualInput")));
+//! # Ok::<(), onvif_topic::ParseTopicError>(())
+//! ```
+use std::{fmt, fmt::Display, str::FromStr};
+
+/// Error returned when a topic expression or one of its parts is not valid.
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+#[error("invalid topic expression {expression:?}: {reason}")]
+pub struct ParseTopicError {
+    expression: String,
+    reason: String,
+}
+
+impl ParseTopicError {
+    fn new(expression: &str, reason: impl Display) -> Self {
+        Self {
+            expression: expression.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// One level of a topic.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Level {
+    /// A level with a name in a namespace, such as `tns1:Device`.
+    Named(Name),
+    /// A level that matches any name, written as `*`.
+    Any,
+}
+
+impl Level {
+    /// Creates a named level.
+    pub fn named(namespace: &str, name: &str) -> Result<Self, ParseTopicError> {
+        Ok(Self::Named(Name::new(namespace, name)?))
+    }
+
+    /// Returns the namespace and the name of the level, or `None` if it is a wildcard.
+    pub fn name(&self) -> Option<(&str, &str)> {
+        match self {
+            Self::Named(name) => Some((name.namespace(), name.name())),
+            Self::Any => None,
+        }
+    }
+}
+
+/// The name of a level together with its namespace.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Name {
+    namespace: String,
+    name: String,
+}
+
+impl Name {
+    /// Creates a name after validating both parts.
+    pub fn new(namespace: &str, name: &str) -> Result<Self, ParseTopicError> {
+        let expression = format!("{namespace}:{name}");
+        if !is_name(namespace) {
+            return Err(ParseTopicError::new(
+                &expression,
+                format!("{namespace:?} is not a valid namespace prefix"),
+            ));
+        }
+        if !is_name(name) {
+            return Err(ParseTopicError::new(
+                &expression,
+                format!("{name:?} is not a valid name"),
+            ));
+        }
+        Ok(Self {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// A single path of levels, optionally including all topics below it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TopicPath {
+    levels: Vec<Level>,
+    descendants: bool,
+}
+
+impl TopicPath {
+    /// Creates a path from its levels.
+    ///
+    /// Returns an error if there are no levels.
+    pub fn new(levels: impl IntoIterator<Item = Level>) -> Result<Self, ParseTopicError> {
+        let levels: Vec<_> = levels.into_iter().collect();
+        if levels.is_empty() {
+            return Err(ParseTopicError::new(
+                "",
+                "a topic must have at least one level",
+            ));
+        }
+        Ok(Self {
+            levels,
+            descendants: false,
+        })
+    }
+
+    /// Makes the path match also all topics below it, like a trailing `//.`.
+    pub fn with_descendants(mut self) -> Self {
+        self.descendants = true;
+        self
+    }
+
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    /// Returns `true` if the path matches also all topics below it.
+    pub fn includes_descendants(&self) -> bool {
+        self.descendants
+    }
+
+    /// Returns `true` if the path has neither wildcard levels nor includes descendants.
+    pub fn is_concrete(&self) -> bool {
+        !self.descendants && self.levels.iter().all(|l| matches!(l, Level::Named(_)))
+    }
+
+    /// Returns `true` if `topic` is matched by this path.
+    ///
+    /// Wildcards in `topic` only match wildcards in this path.
+    pub fn matches(&self, topic: &TopicPath) -> bool {
+        if topic.levels.len() < self.levels.len()
+            || (topic.levels.len() > self.levels.len() && !self.descendants)
+        {
+            return false;
+        }
+        self.levels
+            .iter()
+            .zip(&topic.levels)
+            .all(|(pattern, level)| matches!(pattern, Level::Any) || pattern == level)
+    }
+
+    fn parse(expression: &str, path: &str) -> Result<Self, ParseTopicError> {
+        let (path, descendants) = match path.strip_suffix("//.") {
+            Some(path) => (path, true),
+            None => (path, false),
+        };
+        let mut namespace: Option<&str> = None;
+        let mut levels = Vec::new();
+        for level in path.split('/') {
+            if level == "*" {
+                levels.push(Level::Any);
+                continue;
+            }
+            let name = match level.split_once(':') {
+                Some((prefix, name)) => {
+                    namespace = Some(prefix);
+                    name
+                }
+                None => level,
+            };
+            let Some(namespace) = namespace else {
+                return Err(ParseTopicError::new(
+                    expression,
+                    format!("{level:?} must have a namespace prefix, e.g. `tnsaxis:{level}`"),
+                ));
+            };
+            levels.push(Level::Named(
+                Name::new(namespace, name)
+                    .map_err(|e| ParseTopicError::new(expression, e.reason))?,
+            ));
+        }
+        Ok(Self {
+            levels,
+            descendants,
+        })
+    }
+}
+
+impl Display for TopicPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut namespace: Option<&str> = None;
+        for (i, level) in self.levels.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            match level {
+                Level::Named(name) if namespace == Some(name.namespace()) => {
+                    f.write_str(name.name())?
+                }
+                Level::Named(name) => {
+                    namespace = Some(name.namespace());
+                    write!(f, "{}:{}", name.namespace(), name.name())?
+                }
+                Level::Any => f.write_str("*")?,
+            }
+        }
+        if self.descendants {
+            f.write_str("//.")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for TopicPath {
+    type Err = ParseTopicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s, s)
+    }
+}
+
+/// A topic expression consisting of one or more alternative paths.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Topic {
+    paths: Vec<TopicPath>,
+}
+
+impl Topic {
+    /// Adds `other` as an alternative, like `|`.
+    pub fn or(mut self, other: impl Into<Topic>) -> Self {
+        self.paths.extend(other.into().paths);
+        self
+    }
+
+    pub fn paths(&self) -> &[TopicPath] {
+        &self.paths
+    }
+
+    /// Returns the only path if it is concrete, i.e. if it names a single topic.
+    pub fn as_concrete(&self) -> Option<&TopicPath> {
+        match self.paths.as_slice() {
+            [path] if path.is_concrete() => Some(path),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if `topic` is matched by any of the alternatives.
+    pub fn matches(&self, topic: &TopicPath) -> bool {
+        self.paths.iter().any(|p| p.matches(topic))
+    }
+}
+
+impl From<TopicPath> for Topic {
+    fn from(path: TopicPath) -> Self {
+        Self { paths: vec![path] }
+    }
+}
+
+impl Display for Topic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, path) in self.paths.iter().enumerate() {
+            if i > 0 {
+                f.write_str("|")?;
+            }
+            path.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Topic {
+    type Err = ParseTopicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let paths = s
+            .split('|')
+            .map(|p| TopicPath::parse(s, p.trim()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { paths })
+    }
+}
+
+/// Returns `true` if `name` can be used as a level or a namespace prefix.
+///
+/// This is a simplification of an XML `NCName` that rejects the characters used for wildcards
+/// and alternation in topic expressions.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn path(s: &str) -> TopicPath {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn levels_inherit_namespaces() {
+        let path = path("tns1:Device/tnsaxis:IO/VirtualInput");
+        assert_eq!(
+            path.levels(),
+            &[
+                Level::named("tns1", "Device").unwrap(),
+                Level::named("tnsaxis", "IO").unwrap(),
+                Level::named("tnsaxis", "VirtualInput").unwrap(),
+            ]
+        );
+        assert!(path.is_concrete());
+    }
+
+    #[test]
+    fn expressions_round_trip() {
+        for s in [
+            "tns1:Device/tnsaxis:IO/VirtualInput",
+            "tns1:Device/tnsaxis:IO//.",
+            "tns1:Device/*/tnsaxis:Port",
+            "tns1:Device/tnsaxis:IO/Port|tns1:Device/Trigger/DigitalInput",
+        ] {
+            assert_eq!(s.parse::<Topic>().unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn redundant_prefixes_are_normalized() {
+        assert_eq!(
+            "tns1:Monitoring/tns1:ProcessorUsage | tns1:Device"
+                .parse::<Topic>()
+                .unwrap()
+                .to_string(),
+            "tns1:Monitoring/ProcessorUsage|tns1:Device"
+        );
+    }
+
+    #[test]
+    fn invalid_expressions_are_rejected() {
+        for s in [
+            "",
+            "Monitoring/ProcessorUsage",
+            "tns1:Monitoring//ProcessorUsage",
+            "tns1:Monitoring/",
+            "tns1:Monitoring||tns1:Device",
+            "tns1:",
+            ":Monitoring",
+            "tns1:Monitoring/Processor Usage",
+            "*/ProcessorUsage",
+        ] {
+            assert!(s.parse::<Topic>().is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn wildcards_match_concrete_topics() {
+        let topic = path("tns1:Device/tnsaxis:IO/VirtualInput");
+        for (filter, expected) in [
+            ("tns1:Device/tnsaxis:IO/VirtualInput", true),
+            ("tns1:Device/tnsaxis:IO/*", true),
+            ("tns1:Device//.", true),
+            ("tns1:Device/tnsaxis:IO/VirtualInput//.", true),
+            ("tns1:Device/tnsaxis:IO", false),
+            ("tns1:Device/tnsaxis:IO/Port", false),
+            ("tns1:Device/tns1:IO/VirtualInput", false),
+            ("tns1:Device/tnsaxis:IO/*/*", false),
+            ("tns1:Device/Trigger|tns1:Device/*/VirtualInput", false),
+            (
+                "tns1:Device/Trigger|tns1:Device/*/tnsaxis:VirtualInput",
+                true,
+            ),
+        ] {
+            let filter: Topic = filter.parse().unwrap();
+            assert_eq!(filter.matches(&topic), expected, "{filter}");
+        }
+    }
+
+    #[test]
+    fn only_single_paths_without_wildcards_are_concrete() {
+        assert!("tns1:Device"
+            .parse::<Topic>()
+            .unwrap()
+            .as_concrete()
+            .is_some());
+        for s in [
+            "tns1:Device/*",
+            "tns1:Device//.",
+            "tns1:Device|tns1:Monitoring",
+        ] {
+            assert!(s.parse::<Topic>().unwrap().as_concrete().is_none(), "{s}");
+        }
+    }
+}
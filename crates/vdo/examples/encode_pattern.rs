@@ -0,0 +1,51 @@
+//! Encodes a synthetic moving test pattern to a raw H.264 file.
+//!
+//! The output is an Annex B byte stream that can be played with e.g. `ffplay pattern.h264`.
+
+use std::{env, fs::File, io::Write};
+
+use vdo::{encode::EncoderBuilder, VdoFormat};
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 360;
+const FRAMERATE: u64 = 30;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "pattern.h264".to_string());
+
+    let encoder = EncoderBuilder::new(WIDTH as u32, HEIGHT as u32)
+        .format(VdoFormat::VDO_FORMAT_H264)
+        .framerate(FRAMERATE as u32)
+        .gop_length(FRAMERATE as u32)
+        .build()?;
+
+    println!("Encoding to {path}...");
+    let mut file = File::create(&path)?;
+    let mut frame = vec![0; WIDTH * HEIGHT * 3 / 2];
+    for i in 0..300 {
+        draw_pattern(&mut frame, i);
+        let mut input = encoder.alloc_input()?;
+        input.write(&frame)?;
+        input.set_timestamp(i as u64 * 1_000_000 / FRAMERATE);
+        encoder.encode(input)?;
+        file.write_all(encoder.next_buffer()?.data()?)?;
+    }
+
+    println!("Done!");
+    Ok(())
+}
+
+/// Draws diagonal luma stripes that move one pixel per frame on a neutral chroma background.
+fn draw_pattern(nv12: &mut [u8], i: usize) {
+    let (luma, chroma) = nv12.split_at_mut(WIDTH * HEIGHT);
+    for (y, row) in luma.chunks_exact_mut(WIDTH).enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = ((x + y + i) % 64 * 4) as u8;
+        }
+    }
+    chroma.fill(128);
+}
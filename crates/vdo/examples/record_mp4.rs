@@ -0,0 +1,35 @@
+//! Records a short H.264 clip to a fragmented MP4 file.
+//!
+//! The file can be played by most players, e.g. `ffplay clip.mp4`, and remains playable up to
+//! the last complete fragment if the recording is interrupted.
+
+use std::{env, fs::File, io::BufWriter};
+
+use vdo::{annexb::Codec, mp4::Muxer, Resolution, StreamBuilder, VdoFormat};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let path = env::args().nth(1).unwrap_or_else(|| "clip.mp4".to_string());
+
+    let stream = StreamBuilder::new()
+        .channel(0)
+        .format(VdoFormat::VDO_FORMAT_H264)
+        .resolution(Resolution::Exact {
+            width: 1280,
+            height: 720,
+        })
+        .framerate(30)
+        .build()?
+        .start()?;
+
+    println!("Recording to {path}...");
+    let mut muxer = Muxer::new(Codec::H264, BufWriter::new(File::create(&path)?));
+    for _ in 0..300 {
+        muxer.push_buffer(&stream.next_buffer()?)?;
+    }
+    muxer.finish()?;
+
+    println!("Done!");
+    Ok(())
+}
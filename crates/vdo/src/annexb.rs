@@ -0,0 +1,766 @@
+//! Helpers for H.264 and H.265 bitstreams in the Annex B byte stream format.
+//!
+//! VDO delivers encoded frames as a sequence of NAL units, each preceded by a start code, where
+//! key frames are preceded by the parameter sets needed to decode them. This module iterates the
+//! NAL units of a frame, caches the parameter sets and parses the codec parameters from the SPS,
+//! so that e.g. muxers and packetizers don't have to.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vdo::{
+//!     annexb::{Codec, ParameterSets},
+//!     StreamBuilder, VdoFormat,
+//! };
+//!
+//! let stream = StreamBuilder::new()
+//!     .format(VdoFormat::VDO_FORMAT_H264)
+//!     .build()?
+//!     .start()?;
+//! let mut parameter_sets = ParameterSets::new(Codec::H264);
+//! for _ in 0..10 {
+//!     let buffer = stream.next_buffer()?;
+//!     if parameter_sets.update(buffer.data()?) {
+//!         let parameters = parameter_sets.parameters()?;
+//!         println!("{} {}x{}", parameters.codec_string(), parameters.width, parameters.height);
+//!     }
+//!     for nal in buffer.nal_units(Codec::H264)? {
+//!         println!("NAL unit of type {} and size {}", nal.nal_type(), nal.data().len());
+//!     }
+//! }
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use std::fmt::Write;
+
+use vdo_sys::VdoFormat;
+
+use crate::{Error, StreamBuffer};
+
+const H264_IDR: u8 = 5;
+const H264_SPS: u8 = 7;
+const H264_PPS: u8 = 8;
+const H265_IRAP: std::ops::RangeInclusive<u8> = 16..=23;
+const H265_VPS: u8 = 32;
+const H265_SPS: u8 = 33;
+const H265_PPS: u8 = 34;
+
+/// Profiles for which the H.264 SPS includes the chroma format and bit depths.
+const H264_HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Codec {
+    H264,
+    H265,
+}
+
+impl Codec {
+    /// Returns the codec of `format`, or `None` if it is not H.264 or H.265.
+    pub fn from_format(format: VdoFormat) -> Option<Self> {
+        match format {
+            VdoFormat::VDO_FORMAT_H264 => Some(Self::H264),
+            VdoFormat::VDO_FORMAT_H265 => Some(Self::H265),
+            _ => None,
+        }
+    }
+
+    /// The size of the NAL unit header in bytes.
+    pub fn header_size(self) -> usize {
+        match self {
+            Self::H264 => 1,
+            Self::H265 => 2,
+        }
+    }
+}
+
+/// A NAL unit, including its header but excluding the start code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NalUnit<'a> {
+    codec: Codec,
+    data: &'a [u8],
+}
+
+impl<'a> NalUnit<'a> {
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// The bytes of the NAL unit, starting with the header.
+    ///
+    /// Emulation prevention bytes are kept, as required when muxing or packetizing the unit.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The `nal_unit_type` field of the header.
+    pub fn nal_type(&self) -> u8 {
+        match self.codec {
+            Codec::H264 => self.data[0] & 0x1f,
+            Codec::H265 => (self.data[0] >> 1) & 0x3f,
+        }
+    }
+
+    /// Returns `true` for the VPS, SPS and PPS.
+    pub fn is_parameter_set(&self) -> bool {
+        match self.codec {
+            Codec::H264 => matches!(self.nal_type(), H264_SPS | H264_PPS),
+            Codec::H265 => matches!(self.nal_type(), H265_VPS | H265_SPS | H265_PPS),
+        }
+    }
+
+    /// Returns `true` for slices of pictures that can be decoded without any preceding pictures,
+    /// i.e. IDR pictures for H.264 and IRAP pictures for H.265.
+    pub fn is_keyframe(&self) -> bool {
+        match self.codec {
+            Codec::H264 => self.nal_type() == H264_IDR,
+            Codec::H265 => H265_IRAP.contains(&self.nal_type()),
+        }
+    }
+
+    /// Returns `true` for NAL units that hold a slice of a picture.
+    pub fn is_slice(&self) -> bool {
+        match self.codec {
+            Codec::H264 => (1..=H264_IDR).contains(&self.nal_type()),
+            Codec::H265 => self.nal_type() < H265_VPS,
+        }
+    }
+}
+
+/// Iterator over the NAL units of an Annex B byte stream, created by [`nal_units()`].
+#[derive(Clone, Debug)]
+pub struct NalUnits<'a> {
+    codec: Codec,
+    rest: &'a [u8],
+}
+
+/// Returns an iterator over the NAL units in `data`.
+///
+/// Both 3 and 4 byte start codes are accepted. Bytes before the first start code are ignored, so
+/// data without any start code yields no NAL units.
+pub fn nal_units(codec: Codec, data: &[u8]) -> NalUnits<'_> {
+    let rest = match find_start_code(data) {
+        Some((_, end)) => &data[end..],
+        None => &[],
+    };
+    NalUnits { codec, rest }
+}
+
+impl<'a> Iterator for NalUnits<'a> {
+    type Item = NalUnit<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.rest.is_empty() {
+            let data = match find_start_code(self.rest) {
+                Some((start, end)) => {
+                    let data = &self.rest[..start];
+                    self.rest = &self.rest[end..];
+                    data
+                }
+                None => std::mem::take(&mut self.rest),
+            };
+            // Zero bytes before a start code belong to the start code, e.g. the first byte of a
+            // 4 byte start code, rather than to the NAL unit.
+            let end = data.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+            if end > 0 {
+                return Some(NalUnit {
+                    codec: self.codec,
+                    data: &data[..end],
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Returns the start and end of the first 3 byte start code in `data`.
+fn find_start_code(data: &[u8]) -> Option<(usize, usize)> {
+    data.windows(3)
+        .position(|w| w == [0, 0, 1])
+        .map(|start| (start, start + 3))
+}
+
+impl StreamBuffer<'_> {
+    /// Returns an iterator over the NAL units of an H.264 or H.265 frame.
+    pub fn nal_units(&self, codec: Codec) -> Result<NalUnits<'_>, Error> {
+        Ok(nal_units(codec, self.data()?))
+    }
+}
+
+/// The most recent parameter sets of a stream.
+///
+/// VDO uses a single parameter set of each kind, so only the latest one of each is kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParameterSets {
+    codec: Codec,
+    vps: Option<Vec<u8>>,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+}
+
+impl ParameterSets {
+    pub fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            vps: None,
+            sps: None,
+            pps: None,
+        }
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Caches the parameter sets in `data`, a frame in the Annex B format.
+    ///
+    /// Returns `true` if any of the cached parameter sets changed. Since parameter sets precede
+    /// the slices of the frame, the data after the first slice is not scanned.
+    pub fn update(&mut self, data: &[u8]) -> bool {
+        let mut changed = false;
+        for nal in nal_units(self.codec, data) {
+            if nal.is_slice() {
+                break;
+            }
+            let slot = match (self.codec, nal.nal_type()) {
+                (Codec::H264, H264_SPS) | (Codec::H265, H265_SPS) => &mut self.sps,
+                (Codec::H264, H264_PPS) | (Codec::H265, H265_PPS) => &mut self.pps,
+                (Codec::H265, H265_VPS) => &mut self.vps,
+                _ => continue,
+            };
+            if slot.as_deref() != Some(nal.data()) {
+                *slot = Some(nal.data().to_vec());
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The video parameter set, which is only used by H.265.
+    pub fn vps(&self) -> Option<&[u8]> {
+        self.vps.as_deref()
+    }
+
+    /// The sequence parameter set, including the NAL unit header.
+    pub fn sps(&self) -> Option<&[u8]> {
+        self.sps.as_deref()
+    }
+
+    /// The picture parameter set, including the NAL unit header.
+    pub fn pps(&self) -> Option<&[u8]> {
+        self.pps.as_deref()
+    }
+
+    /// Returns `true` if all parameter sets needed to decode the stream have been found.
+    pub fn is_complete(&self) -> bool {
+        self.sps.is_some()
+            && self.pps.is_some()
+            && (self.codec == Codec::H264 || self.vps.is_some())
+    }
+
+    /// Parses the codec parameters from the cached SPS.
+    pub fn parameters(&self) -> Result<CodecParameters, Error> {
+        let sps = self
+            .sps()
+            .ok_or(Error::Bitstream("no SPS has been found"))?;
+        CodecParameters::from_sps(self.codec, sps)
+    }
+}
+
+/// Codec parameters of a stream, as signaled in its sequence parameter set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodecParameters {
+    pub codec: Codec,
+    /// The `profile_space`; always 0 for H.264.
+    pub profile_space: u8,
+    /// The `tier_flag`, `true` for the high tier; always `false` for H.264.
+    pub tier: bool,
+    /// The `profile_idc`, e.g. 100 for High in H.264 and 1 for Main in H.265.
+    pub profile: u8,
+    /// The byte of `constraint_set` flags for H.264 and the `profile_compatibility` flags for
+    /// H.265, in bitstream order.
+    pub compatibility: u32,
+    /// The 48 bits of constraint flags following the compatibility flags in H.265, in bitstream
+    /// order; always 0 for H.264.
+    pub constraint_flags: u64,
+    /// The `level_idc`, which is 10 times the level for H.264 and 30 times the level for H.265.
+    pub level: u8,
+    /// The `chroma_format_idc`, e.g. 1 for 4:2:0.
+    pub chroma_format: u8,
+    pub bit_depth_luma: u8,
+    pub bit_depth_chroma: u8,
+    /// The width in pixels after cropping.
+    pub width: u32,
+    /// The height in pixels after cropping.
+    pub height: u32,
+}
+
+impl CodecParameters {
+    /// Parses a sequence parameter set, including the NAL unit header.
+    pub fn from_sps(codec: Codec, sps: &[u8]) -> Result<Self, Error> {
+        if sps.len() < codec.header_size() {
+            return Err(Error::Bitstream("not an SPS"));
+        }
+        let nal = NalUnit { codec, data: sps };
+        let mut r = BitReader::new(&sps[codec.header_size()..]);
+        match (codec, nal.nal_type()) {
+            (Codec::H264, H264_SPS) => parse_h264_sps(&mut r),
+            (Codec::H265, H265_SPS) => parse_h265_sps(&mut r),
+            _ => Err(Error::Bitstream("not an SPS")),
+        }
+    }
+
+    /// Returns the codec string defined by RFC 6381, such as `avc1.64002A` or `hvc1.1.6.L120.90`.
+    ///
+    /// This is the string expected by e.g. HLS and DASH manifests and the browser Media Source
+    /// API. The `hvc1` form signals that the parameter sets are stored out of band.
+    pub fn codec_string(&self) -> String {
+        match self.codec {
+            Codec::H264 => format!(
+                "avc1.{:02X}{:02X}{:02X}",
+                self.profile, self.compatibility, self.level
+            ),
+            Codec::H265 => {
+                let space = ["", "A", "B", "C"][usize::from(self.profile_space & 3)];
+                let tier = if self.tier { 'H' } else { 'L' };
+                let mut s = format!(
+                    "hvc1.{space}{}.{:X}.{tier}{}",
+                    self.profile,
+                    self.compatibility.reverse_bits(),
+                    self.level
+                );
+                let constraints = &self.constraint_flags.to_be_bytes()[2..];
+                let len = constraints
+                    .iter()
+                    .rposition(|&b| b != 0)
+                    .map_or(0, |i| i + 1);
+                for b in &constraints[..len] {
+                    write!(s, ".{b:02X}").unwrap();
+                }
+                s
+            }
+        }
+    }
+}
+
+fn parse_h264_sps(r: &mut BitReader) -> Result<CodecParameters, Error> {
+    let profile = r.bits(8)? as u8;
+    let compatibility = r.bits(8)?;
+    let level = r.bits(8)? as u8;
+    r.ue()?; // seq_parameter_set_id
+    let mut chroma_format = 1;
+    let mut separate_colour_planes = false;
+    let mut bit_depth_luma = 8;
+    let mut bit_depth_chroma = 8;
+    if H264_HIGH_PROFILES.contains(&profile) {
+        chroma_format = r.ue()?;
+        if chroma_format == 3 {
+            separate_colour_planes = r.bit()?;
+        }
+        bit_depth_luma = r.ue()?.saturating_add(8);
+        bit_depth_chroma = r.ue()?.saturating_add(8);
+        r.skip(1)?; // qpprime_y_zero_transform_bypass_flag
+        if r.bit()? {
+            let lists = if chroma_format == 3 { 12 } else { 8 };
+            for i in 0..lists {
+                if r.bit()? {
+                    skip_scaling_list(r, if i < 6 { 16 } else { 64 })?;
+                }
+            }
+        }
+    }
+    r.ue()?; // log2_max_frame_num_minus4
+    match r.ue()? {
+        0 => {
+            r.ue()?; // log2_max_pic_order_cnt_lsb_minus4
+        }
+        1 => {
+            r.skip(1)?; // delta_pic_order_always_zero_flag
+            r.se()?; // offset_for_non_ref_pic
+            r.se()?; // offset_for_top_to_bottom_field
+            for _ in 0..r.ue()? {
+                r.se()?; // offset_for_ref_frame
+            }
+        }
+        _ => {}
+    }
+    r.ue()?; // max_num_ref_frames
+    r.skip(1)?; // gaps_in_frame_num_value_allowed_flag
+    let width_in_mbs = u64::from(r.ue()?) + 1;
+    let height_in_map_units = u64::from(r.ue()?) + 1;
+    let frame_mbs_only = r.bit()?;
+    if !frame_mbs_only {
+        r.skip(1)?; // mb_adaptive_frame_field_flag
+    }
+    r.skip(1)?; // direct_8x8_inference_flag
+    let [left, right, top, bottom] = read_window(r)?;
+
+    let fields = if frame_mbs_only { 1 } else { 2 };
+    let chroma_array_type = if separate_colour_planes {
+        0
+    } else {
+        chroma_format
+    };
+    let (crop_x, crop_y) = match chroma_array_type {
+        1 => (2, 2 * fields),
+        2 => (2, fields),
+        _ => (1, fields),
+    };
+    Ok(CodecParameters {
+        codec: Codec::H264,
+        profile_space: 0,
+        tier: false,
+        profile,
+        compatibility,
+        constraint_flags: 0,
+        level,
+        chroma_format: narrow(chroma_format)?,
+        bit_depth_luma: narrow(bit_depth_luma)?,
+        bit_depth_chroma: narrow(bit_depth_chroma)?,
+        width: crop(width_in_mbs * 16, crop_x * (left + right))?,
+        height: crop(fields * height_in_map_units * 16, crop_y * (top + bottom))?,
+    })
+}
+
+fn skip_scaling_list(r: &mut BitReader, size: usize) -> Result<(), Error> {
+    let mut last = 8;
+    let mut next = 8;
+    for _ in 0..size {
+        if next != 0 {
+            next = (last + r.se()? + 256).rem_euclid(256);
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Ok(())
+}
+
+fn parse_h265_sps(r: &mut BitReader) -> Result<CodecParameters, Error> {
+    r.skip(4)?; // sps_video_parameter_set_id
+    let max_sub_layers_minus1 = r.bits(3)? as usize;
+    r.skip(1)?; // sps_temporal_id_nesting_flag
+
+    // profile_tier_level(1, sps_max_sub_layers_minus1)
+    let profile_space = r.bits(2)? as u8;
+    let tier = r.bit()?;
+    let profile = r.bits(5)? as u8;
+    let compatibility = r.bits(32)?;
+    let constraint_flags = (u64::from(r.bits(16)?) << 32) | u64::from(r.bits(32)?);
+    let level = r.bits(8)? as u8;
+    let mut sub_layers = [(false, false); 7];
+    for sub_layer in &mut sub_layers[..max_sub_layers_minus1] {
+        *sub_layer = (r.bit()?, r.bit()?);
+    }
+    if max_sub_layers_minus1 > 0 {
+        r.skip(2 * (8 - max_sub_layers_minus1))?; // reserved_zero_2bits
+    }
+    for &(profile_present, level_present) in &sub_layers[..max_sub_layers_minus1] {
+        if profile_present {
+            r.skip(88)?;
+        }
+        if level_present {
+            r.skip(8)?;
+        }
+    }
+
+    r.ue()?; // sps_seq_parameter_set_id
+    let chroma_format = r.ue()?;
+    let separate_colour_planes = chroma_format == 3 && r.bit()?;
+    let width = r.ue()?;
+    let height = r.ue()?;
+    let [left, right, top, bottom] = read_window(r)?;
+    let bit_depth_luma = r.ue()?.saturating_add(8);
+    let bit_depth_chroma = r.ue()?.saturating_add(8);
+
+    let (sub_width, sub_height) = match chroma_format {
+        _ if separate_colour_planes => (1, 1),
+        1 => (2, 2),
+        2 => (2, 1),
+        _ => (1, 1),
+    };
+    Ok(CodecParameters {
+        codec: Codec::H265,
+        profile_space,
+        tier,
+        profile,
+        compatibility,
+        constraint_flags,
+        level,
+        chroma_format: narrow(chroma_format)?,
+        bit_depth_luma: narrow(bit_depth_luma)?,
+        bit_depth_chroma: narrow(bit_depth_chroma)?,
+        width: crop(width.into(), sub_width * (left + right))?,
+        height: crop(height.into(), sub_height * (top + bottom))?,
+    })
+}
+
+/// Reads the cropping or conformance window as `[left, right, top, bottom]`, if present.
+fn read_window(r: &mut BitReader) -> Result<[u64; 4], Error> {
+    if !r.bit()? {
+        return Ok([0; 4]);
+    }
+    Ok([
+        r.ue()?.into(),
+        r.ue()?.into(),
+        r.ue()?.into(),
+        r.ue()?.into(),
+    ])
+}
+
+fn crop(size: u64, cropped: u64) -> Result<u32, Error> {
+    size.checked_sub(cropped)
+        .and_then(|s| u32::try_from(s).ok())
+        .ok_or(Error::Bitstream("invalid picture size"))
+}
+
+fn narrow(value: u32) -> Result<u8, Error> {
+    u8::try_from(value).map_err(|_| Error::Bitstream("value out of range"))
+}
+
+/// Removes the emulation prevention bytes of a NAL unit, yielding the raw payload.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &b in data {
+        if zeros >= 2 && b == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if b == 0 { zeros + 1 } else { 0 };
+        out.push(b);
+    }
+    out
+}
+
+/// Reads bits, most significant first, from a NAL unit payload.
+struct BitReader {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl BitReader {
+    /// Creates a reader for the payload following the NAL unit header.
+    fn new(escaped: &[u8]) -> Self {
+        Self {
+            data: unescape(escaped),
+            pos: 0,
+        }
+    }
+
+    fn bit(&mut self) -> Result<bool, Error> {
+        let byte = self
+            .data
+            .get(self.pos / 8)
+            .ok_or(Error::Bitstream("unexpected end of data"))?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(bit == 1)
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, Error> {
+        debug_assert!(n <= 32);
+        let mut value = 0u64;
+        for _ in 0..n {
+            value = (value << 1) | u64::from(self.bit()?);
+        }
+        Ok(value as u32)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Error> {
+        if self.pos + n > self.data.len() * 8 {
+            return Err(Error::Bitstream("unexpected end of data"));
+        }
+        self.pos += n;
+        Ok(())
+    }
+
+    /// Reads an unsigned exponential-Golomb code.
+    fn ue(&mut self) -> Result<u32, Error> {
+        let mut zeros = 0;
+        while !self.bit()? {
+            zeros += 1;
+            if zeros > 31 {
+                return Err(Error::Bitstream("exp-Golomb code is too long"));
+            }
+        }
+        Ok(((1u64 << zeros) - 1 + u64::from(self.bits(zeros)?)) as u32)
+    }
+
+    /// Reads a signed exponential-Golomb code.
+    fn se(&mut self) -> Result<i64, Error> {
+        let k = i64::from(self.ue()?);
+        Ok(if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) })
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    const H264_BASELINE_720P_SPS: [u8; 9] = [0x67, 0x42, 0xC0, 0x1F, 0xDA, 0x01, 0x40, 0x16, 0xE4];
+    const H264_HIGH_1080P_SPS: [u8; 12] = [
+        0x67, 0x64, 0x00, 0x28, 0xAC, 0xCA, 0x80, 0x78, 0x02, 0x27, 0xE5, 0x40,
+    ];
+    const H264_MAIN_576I_SPS: [u8; 11] = [
+        0x67, 0x4D, 0x40, 0x1E, 0xD0, 0xA6, 0x69, 0xA0, 0x2D, 0x09, 0x32,
+    ];
+    const H265_MAIN_1080P_SPS: [u8; 42] = [
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x78, 0xA0, 0x03, 0xC0, 0x80, 0x10, 0xE5, 0x96, 0x66, 0x69, 0x24, 0xCA, 0xE0,
+        0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0xE0, 0x80,
+    ];
+
+    #[test]
+    fn nal_units_are_split_on_start_codes() {
+        let data = [
+            0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x68, 0, 0, 3, 0, 0, 0, 0, 1, 0x65, 4,
+        ];
+        let nals: Vec<_> = nal_units(Codec::H264, &data).collect();
+        assert_eq!(
+            nals.iter().map(|n| n.data()).collect::<Vec<_>>(),
+            vec![&[0x67, 1, 2][..], &[0x68, 0, 0, 3], &[0x65, 4]]
+        );
+        assert_eq!(
+            nals.iter().map(|n| n.nal_type()).collect::<Vec<_>>(),
+            vec![7, 8, 5]
+        );
+        assert!(nals[0].is_parameter_set());
+        assert!(nals[2].is_keyframe() && nals[2].is_slice());
+    }
+
+    #[test]
+    fn data_without_start_code_has_no_nal_units() {
+        assert_eq!(nal_units(Codec::H264, &[0x67, 1, 2, 0, 0]).count(), 0);
+        assert_eq!(nal_units(Codec::H264, &[0, 0, 1]).count(), 0);
+        assert_eq!(nal_units(Codec::H264, &[]).count(), 0);
+    }
+
+    #[test]
+    fn h265_nal_types_are_decoded() {
+        let data = [
+            0, 0, 1, 0x40, 1, 0, 0, 1, 0x26, 1, 0xAF, 0, 0, 1, 0x02, 1, 0x80,
+        ];
+        let nals: Vec<_> = nal_units(Codec::H265, &data).collect();
+        assert_eq!(
+            nals.iter().map(|n| n.nal_type()).collect::<Vec<_>>(),
+            vec![32, 19, 1]
+        );
+        assert!(nals[0].is_parameter_set() && !nals[0].is_slice());
+        assert!(nals[1].is_keyframe());
+        assert!(nals[2].is_slice() && !nals[2].is_keyframe());
+    }
+
+    #[test]
+    fn emulation_prevention_bytes_are_removed() {
+        assert_eq!(unescape(&[0, 0, 3, 1, 0, 0, 3]), vec![0, 0, 1, 0, 0]);
+        assert_eq!(unescape(&[0, 3, 0, 0, 3, 3]), vec![0, 3, 0, 0, 3]);
+    }
+
+    #[test]
+    fn exp_golomb_codes_are_read() {
+        // 1 | 010 | 011 | 00100 | 00101
+        let mut r = BitReader::new(&[0b1010_0110, 0b0100_0010, 0b1000_0000]);
+        assert_eq!(r.ue().unwrap(), 0);
+        assert_eq!(r.ue().unwrap(), 1);
+        assert_eq!(r.se().unwrap(), -1);
+        assert_eq!(r.se().unwrap(), 2);
+        assert_eq!(r.se().unwrap(), -2);
+        assert!(r.ue().is_err());
+    }
+
+    #[test]
+    fn h264_baseline_sps_is_parsed() {
+        let p = CodecParameters::from_sps(Codec::H264, &H264_BASELINE_720P_SPS).unwrap();
+        assert_eq!((p.width, p.height), (1280, 720));
+        assert_eq!((p.profile, p.compatibility, p.level), (66, 0xC0, 31));
+        assert_eq!(p.codec_string(), "avc1.42C01F");
+    }
+
+    #[test]
+    fn h264_high_sps_is_cropped() {
+        let p = CodecParameters::from_sps(Codec::H264, &H264_HIGH_1080P_SPS).unwrap();
+        assert_eq!((p.width, p.height), (1920, 1080));
+        assert_eq!(
+            (p.chroma_format, p.bit_depth_luma, p.bit_depth_chroma),
+            (1, 8, 8)
+        );
+        assert_eq!(p.codec_string(), "avc1.640028");
+    }
+
+    #[test]
+    fn h264_interlaced_sps_is_parsed() {
+        let p = CodecParameters::from_sps(Codec::H264, &H264_MAIN_576I_SPS).unwrap();
+        assert_eq!((p.width, p.height), (720, 576));
+        assert_eq!(p.codec_string(), "avc1.4D401E");
+    }
+
+    #[test]
+    fn h265_sps_is_parsed() {
+        let p = CodecParameters::from_sps(Codec::H265, &H265_MAIN_1080P_SPS).unwrap();
+        assert_eq!((p.width, p.height), (1920, 1080));
+        assert_eq!((p.profile, p.level, p.tier), (1, 120, false));
+        assert_eq!(p.compatibility, 0x6000_0000);
+        assert_eq!(p.constraint_flags, 0x9000_0000_0000);
+        assert_eq!(
+            (p.chroma_format, p.bit_depth_luma, p.bit_depth_chroma),
+            (1, 8, 8)
+        );
+        assert_eq!(p.codec_string(), "hvc1.1.6.L120.90");
+    }
+
+    #[test]
+    fn invalid_sps_is_rejected() {
+        assert!(CodecParameters::from_sps(Codec::H264, &[0x68, 0x42]).is_err());
+        assert!(CodecParameters::from_sps(Codec::H264, &H264_HIGH_1080P_SPS[..6]).is_err());
+        assert!(CodecParameters::from_sps(Codec::H265, &H264_HIGH_1080P_SPS).is_err());
+        assert!(CodecParameters::from_sps(Codec::H265, &[]).is_err());
+    }
+
+    #[test]
+    fn parameter_sets_are_cached_until_changed() {
+        let mut sets = ParameterSets::new(Codec::H264);
+        assert!(!sets.is_complete());
+        assert!(sets.parameters().is_err());
+        let frame = [
+            &[0, 0, 0, 1][..],
+            &H264_HIGH_1080P_SPS,
+            &[0, 0, 0, 1, 0x68, 0xEE, 0x3C, 0x80],
+            &[0, 0, 0, 1, 0x65, 0x88, 0x84],
+        ]
+        .concat();
+        assert!(sets.update(&frame));
+        assert!(sets.is_complete());
+        assert_eq!(sets.pps(), Some(&[0x68, 0xEE, 0x3C, 0x80][..]));
+        assert_eq!(sets.parameters().unwrap().width, 1920);
+        assert!(!sets.update(&frame));
+        assert!(!sets.update(&[0, 0, 0, 1, 0x41, 0x9A]));
+
+        let frame = [&[0, 0, 0, 1][..], &H264_BASELINE_720P_SPS].concat();
+        assert!(sets.update(&frame));
+        assert_eq!(sets.parameters().unwrap().width, 1280);
+    }
+
+    #[test]
+    fn parameter_sets_after_the_first_slice_are_ignored() {
+        let mut sets = ParameterSets::new(Codec::H264);
+        let frame = [&[0, 0, 1, 0x65, 0x88, 0, 0, 1][..], &H264_HIGH_1080P_SPS].concat();
+        assert!(!sets.update(&frame));
+        assert_eq!(sets.sps(), None);
+    }
+
+    #[test]
+    fn h265_requires_vps() {
+        let mut sets = ParameterSets::new(Codec::H265);
+        let frame = [
+            &[0, 0, 0, 1][..],
+            &H265_MAIN_1080P_SPS,
+            &[0, 0, 0, 1, 0x44, 0x01, 0xC1],
+        ]
+        .concat();
+        assert!(sets.update(&frame));
+        assert!(!sets.is_complete());
+        assert!(sets.update(&[0, 0, 0, 1, 0x40, 0x01, 0x0C]));
+        assert!(sets.is_complete());
+        assert_eq!(sets.parameters().unwrap().height, 1080);
+    }
+}
@@ -0,0 +1,361 @@
+//! Discovery of video channels and their capabilities.
+
+use std::{ffi::c_void, fmt::Write};
+
+use gobject_sys::{g_object_unref, GObject};
+use vdo_sys::{VdoChannel, VdoFormat, VdoResolutionSet};
+
+use crate::{Error, Map, Resolution};
+
+/// Formats that [`Channel::supported_formats()`] probes for.
+const KNOWN_FORMATS: [VdoFormat; 8] = [
+    VdoFormat::VDO_FORMAT_H264,
+    VdoFormat::VDO_FORMAT_H265,
+    VdoFormat::VDO_FORMAT_AV1,
+    VdoFormat::VDO_FORMAT_JPEG,
+    VdoFormat::VDO_FORMAT_YUV,
+    VdoFormat::VDO_FORMAT_RGBA,
+    VdoFormat::VDO_FORMAT_RGB,
+    VdoFormat::VDO_FORMAT_PLANAR_RGB,
+];
+
+/// A video channel, typically corresponding to a view area of an image sensor.
+///
+/// Use [`Channel::all()`] to discover the channels of the device and the query methods to find a
+/// configuration that [`StreamBuilder`](crate::StreamBuilder) can create a stream for.
+///
+/// # Example
+///
+/// ```no_run
+/// use vdo::{Channel, StreamBuilder, VdoFormat};
+///
+/// for channel in Channel::all()? {
+///     println!("Channel {}", channel.id());
+///     for resolution in channel.resolutions(Some(VdoFormat::VDO_FORMAT_YUV))? {
+///         println!("  {resolution:?}");
+///     }
+/// }
+/// # Ok::<(), vdo::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Channel {
+    raw: *mut VdoChannel,
+}
+
+// SAFETY: We hold exclusive ownership of the raw pointer and the VDO SDK
+// does not require channels to be pinned to a specific thread.
+unsafe impl Send for Channel {}
+
+impl Channel {
+    /// Returns the channel with number `id`.
+    pub fn get(id: u32) -> Result<Self, Error> {
+        let (raw, maybe_error) = unsafe { try_func!(vdo_sys::vdo_channel_get, id) };
+        if raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        Ok(Self { raw })
+    }
+
+    /// Returns all channels of the device.
+    pub fn all() -> Result<Vec<Self>, Error> {
+        let mut error: *mut glib_sys::GError = std::ptr::null_mut();
+        let list = unsafe { vdo_sys::vdo_channel_get_all(&mut error) };
+        if !error.is_null() {
+            return Err(Error::Vdo(crate::VdoError::from_gerror(error)));
+        }
+        let mut channels = Vec::new();
+        let mut node = list;
+        while !node.is_null() {
+            // SAFETY: The list and its elements are owned by us; the elements are moved into
+            // `Channel`s and the list itself is freed below.
+            unsafe {
+                channels.push(Self {
+                    raw: (*node).data as *mut VdoChannel,
+                });
+                node = (*node).next;
+            }
+        }
+        unsafe { glib_sys::g_list_free(list) };
+        Ok(channels)
+    }
+
+    /// The number of the channel, as used by [`StreamBuilder::channel()`](crate::StreamBuilder::channel).
+    pub fn id(&self) -> u32 {
+        unsafe { vdo_sys::vdo_channel_get_id(self.raw) }
+    }
+
+    /// Returns channel information, such as the sensor resolution, as a map.
+    pub fn info(&self) -> Result<Map, Error> {
+        let (map_raw, maybe_error) = unsafe { try_func!(vdo_sys::vdo_channel_get_info, self.raw) };
+        if map_raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        // SAFETY: map_raw is non-null and freshly returned by VDO with ownership transferred.
+        Ok(unsafe { Map::from_raw(map_raw) })
+    }
+
+    /// Returns channel settings, such as the rotation and framerate, as a map.
+    pub fn settings(&self) -> Result<Map, Error> {
+        let (map_raw, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_channel_get_settings, self.raw) };
+        if map_raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        Ok(unsafe { Map::from_raw(map_raw) })
+    }
+
+    /// Returns the resolutions supported by the channel.
+    ///
+    /// If `format` is given, only resolutions supported for that format are returned.
+    /// All returned values are [`Resolution::Exact`].
+    pub fn resolutions(&self, format: Option<VdoFormat>) -> Result<Vec<Resolution>, Error> {
+        let mut filter = Map::new();
+        if let Some(format) = format {
+            filter.set_u32(c"format", format.0 as u32);
+        }
+        let (set, maybe_error) = unsafe {
+            try_func!(
+                vdo_sys::vdo_channel_get_resolutions,
+                self.raw,
+                filter.as_ptr()
+            )
+        };
+        if set.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        // SAFETY: A non-null set holds `count` resolutions and is owned by us.
+        let resolutions = unsafe { resolutions_from_set(set) };
+        unsafe { glib_sys::g_free(set as *mut c_void) };
+        Ok(resolutions)
+    }
+
+    /// Returns the formats for which the channel supports at least one resolution.
+    pub fn supported_formats(&self) -> Vec<VdoFormat> {
+        KNOWN_FORMATS
+            .into_iter()
+            .filter(|&f| self.resolutions(Some(f)).is_ok_and(|r| !r.is_empty()))
+            .collect()
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        unsafe { g_object_unref(self.raw as *mut GObject) };
+    }
+}
+
+/// # Safety
+///
+/// `set` must point to a valid `VdoResolutionSet`.
+unsafe fn resolutions_from_set(set: *const VdoResolutionSet) -> Vec<Resolution> {
+    let count = (*set).count;
+    (*set)
+        .resolutions
+        .as_slice(count)
+        .iter()
+        .map(|r| Resolution::Exact {
+            width: r.width,
+            height: r.height,
+        })
+        .collect()
+}
+
+/// Returns a human readable name for `format`.
+pub(crate) fn format_name(format: VdoFormat) -> &'static str {
+    match format {
+        VdoFormat::VDO_FORMAT_H264 => "H.264",
+        VdoFormat::VDO_FORMAT_H265 => "H.265",
+        VdoFormat::VDO_FORMAT_JPEG => "JPEG",
+        VdoFormat::VDO_FORMAT_YUV => "YUV",
+        VdoFormat::VDO_FORMAT_BAYER => "Bayer",
+        VdoFormat::VDO_FORMAT_IVS => "IVS",
+        VdoFormat::VDO_FORMAT_RAW => "raw",
+        VdoFormat::VDO_FORMAT_RGBA => "RGBA",
+        VdoFormat::VDO_FORMAT_RGB => "RGB",
+        VdoFormat::VDO_FORMAT_PLANAR_RGB => "planar RGB",
+        VdoFormat::VDO_FORMAT_AV1 => "AV1",
+        VdoFormat::VDO_FORMAT_AVIF => "AVIF",
+        _ => "unknown",
+    }
+}
+
+/// Formats a list of resolutions like `640x480, 1920x1080`.
+pub(crate) fn format_resolutions(resolutions: &[Resolution]) -> String {
+    let mut s = String::new();
+    for (i, r) in resolutions.iter().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        match r {
+            Resolution::Native => s.push_str("native"),
+            Resolution::Exact { width, height } => write!(s, "{width}x{height}").unwrap(),
+        }
+    }
+    s
+}
+
+/// Picks the resolution in `supported` that best fits `width`x`height`.
+///
+/// See [`StreamBuilder::fit_resolution()`](crate::StreamBuilder::fit_resolution).
+pub(crate) fn fit_resolution(
+    supported: &[Resolution],
+    width: u32,
+    height: u32,
+) -> Option<Resolution> {
+    let exact = supported.iter().filter_map(|r| match r {
+        Resolution::Exact { width, height } => Some((*width, *height)),
+        Resolution::Native => None,
+    });
+    let area = |(w, h): (u32, u32)| u64::from(w) * u64::from(h);
+    exact
+        .clone()
+        .filter(|&(w, h)| w >= width && h >= height)
+        .min_by_key(|&r| area(r))
+        .or_else(|| exact.max_by_key(|&r| area(r)))
+        .map(|(width, height)| Resolution::Exact { width, height })
+}
+
+/// Explains why a stream with the given configuration cannot be created, if the reason can be
+/// found by querying the channel.
+pub(crate) fn explain_unsupported(
+    channel: u32,
+    format: VdoFormat,
+    resolution: &Resolution,
+) -> Option<String> {
+    let c = match Channel::get(channel) {
+        Ok(c) => c,
+        Err(Error::Vdo(e)) if e.code() == vdo_sys::VDO_ERROR_NOT_FOUND.0 as i32 => {
+            let ids = Channel::all()
+                .map(|all| {
+                    all.iter()
+                        .map(|c| c.id().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                })
+                .unwrap_or_default();
+            return Some(format!(
+                "Channel {channel} does not exist; available channels are [{ids}]"
+            ));
+        }
+        // Nothing is known about the channel, so the error from VDO has to speak for itself.
+        Err(_) => return None,
+    };
+    let supported = match c.resolutions(Some(format)) {
+        Ok(r) if !r.is_empty() => r,
+        _ => {
+            let formats = c
+                .supported_formats()
+                .into_iter()
+                .map(format_name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            return Some(format!(
+                "Channel {channel} does not support the format {}; supported formats are [{formats}]",
+                format_name(format)
+            ));
+        }
+    };
+    match resolution {
+        Resolution::Exact { width, height } if !supported.contains(resolution) => Some(format!(
+            "Channel {channel} does not support the resolution {width}x{height} for the format {}; supported resolutions are [{}]",
+            format_name(format),
+            format_resolutions(&supported)
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use expect_test::expect;
+
+    use super::*;
+
+    #[test]
+    fn resolutions_are_listed() {
+        let resolutions = [
+            Resolution::Exact {
+                width: 640,
+                height: 480,
+            },
+            Resolution::Exact {
+                width: 1920,
+                height: 1080,
+            },
+        ];
+        expect!["640x480, 1920x1080"].assert_eq(&format_resolutions(&resolutions));
+        expect![""].assert_eq(&format_resolutions(&[]));
+    }
+
+    #[test]
+    fn resolutions_are_fitted() {
+        let exact = |width, height| Resolution::Exact { width, height };
+        let supported = [exact(1920, 1080), exact(640, 480), exact(1280, 720)];
+        assert_eq!(
+            fit_resolution(&supported, 1280, 720),
+            Some(exact(1280, 720))
+        );
+        assert_eq!(
+            fit_resolution(&supported, 1000, 500),
+            Some(exact(1280, 720))
+        );
+        assert_eq!(
+            fit_resolution(&supported, 1280, 800),
+            Some(exact(1920, 1080))
+        );
+        assert_eq!(
+            fit_resolution(&supported, 4000, 3000),
+            Some(exact(1920, 1080))
+        );
+        assert_eq!(fit_resolution(&[], 640, 480), None);
+    }
+
+    #[test]
+    fn format_names() {
+        expect!["H.264"].assert_eq(format_name(VdoFormat::VDO_FORMAT_H264));
+        expect!["unknown"].assert_eq(format_name(VdoFormat::VDO_FORMAT_NONE));
+    }
+}
+
+// These tests require a camera and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StreamBuilder;
+
+    #[test]
+    fn channels_support_yuv() -> Result<(), Error> {
+        let channels = Channel::all()?;
+        assert!(!channels.is_empty());
+        for channel in channels {
+            assert_eq!(Channel::get(channel.id())?.id(), channel.id());
+            assert!(channel
+                .supported_formats()
+                .contains(&VdoFormat::VDO_FORMAT_YUV));
+            assert!(!channel
+                .resolutions(Some(VdoFormat::VDO_FORMAT_YUV))?
+                .is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_resolution_is_explained() {
+        let builder = StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_YUV)
+            .resolution(Resolution::Exact {
+                width: 12345,
+                height: 17,
+            });
+        let err = builder
+            .clone()
+            .build()
+            .expect_err("Resolution should not be supported");
+        let Error::Vdo(e) = err else {
+            panic!("Expected Error::Vdo, got: {err:?}");
+        };
+        assert!(e.explanation().is_some(), "{e:?}");
+        builder.fit_resolution().unwrap().build().unwrap();
+    }
+}
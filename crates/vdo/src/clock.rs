@@ -0,0 +1,140 @@
+//! Conversion between the monotonic and UTC clocks used for frame timestamps.
+//!
+//! [`StreamBuffer::timestamp()`](crate::StreamBuffer::timestamp) is taken from the monotonic
+//! clock, which is suitable for measuring intervals but means nothing outside the device, while
+//! other data, such as events, is usually stamped with the wall-clock time. A [`ClockOffset`]
+//! translates between the two.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vdo::{clock::ClockOffset, StreamBuilder};
+//!
+//! let stream = StreamBuilder::new().build()?.start()?;
+//! let offset = ClockOffset::now();
+//! let buffer = stream.next_buffer()?;
+//! println!("Captured at {:?}", offset.to_system_time(buffer.timestamp()));
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::StreamBuffer;
+
+/// The difference between the UTC and the monotonic clock at some point in time.
+///
+/// The offset changes when the wall clock is adjusted, e.g. by NTP, so long-running applications
+/// should refresh it periodically.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockOffset {
+    utc_minus_monotonic_us: i64,
+}
+
+impl ClockOffset {
+    /// Measures the current offset between the clocks.
+    pub fn now() -> Self {
+        // Sampling the wall clock between two readings of the monotonic clock bounds the error
+        // by half the time it takes to read the clocks.
+        let before = now_us(libc::CLOCK_MONOTONIC);
+        let utc = now_us(libc::CLOCK_REALTIME);
+        let after = now_us(libc::CLOCK_MONOTONIC);
+        Self {
+            utc_minus_monotonic_us: utc - (before + (after - before) / 2),
+        }
+    }
+
+    /// Derives the offset from a pair of timestamps, in microseconds, that refer to the same
+    /// instant.
+    pub fn from_timestamps(monotonic_us: u64, utc_us: u64) -> Self {
+        Self {
+            utc_minus_monotonic_us: utc_us as i64 - monotonic_us as i64,
+        }
+    }
+
+    /// Derives the offset from the timestamps of a frame, or returns `None` if VDO did not
+    /// provide a UTC timestamp.
+    pub fn from_buffer(buffer: &StreamBuffer) -> Option<Self> {
+        match buffer.utc_timestamp() {
+            0 => None,
+            utc => Some(Self::from_timestamps(buffer.timestamp(), utc)),
+        }
+    }
+
+    /// The number of microseconds to add to a monotonic timestamp to get a UTC timestamp.
+    pub fn as_micros(&self) -> i64 {
+        self.utc_minus_monotonic_us
+    }
+
+    /// Converts a monotonic timestamp to microseconds since the Unix epoch.
+    pub fn to_utc_us(&self, monotonic_us: u64) -> u64 {
+        monotonic_us.saturating_add_signed(self.utc_minus_monotonic_us)
+    }
+
+    /// Converts microseconds since the Unix epoch to a monotonic timestamp.
+    ///
+    /// Saturates at 0 for instants before the monotonic clock started.
+    pub fn to_monotonic_us(&self, utc_us: u64) -> u64 {
+        utc_us.saturating_add_signed(-self.utc_minus_monotonic_us)
+    }
+
+    /// Converts a monotonic timestamp to a [`SystemTime`].
+    pub fn to_system_time(&self, monotonic_us: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.to_utc_us(monotonic_us))
+    }
+
+    /// Converts a [`SystemTime`] to a monotonic timestamp.
+    pub fn from_system_time(&self, time: SystemTime) -> u64 {
+        let utc_us = time
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as u64);
+        self.to_monotonic_us(utc_us)
+    }
+}
+
+// The fields of `timespec` are only 32 bits wide on some targets, such as armv7.
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn now_us(clock: libc::clockid_t) -> i64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid timespec and both clocks used are supported on Linux.
+    let ret = unsafe { libc::clock_gettime(clock, &mut ts) };
+    assert_eq!(ret, 0, "expect clock_gettime to succeed");
+    ts.tv_sec as i64 * 1_000_000 + ts.tv_nsec as i64 / 1_000
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn converts_in_both_directions() {
+        let offset = ClockOffset::from_timestamps(5_000_000, 1_700_000_000_000_000);
+        assert_eq!(offset.to_utc_us(6_000_000), 1_700_000_001_000_000);
+        assert_eq!(offset.to_monotonic_us(1_700_000_001_000_000), 6_000_000);
+        assert_eq!(
+            offset.from_system_time(offset.to_system_time(7_000_001)),
+            7_000_001
+        );
+    }
+
+    #[test]
+    fn saturates_before_boot() {
+        let offset = ClockOffset::from_timestamps(5_000_000, 1_700_000_000_000_000);
+        assert_eq!(offset.to_monotonic_us(1_000_000), 0);
+        assert_eq!(offset.from_system_time(UNIX_EPOCH), 0);
+    }
+
+    #[test]
+    fn now_agrees_with_system_time() {
+        let offset = ClockOffset::now();
+        let monotonic = now_us(libc::CLOCK_MONOTONIC) as u64;
+        let expected = SystemTime::now();
+        let actual = offset.to_system_time(monotonic);
+        let error = actual
+            .duration_since(expected)
+            .unwrap_or_else(|e| e.duration());
+        assert!(error < Duration::from_millis(100), "{error:?}");
+    }
+}
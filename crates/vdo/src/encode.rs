@@ -0,0 +1,426 @@
+//! Encoding of frames supplied by the application.
+//!
+//! An [`Encoder`] is a stream that takes its input from the application instead of from a camera
+//! channel, which makes it possible to encode composited or synthetic frames using the hardware
+//! encoder. The stream uses the `EXPLICIT` buffer strategy, so the buffers that receive the
+//! encoded frames are allocated up front and returned to the encoder when they are dropped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vdo::{encode::EncoderBuilder, VdoFormat};
+//!
+//! let encoder = EncoderBuilder::new(640, 360)
+//!     .format(VdoFormat::VDO_FORMAT_JPEG)
+//!     .build()?;
+//! let mut input = encoder.alloc_input()?;
+//! // A mid-gray NV12 frame.
+//! input.write(&vec![128; 640 * 360 * 3 / 2])?;
+//! encoder.encode(input)?;
+//! let output = encoder.next_buffer()?;
+//! std::fs::write("gray.jpg", output.data()?).expect("Failed to write image");
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use std::marker::PhantomData;
+
+use vdo_sys::{VdoBuffer, VdoBufferStrategy, VdoFormat, VdoFrameType, VdoStream};
+
+use crate::{Error, Map, RunningStream, Stream, StreamBuffer};
+
+/// Builder for creating an [`Encoder`].
+///
+/// Use [`EncoderBuilder::new()`] to create a new builder.
+#[derive(Clone, Debug)]
+pub struct EncoderBuilder {
+    format: VdoFormat,
+    width: u32,
+    height: u32,
+    framerate: u32,
+    gop_length: u32,
+    buffer_count: u32,
+}
+
+impl EncoderBuilder {
+    /// Creates a builder for an encoder of frames that are `width` by `height` pixels.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            format: VdoFormat::VDO_FORMAT_H264,
+            width,
+            height,
+            framerate: 0,
+            gop_length: 0,
+            buffer_count: 3,
+        }
+    }
+
+    /// Default: `VdoFormat::VDO_FORMAT_H264`
+    ///
+    /// Must be one of H.264, H.265 or JPEG.
+    pub fn format(mut self, format: VdoFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// The nominal framerate, used by the rate control of the encoder. If 0, the default of the
+    /// platform is used.
+    pub fn framerate(mut self, framerate: u32) -> Self {
+        self.framerate = framerate;
+        self
+    }
+
+    /// The number of frames between key frames. If 0, the default of the platform is used.
+    pub fn gop_length(mut self, gop_length: u32) -> Self {
+        self.gop_length = gop_length;
+        self
+    }
+
+    /// Default: 3. The number of buffers that receive encoded frames.
+    ///
+    /// This bounds the number of encoded frames that can be held by the application at once.
+    pub fn buffers(mut self, count: u32) -> Self {
+        self.buffer_count = count;
+        self
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if ![
+            VdoFormat::VDO_FORMAT_H264,
+            VdoFormat::VDO_FORMAT_H265,
+            VdoFormat::VDO_FORMAT_JPEG,
+        ]
+        .contains(&self.format)
+        {
+            return Err(Error::InvalidArgument(
+                "encoder format must be H.264, H.265 or JPEG",
+            ));
+        }
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidArgument(
+                "encoder resolution must be non-zero",
+            ));
+        }
+        if self.buffer_count == 0 {
+            return Err(Error::InvalidArgument(
+                "encoder must have at least one buffer",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Builds and starts the encoder.
+    ///
+    /// Returns [`Error::InvalidArgument`] without contacting VDO if the configuration is invalid.
+    pub fn build(self) -> Result<Encoder, Error> {
+        self.validate()?;
+        let mut map = Map::new();
+        map.set_u32(c"format", self.format.0 as u32);
+        map.set_u32(c"width", self.width);
+        map.set_u32(c"height", self.height);
+        if self.framerate > 0 {
+            map.set_u32(c"framerate", self.framerate);
+        }
+        if self.gop_length > 0 {
+            map.set_u32(c"gop_length", self.gop_length);
+        }
+        map.set_u32(c"buffer.count", self.buffer_count);
+        map.set_u32(
+            c"buffer.strategy",
+            VdoBufferStrategy::VDO_BUFFER_STRATEGY_EXPLICIT.0,
+        );
+
+        let (stream_raw, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_stream_new, map.as_ptr(), None) };
+        if stream_raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        let stream = Stream {
+            raw: stream_raw,
+            started: false,
+        };
+
+        // With the EXPLICIT strategy the encoder only writes to buffers that have been enqueued
+        // by the application. The stream owns them once enqueued and releases them when closed.
+        for _ in 0..self.buffer_count {
+            // Released when dropped unless it has been enqueued.
+            let buffer = alloc(stream.raw)?;
+            enqueue(stream.raw, buffer.raw)?;
+            buffer.into_raw();
+        }
+
+        Ok(Encoder {
+            stream: stream.start()?,
+        })
+    }
+}
+
+/// A stream that encodes frames supplied by the application.
+///
+/// Created using [`EncoderBuilder`]. Frames are written to buffers from
+/// [`alloc_input()`](Encoder::alloc_input), passed to [`encode()`](Encoder::encode) and the
+/// results are retrieved using [`next_buffer()`](Encoder::next_buffer). Drop this value to stop
+/// the encoder.
+pub struct Encoder {
+    stream: RunningStream,
+}
+
+impl Encoder {
+    /// Allocates a buffer for an input frame.
+    ///
+    /// The buffer is sized by VDO to fit a frame of the configured resolution. Its contents are
+    /// unspecified until written.
+    pub fn alloc_input(&self) -> Result<InputBuffer<'_>, Error> {
+        alloc(self.raw())
+    }
+
+    /// Submits a frame for encoding.
+    ///
+    /// Ownership of the buffer passes to VDO. Returns [`Error::InvalidArgument`] if the buffer
+    /// was allocated by another encoder.
+    pub fn encode(&self, input: InputBuffer<'_>) -> Result<(), Error> {
+        self.encode_inner(input, None)
+    }
+
+    /// Like [`encode()`](Encoder::encode) but with settings that apply to this frame only.
+    pub fn encode_with(&self, input: InputBuffer<'_>, settings: &Map) -> Result<(), Error> {
+        self.encode_inner(input, Some(settings))
+    }
+
+    fn encode_inner(&self, input: InputBuffer<'_>, settings: Option<&Map>) -> Result<(), Error> {
+        if input.stream != self.raw() {
+            return Err(Error::InvalidArgument(
+                "input buffer was allocated by another encoder",
+            ));
+        }
+        let stream = input.stream;
+        let mut raw = input.into_raw();
+        let settings = settings.map_or(std::ptr::null_mut(), Map::as_ptr);
+        let (success, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_stream_encode, self.raw(), &mut raw, settings) };
+        // VDO clears the pointer when it takes the buffer, so a buffer that is left after a
+        // failure is still ours to release.
+        if success == glib_sys::GFALSE && !raw.is_null() {
+            drop(InputBuffer {
+                raw,
+                stream,
+                _marker: PhantomData,
+            });
+        }
+        crate::into_unit(success, maybe_error)
+    }
+
+    /// Blocks until an encoded frame is available and returns it.
+    ///
+    /// The buffer is returned to the encoder when dropped, so holding on to more than the
+    /// configured number of [`buffers`](EncoderBuilder::buffers) stalls the encoder.
+    pub fn next_buffer(&self) -> Result<StreamBuffer<'_>, Error> {
+        let mut buffer = self.stream.next_buffer()?;
+        buffer.recycle = true;
+        Ok(buffer)
+    }
+
+    /// Like [`RunningStream::info()`].
+    pub fn info(&self) -> Result<Map, Error> {
+        self.stream.info()
+    }
+
+    /// Like [`RunningStream::force_key_frame()`].
+    pub fn force_key_frame(&self) -> Result<(), Error> {
+        self.stream.force_key_frame()
+    }
+
+    fn raw(&self) -> *mut VdoStream {
+        self.stream.stream.raw
+    }
+}
+
+/// A buffer holding a frame to be encoded.
+///
+/// Borrows from the [`Encoder`] that allocated it and is released when dropped, unless it has
+/// been passed to [`Encoder::encode()`].
+pub struct InputBuffer<'a> {
+    raw: *mut VdoBuffer,
+    stream: *mut VdoStream,
+    _marker: PhantomData<&'a Encoder>,
+}
+
+impl InputBuffer<'_> {
+    pub fn capacity(&self) -> usize {
+        unsafe { vdo_sys::vdo_buffer_get_capacity(self.raw) }
+    }
+
+    /// Returns the whole buffer as a mutable byte slice of
+    /// [`capacity()`](InputBuffer::capacity) bytes.
+    ///
+    /// Remember to [`set_size()`](InputBuffer::set_size) after writing to it.
+    pub fn as_mut_slice(&mut self) -> Result<&mut [u8], Error> {
+        let data = unsafe { vdo_sys::vdo_buffer_get_data(self.raw) };
+        if data.is_null() {
+            return Err(Error::NullPointer);
+        }
+        // SAFETY: The region of capacity bytes is allocated and initialized by VDO and is
+        // exclusively ours until the buffer is passed to the encoder.
+        let slice = unsafe { std::slice::from_raw_parts_mut(data as *mut u8, self.capacity()) };
+        Ok(slice)
+    }
+
+    /// Copies `data` to the start of the buffer and sets the size of the frame accordingly.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let slice = self.as_mut_slice()?;
+        let Some(dst) = slice.get_mut(..data.len()) else {
+            return Err(Error::InvalidArgument("frame does not fit in input buffer"));
+        };
+        dst.copy_from_slice(data);
+        self.set_size(data.len())
+    }
+
+    /// Size of the frame data in bytes.
+    pub fn size(&self) -> usize {
+        unsafe { vdo_sys::vdo_frame_get_size(self.raw) }
+    }
+
+    /// Sets the size of the frame data in bytes.
+    pub fn set_size(&mut self, size: usize) -> Result<(), Error> {
+        if size > self.capacity() {
+            return Err(Error::InvalidArgument("frame does not fit in input buffer"));
+        }
+        unsafe { vdo_sys::vdo_frame_set_size(self.raw, size) };
+        Ok(())
+    }
+
+    /// Timestamp in microseconds, carried over to the encoded frame.
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        unsafe { vdo_sys::vdo_frame_set_timestamp(self.raw, timestamp) };
+    }
+
+    pub fn set_custom_timestamp_us(&mut self, timestamp: i64) {
+        unsafe { vdo_sys::vdo_frame_set_custom_timestamp(self.raw, timestamp) };
+    }
+
+    /// Default: the raw format of the encoder input, as decided by VDO.
+    pub fn set_frame_type(&mut self, frame_type: VdoFrameType) {
+        unsafe { vdo_sys::vdo_frame_set_frame_type(self.raw, frame_type) };
+    }
+
+    fn into_raw(self) -> *mut VdoBuffer {
+        let raw = self.raw;
+        std::mem::forget(self);
+        raw
+    }
+}
+
+impl Drop for InputBuffer<'_> {
+    fn drop(&mut self) {
+        let (success, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_stream_buffer_unref, self.stream, &mut self.raw) };
+        if success == glib_sys::GFALSE || maybe_error.is_some() {
+            match maybe_error {
+                Some(err) => log::error!("Failed to unref input buffer: {}", err),
+                None => log::error!("Failed to unref input buffer (no GError details)"),
+            }
+        }
+    }
+}
+
+fn alloc<'a>(stream: *mut VdoStream) -> Result<InputBuffer<'a>, Error> {
+    let (raw, maybe_error) = unsafe {
+        try_func!(
+            vdo_sys::vdo_stream_buffer_alloc,
+            stream,
+            std::ptr::null_mut()
+        )
+    };
+    if raw.is_null() {
+        return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+    }
+    Ok(InputBuffer {
+        raw,
+        stream,
+        _marker: PhantomData,
+    })
+}
+
+pub(crate) fn enqueue(stream: *mut VdoStream, buffer: *mut VdoBuffer) -> Result<(), Error> {
+    let (success, maybe_error) =
+        unsafe { try_func!(vdo_sys::vdo_stream_buffer_enqueue, stream, buffer) };
+    crate::into_unit(success, maybe_error)
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn rejects_raw_formats() {
+        let err = EncoderBuilder::new(640, 360)
+            .format(VdoFormat::VDO_FORMAT_YUV)
+            .validate()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)), "{err:?}");
+    }
+
+    #[test]
+    fn rejects_empty_resolution() {
+        let err = EncoderBuilder::new(0, 360).validate().unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)), "{err:?}");
+    }
+
+    #[test]
+    fn rejects_zero_buffers() {
+        let err = EncoderBuilder::new(640, 360)
+            .buffers(0)
+            .validate()
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)), "{err:?}");
+    }
+
+    #[test]
+    fn accepts_encoded_formats() {
+        for format in [
+            VdoFormat::VDO_FORMAT_H264,
+            VdoFormat::VDO_FORMAT_H265,
+            VdoFormat::VDO_FORMAT_JPEG,
+        ] {
+            EncoderBuilder::new(640, 360)
+                .format(format)
+                .validate()
+                .unwrap();
+        }
+    }
+}
+
+// These tests require the hardware encoder and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_synthetic_jpeg() -> Result<(), Error> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let encoder = EncoderBuilder::new(640, 360)
+            .format(VdoFormat::VDO_FORMAT_JPEG)
+            .build()?;
+        let mut input = encoder.alloc_input()?;
+        input.write(&vec![128; 640 * 360 * 3 / 2])?;
+        encoder.encode(input)?;
+        let output = encoder.next_buffer()?;
+        assert_eq!(output.frame_type(), VdoFrameType::VDO_FRAME_TYPE_JPEG);
+        assert_eq!(&output.data()?[..2], &[0xFF, 0xD8]);
+        Ok(())
+    }
+
+    #[test]
+    fn recycles_output_buffers() -> Result<(), Error> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let encoder = EncoderBuilder::new(640, 360).buffers(2).build()?;
+        for timestamp in 0..5 {
+            let mut input = encoder.alloc_input()?;
+            input.write(&vec![16; 640 * 360 * 3 / 2])?;
+            input.set_timestamp(timestamp * 33_333);
+            encoder.encode(input)?;
+            assert!(encoder.next_buffer()?.size() > 0);
+        }
+        Ok(())
+    }
+}
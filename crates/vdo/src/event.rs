@@ -0,0 +1,216 @@
+//! Lifecycle events of running streams.
+
+use std::{io, os::fd::RawFd};
+
+use vdo_sys::VdoStreamEvent;
+
+use crate::{Error, Map, RunningStream, Stream, StreamBuffer};
+
+/// An event that changes the state of a running stream.
+///
+/// Events are delivered alongside frames by [`RunningStream::next_item()`] and can also be
+/// polled using [`RunningStream::poll_event()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The stream was started again after having been stopped.
+    Resumed,
+    /// The stream was stopped by the system, e.g. because the capture mode changed. No frames
+    /// are delivered until it is resumed, which may never happen.
+    Stopped,
+    /// The stream was closed by the system and must be recreated to receive more frames.
+    Closed,
+    /// The resources backing the stream changed, e.g. because the settings of the channel were
+    /// changed. Frames may have a different layout from now on.
+    SettingsChanged,
+    /// The stream uses more than its share of the memory for buffering. If the quota is `hard`,
+    /// frames are being dropped.
+    QuotaExceeded { hard: bool },
+    /// An event without a dedicated variant.
+    Other(VdoStreamEvent),
+}
+
+impl StreamEvent {
+    pub fn from_raw(event: VdoStreamEvent) -> Self {
+        match event {
+            VdoStreamEvent::VDO_STREAM_EVENT_STARTED => Self::Resumed,
+            VdoStreamEvent::VDO_STREAM_EVENT_STOPPED => Self::Stopped,
+            VdoStreamEvent::VDO_STREAM_EVENT_CLOSED => Self::Closed,
+            VdoStreamEvent::VDO_STREAM_EVENT_RESOURCE => Self::SettingsChanged,
+            VdoStreamEvent::VDO_STREAM_EVENT_QUOTA_SOFT => Self::QuotaExceeded { hard: false },
+            VdoStreamEvent::VDO_STREAM_EVENT_QUOTA_HARD => Self::QuotaExceeded { hard: true },
+            other => Self::Other(other),
+        }
+    }
+
+    /// Returns `true` if no more frames should be expected from the stream.
+    ///
+    /// A stopped stream may in theory be resumed, but in practice the system stops streams when
+    /// they cannot continue with their current settings, so it is usually best to recreate it.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, Self::Stopped | Self::Closed)
+    }
+}
+
+/// A frame or an event from a [`RunningStream`].
+pub enum StreamItem<'a> {
+    Frame(StreamBuffer<'a>),
+    Event(StreamEvent),
+}
+
+impl Stream {
+    pub(crate) fn fd(&self) -> Result<RawFd, Error> {
+        let (fd, maybe_error) = unsafe { try_func!(vdo_sys::vdo_stream_get_fd, self.raw) };
+        if fd < 0 {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        Ok(fd)
+    }
+
+    pub(crate) fn event_fd(&self) -> Result<RawFd, Error> {
+        let (fd, maybe_error) = unsafe { try_func!(vdo_sys::vdo_stream_get_event_fd, self.raw) };
+        if fd < 0 {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        Ok(fd)
+    }
+
+    pub(crate) fn next_event(&self) -> Result<StreamEvent, Error> {
+        let (map_raw, maybe_error) = unsafe { try_func!(vdo_sys::vdo_stream_get_event, self.raw) };
+        if map_raw.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        // SAFETY: map_raw is non-null and freshly returned by VDO with ownership transferred.
+        let map = unsafe { Map::from_raw(map_raw) };
+        Ok(StreamEvent::from_raw(VdoStreamEvent(map.get_u32(
+            c"event",
+            VdoStreamEvent::VDO_STREAM_EVENT_NONE.0,
+        ))))
+    }
+}
+
+impl RunningStream {
+    /// Blocks until the stream receives an event and returns it.
+    pub fn next_event(&self) -> Result<StreamEvent, Error> {
+        self.stream.next_event()
+    }
+
+    /// Returns the next event if one is pending, without blocking.
+    pub fn poll_event(&self) -> Result<Option<StreamEvent>, Error> {
+        let [pending] = poll_readable([self.stream.event_fd()?], 0)?;
+        if pending {
+            self.next_event().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Blocks until a frame or an event is available and returns it.
+    ///
+    /// Events take precedence over frames, so a [`StreamEvent::Stopped`] is seen before the
+    /// error that retrieving a frame from a stopped stream would result in.
+    pub fn next_item(&self) -> Result<StreamItem<'_>, Error> {
+        let fds = [self.stream.event_fd()?, self.stream.fd()?];
+        loop {
+            match poll_readable(fds, -1)? {
+                [true, _] => return self.next_event().map(StreamItem::Event),
+                [false, true] => return self.next_buffer().map(StreamItem::Frame),
+                [false, false] => continue,
+            }
+        }
+    }
+}
+
+/// Waits until at least one of `fds` is readable, or `timeout_ms` has passed, and returns which
+/// of them are readable.
+///
+/// A negative timeout waits indefinitely.
+pub(crate) fn poll_readable<const N: usize>(
+    fds: [RawFd; N],
+    timeout_ms: i32,
+) -> io::Result<[bool; N]> {
+    let mut pollfds = fds.map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+    loop {
+        let n = unsafe { libc::poll(pollfds.as_mut_ptr(), N as libc::nfds_t, timeout_ms) };
+        if n >= 0 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(pollfds.map(|p| p.revents & (libc::POLLIN | libc::POLLHUP | libc::POLLERR) != 0))
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    use super::*;
+
+    #[test]
+    fn maps_raw_events() {
+        assert_eq!(
+            StreamEvent::from_raw(VdoStreamEvent::VDO_STREAM_EVENT_STARTED),
+            StreamEvent::Resumed
+        );
+        assert_eq!(
+            StreamEvent::from_raw(VdoStreamEvent::VDO_STREAM_EVENT_QUOTA_HARD),
+            StreamEvent::QuotaExceeded { hard: true }
+        );
+        assert_eq!(
+            StreamEvent::from_raw(VdoStreamEvent::VDO_STREAM_EVENT_BUFFERING),
+            StreamEvent::Other(VdoStreamEvent::VDO_STREAM_EVENT_BUFFERING)
+        );
+    }
+
+    #[test]
+    fn only_stopped_and_closed_are_terminal() {
+        assert!(StreamEvent::Stopped.is_terminal());
+        assert!(StreamEvent::Closed.is_terminal());
+        assert!(!StreamEvent::SettingsChanged.is_terminal());
+        assert!(!StreamEvent::QuotaExceeded { hard: true }.is_terminal());
+    }
+
+    #[test]
+    fn polls_readiness_of_each_descriptor() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        assert_eq!(poll_readable([read.as_raw_fd()], 0).unwrap(), [false]);
+        assert_eq!(
+            unsafe { libc::write(write.as_raw_fd(), b"x".as_ptr().cast(), 1) },
+            1
+        );
+        assert_eq!(
+            poll_readable([read.as_raw_fd(), write.as_raw_fd()], -1).unwrap(),
+            [true, false]
+        );
+    }
+}
+
+// These tests require a camera and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StreamBuilder, VdoFormat};
+
+    #[test]
+    fn delivers_frames_alongside_events() -> Result<(), Error> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let running = StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_YUV)
+            .build()?
+            .start()?;
+        let frames = (0..10)
+            .filter(|_| matches!(running.next_item(), Ok(StreamItem::Frame(_))))
+            .count();
+        assert!(frames > 0);
+        Ok(())
+    }
+}
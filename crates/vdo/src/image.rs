@@ -0,0 +1,529 @@
+//! Typed views of frames in raw formats.
+//!
+//! An [`ImageLayout`] describes the frames of a stream and is derived from its info map once.
+//! It is then used to view the data of each frame as an [`Image`], whose planes can be passed to
+//! other APIs along with their pitch, cropped, or converted to RGB.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use vdo::{image::ImageLayout, Resolution, StreamBuilder, VdoFormat};
+//!
+//! let stream = StreamBuilder::new()
+//!     .format(VdoFormat::VDO_FORMAT_YUV)
+//!     .resolution(Resolution::Exact {
+//!         width: 1920,
+//!         height: 1080,
+//!     })
+//!     .build()?
+//!     .start()?;
+//! let layout = ImageLayout::from_info(&stream.info()?)?;
+//! let buffer = stream.next_buffer()?;
+//! let image = buffer.image(&layout)?;
+//! let center = image.crop(480, 270, 960, 540)?;
+//! let rgb = center.to_rgb_resized(300, 300);
+//! assert_eq!(rgb.data().len(), 300 * 300 * 3);
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use vdo_sys::VdoFormat;
+
+use crate::{Error, Map, StreamBuffer};
+
+/// The layout of pixels in memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    /// YUV 4:2:0 with a Y plane followed by a plane of interleaved U and V samples.
+    Nv12,
+    /// Like [`PixelFormat::Nv12`] but with V before U.
+    Nv21,
+    /// Only the Y plane.
+    Y800,
+    /// Interleaved 8 bit R, G and B samples.
+    Rgb,
+}
+
+impl PixelFormat {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Nv12 | Self::Nv21 | Self::Y800 => 1,
+            Self::Rgb => 3,
+        }
+    }
+}
+
+/// The dimensions and memory layout of the frames of a stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageLayout {
+    pub format: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+    /// The distance in bytes between the starts of consecutive rows, in all planes.
+    pub pitch: u32,
+}
+
+impl ImageLayout {
+    /// Derives the layout from the info map of a stream, as returned by
+    /// [`Stream::info()`](crate::Stream::info).
+    ///
+    /// YUV streams without a `subformat` are assumed to be NV12, and streams without a `pitch`
+    /// are assumed to have no padding.
+    pub fn from_info(info: &Map) -> Result<Self, Error> {
+        let format = match VdoFormat(info.get_u32(c"format", 0) as i32) {
+            VdoFormat::VDO_FORMAT_YUV => match info.get_string(c"subformat") {
+                None => PixelFormat::Nv12,
+                Some(s) if s.to_bytes().eq_ignore_ascii_case(b"nv12") => PixelFormat::Nv12,
+                Some(s) if s.to_bytes().eq_ignore_ascii_case(b"nv21") => PixelFormat::Nv21,
+                Some(s) if s.to_bytes().eq_ignore_ascii_case(b"y800") => PixelFormat::Y800,
+                Some(_) => return Err(Error::Image("unsupported YUV subformat")),
+            },
+            VdoFormat::VDO_FORMAT_RGB => PixelFormat::Rgb,
+            _ => return Err(Error::Image("unsupported format")),
+        };
+        let width = info.get_u32(c"width", 0);
+        let height = info.get_u32(c"height", 0);
+        if width == 0 || height == 0 {
+            return Err(Error::Image("missing width or height"));
+        }
+        let pitch = match info.get_u32(c"pitch", 0) {
+            0 => width * format.bytes_per_pixel() as u32,
+            pitch => pitch,
+        };
+        Ok(Self {
+            format,
+            width,
+            height,
+            pitch,
+        })
+    }
+
+    /// Views `data` as an image with this layout.
+    pub fn view<'a>(&self, data: &'a [u8]) -> Result<Image<'a>, Error> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let pitch = self.pitch as usize;
+        let row = width * self.format.bytes_per_pixel();
+        if width == 0 || height == 0 || pitch < row {
+            return Err(Error::Image("the pitch is smaller than a row"));
+        }
+        let first = Plane::new(data, 0, row, height, pitch)?;
+        let second = match self.format {
+            PixelFormat::Nv12 | PixelFormat::Nv21 => Some(Plane::new(
+                data,
+                pitch * height,
+                width.div_ceil(2) * 2,
+                height.div_ceil(2),
+                pitch,
+            )?),
+            PixelFormat::Y800 | PixelFormat::Rgb => None,
+        };
+        Ok(Image {
+            format: self.format,
+            width: self.width,
+            height: self.height,
+            first,
+            second,
+        })
+    }
+}
+
+impl StreamBuffer<'_> {
+    /// Views the frame as an image with the given layout.
+    pub fn image(&self, layout: &ImageLayout) -> Result<Image<'_>, Error> {
+        layout.view(self.data()?)
+    }
+}
+
+/// A borrowed plane of an image.
+#[derive(Clone, Copy, Debug)]
+pub struct Plane<'a> {
+    /// The bytes from the start of the first row to the end of the last row.
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+    pitch: usize,
+}
+
+impl<'a> Plane<'a> {
+    fn new(
+        data: &'a [u8],
+        offset: usize,
+        width: usize,
+        height: usize,
+        pitch: usize,
+    ) -> Result<Self, Error> {
+        let len = pitch * (height - 1) + width;
+        let data = data
+            .get(offset..offset + len)
+            .ok_or(Error::Image("the data is smaller than the layout"))?;
+        Ok(Self {
+            data,
+            width,
+            height,
+            pitch,
+        })
+    }
+
+    /// The number of bytes in a row, excluding padding.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The distance in bytes between the starts of consecutive rows.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// Returns row `y`, excluding padding.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `y` is not less than the height.
+    pub fn row(&self, y: usize) -> &'a [u8] {
+        assert!(y < self.height, "row {y} is out of bounds");
+        &self.data[y * self.pitch..][..self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    /// The bytes from the start of the first row to the end of the last row, including the
+    /// padding between rows, as expected by APIs that take a pointer and a pitch.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let start = y * self.pitch + x;
+        Self {
+            data: &self.data[start..start + self.pitch * (height - 1) + width],
+            width,
+            height,
+            pitch: self.pitch,
+        }
+    }
+}
+
+/// A borrowed view of an image in one of the supported [`PixelFormat`]s.
+#[derive(Clone, Copy, Debug)]
+pub struct Image<'a> {
+    format: PixelFormat,
+    width: u32,
+    height: u32,
+    first: Plane<'a>,
+    second: Option<Plane<'a>>,
+}
+
+impl<'a> Image<'a> {
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The Y plane of a YUV image.
+    pub fn y(&self) -> Option<Plane<'a>> {
+        (self.format != PixelFormat::Rgb).then_some(self.first)
+    }
+
+    /// The plane of interleaved chroma samples of an NV12 or NV21 image.
+    pub fn uv(&self) -> Option<Plane<'a>> {
+        self.second
+    }
+
+    /// The plane of an RGB image.
+    pub fn rgb(&self) -> Option<Plane<'a>> {
+        (self.format == PixelFormat::Rgb).then_some(self.first)
+    }
+
+    /// Returns a view of the rectangle at `x` and `y` of `width` by `height` pixels.
+    ///
+    /// For NV12 and NV21 images, `x` and `y` must be even so that the chroma samples stay
+    /// aligned with the luma samples.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Image<'a>, Error> {
+        if width == 0
+            || height == 0
+            || x.checked_add(width).is_none_or(|r| r > self.width)
+            || y.checked_add(height).is_none_or(|b| b > self.height)
+        {
+            return Err(Error::Image("the crop is out of bounds"));
+        }
+        if self.second.is_some() && (x % 2 != 0 || y % 2 != 0) {
+            return Err(Error::Image(
+                "the crop of a YUV 4:2:0 image must start at even coordinates",
+            ));
+        }
+        let (x, y, w, h) = (x as usize, y as usize, width as usize, height as usize);
+        let bpp = self.format.bytes_per_pixel();
+        Ok(Image {
+            format: self.format,
+            width,
+            height,
+            first: self.first.crop(x * bpp, y, w * bpp, h),
+            second: self
+                .second
+                .map(|uv| uv.crop(x, y / 2, w.div_ceil(2) * 2, h.div_ceil(2))),
+        })
+    }
+
+    /// Converts the image to RGB.
+    pub fn to_rgb(&self) -> RgbImage {
+        self.to_rgb_resized(self.width, self.height)
+    }
+
+    /// Converts the image to RGB and scales it to `width` by `height` pixels.
+    ///
+    /// Scaling uses the nearest pixel, which is fast and suitable for downscaling e.g. the
+    /// input of a model, but may produce aliasing. YUV is converted using BT.601 full range, as
+    /// used by JPEG.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` or `height` is zero.
+    pub fn to_rgb_resized(&self, width: u32, height: u32) -> RgbImage {
+        assert!(width > 0 && height > 0, "the size must not be zero");
+        let xs: Vec<usize> = (0..width).map(|x| nearest(x, width, self.width)).collect();
+        let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+        for y in 0..height {
+            let sy = nearest(y, height, self.height);
+            let row = self.first.row(sy);
+            let chroma = self.second.map(|uv| uv.row(sy / 2));
+            for &sx in &xs {
+                let rgb = match (self.format, chroma) {
+                    (PixelFormat::Nv12, Some(uv)) => {
+                        yuv_to_rgb(row[sx], uv[sx & !1], uv[(sx & !1) + 1])
+                    }
+                    (PixelFormat::Nv21, Some(vu)) => {
+                        yuv_to_rgb(row[sx], vu[(sx & !1) + 1], vu[sx & !1])
+                    }
+                    (PixelFormat::Rgb, _) => [row[3 * sx], row[3 * sx + 1], row[3 * sx + 2]],
+                    _ => [row[sx]; 3],
+                };
+                data.extend_from_slice(&rgb);
+            }
+        }
+        RgbImage {
+            width,
+            height,
+            data,
+        }
+    }
+}
+
+/// Returns the source coordinate closest to the center of destination pixel `i`.
+fn nearest(i: u32, destination: u32, source: u32) -> usize {
+    ((2 * u64::from(i) + 1) * u64::from(source) / (2 * u64::from(destination))) as usize
+}
+
+/// Converts a full range BT.601 YUV sample to RGB using 16 bit fixed point arithmetic.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let y = i32::from(y) << 16;
+    let u = i32::from(u) - 128;
+    let v = i32::from(v) - 128;
+    let clamp = |c: i32| ((c + (1 << 15)) >> 16).clamp(0, 255) as u8;
+    [
+        clamp(y + 91881 * v),
+        clamp(y - 22554 * u - 46802 * v),
+        clamp(y + 116130 * u),
+    ]
+}
+
+/// An owned image with interleaved 8 bit R, G and B samples and no padding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl RgbImage {
+    /// Creates an image from `data`, or returns `None` if its length does not match the size.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        (data.len() == width as usize * height as usize * 3).then_some(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Views the image, e.g. to crop or scale it further.
+    pub fn as_image(&self) -> Image<'_> {
+        ImageLayout {
+            format: PixelFormat::Rgb,
+            width: self.width,
+            height: self.height,
+            pitch: self.width * 3,
+        }
+        .view(&self.data)
+        .expect("the data matches the layout")
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    /// A 4x4 NV12 image with a pitch of 6, where Y is `10 * y + x` and U and V identify the
+    /// chroma block.
+    fn nv12() -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in 0..4 {
+            data.extend((0..4).map(|x| 10 * y + x));
+            data.extend([0xEE, 0xEE]);
+        }
+        for y in 0..2 {
+            data.extend((0..2).flat_map(|x| [100 + 10 * y + x, 200 + 10 * y + x]));
+            data.extend([0xEE, 0xEE]);
+        }
+        data
+    }
+
+    fn layout(format: PixelFormat, width: u32, height: u32, pitch: u32) -> ImageLayout {
+        ImageLayout {
+            format,
+            width,
+            height,
+            pitch,
+        }
+    }
+
+    #[test]
+    fn nv12_planes_skip_padding() {
+        let data = nv12();
+        let image = layout(PixelFormat::Nv12, 4, 4, 6).view(&data).unwrap();
+        let y = image.y().unwrap();
+        assert_eq!(y.row(3), &[30, 31, 32, 33]);
+        assert_eq!(y.rows().count(), 4);
+        assert_eq!(y.as_bytes().len(), 6 * 3 + 4);
+        let uv = image.uv().unwrap();
+        assert_eq!((uv.width(), uv.height(), uv.pitch()), (4, 2, 6));
+        assert_eq!(uv.row(1), &[110, 210, 111, 211]);
+        assert!(image.rgb().is_none());
+    }
+
+    #[test]
+    fn short_data_is_rejected() {
+        let data = nv12();
+        assert!(layout(PixelFormat::Nv12, 4, 4, 6)
+            .view(&data[..data.len() - 3])
+            .is_err());
+        assert!(layout(PixelFormat::Nv12, 4, 4, 3).view(&data).is_err());
+        assert!(layout(PixelFormat::Rgb, 2, 2, 6).view(&[0; 11]).is_err());
+    }
+
+    #[test]
+    fn crop_offsets_both_planes() {
+        let data = nv12();
+        let image = layout(PixelFormat::Nv12, 4, 4, 6).view(&data).unwrap();
+        let cropped = image.crop(2, 2, 2, 2).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (2, 2));
+        let y = cropped.y().unwrap();
+        assert_eq!(y.rows().collect::<Vec<_>>(), vec![&[22, 23][..], &[32, 33]]);
+        assert_eq!(cropped.uv().unwrap().row(0), &[111, 211]);
+        assert!(image.crop(1, 0, 2, 2).is_err());
+        assert!(image.crop(2, 2, 3, 2).is_err());
+        assert!(image.crop(0, 0, 0, 2).is_err());
+    }
+
+    #[test]
+    fn rgb_is_cropped_and_scaled() {
+        let data: Vec<u8> = (0..4 * 2 * 3).collect();
+        let image = layout(PixelFormat::Rgb, 4, 2, 12).view(&data).unwrap();
+        let cropped = image.crop(1, 1, 2, 1).unwrap();
+        assert_eq!(cropped.rgb().unwrap().row(0), &[15, 16, 17, 18, 19, 20]);
+        let scaled = image.to_rgb_resized(2, 1);
+        // The centers of the destination pixels are closest to source pixels 1 and 3 of row 1.
+        assert_eq!(scaled.data(), &[15, 16, 17, 21, 22, 23]);
+        assert_eq!(scaled.as_image().to_rgb(), scaled);
+    }
+
+    #[test]
+    fn yuv_is_converted_to_rgb() {
+        assert_eq!(yuv_to_rgb(0, 128, 128), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(255, 128, 128), [255, 255, 255]);
+        assert_eq!(yuv_to_rgb(76, 85, 255), [254, 0, 0]);
+        assert_eq!(yuv_to_rgb(150, 44, 21), [0, 255, 1]);
+        assert_eq!(yuv_to_rgb(29, 255, 107), [0, 0, 254]);
+    }
+
+    #[test]
+    fn nv21_swaps_chroma() {
+        let mut data = vec![128; 4];
+        data.extend([85, 255]);
+        let nv12 = layout(PixelFormat::Nv12, 2, 2, 2).view(&data).unwrap();
+        let nv21 = layout(PixelFormat::Nv21, 2, 2, 2).view(&data).unwrap();
+        let nv12 = nv12.to_rgb();
+        let nv21 = nv21.to_rgb();
+        assert!(nv12.data()[0] > nv12.data()[2]);
+        assert!(nv21.data()[0] < nv21.data()[2]);
+        assert_eq!(nv12.data().len(), 2 * 2 * 3);
+    }
+
+    #[test]
+    fn gray_is_converted_to_rgb() {
+        let image = layout(PixelFormat::Y800, 2, 1, 2).view(&[7, 9]).unwrap();
+        assert_eq!(image.to_rgb().data(), &[7, 7, 7, 9, 9, 9]);
+        assert!(image.uv().is_none());
+    }
+
+    #[test]
+    fn rgb_image_requires_matching_size() {
+        assert!(RgbImage::new(2, 2, vec![0; 12]).is_some());
+        assert!(RgbImage::new(2, 2, vec![0; 11]).is_none());
+    }
+}
+
+// These tests require a camera and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Resolution, StreamBuilder};
+
+    #[test]
+    fn yuv_frames_can_be_viewed() -> Result<(), Error> {
+        let stream = StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_YUV)
+            .resolution(Resolution::Exact {
+                width: 640,
+                height: 360,
+            })
+            .build()?
+            .start()?;
+        let layout = ImageLayout::from_info(&stream.info()?)?;
+        assert_eq!((layout.width, layout.height), (640, 360));
+        assert!(layout.pitch >= 640);
+        let buffer = stream.next_buffer()?;
+        let image = buffer.image(&layout)?;
+        let rgb = image.crop(320, 180, 320, 180)?.to_rgb_resized(32, 18);
+        assert_eq!(rgb.data().len(), 32 * 18 * 3);
+        Ok(())
+    }
+}
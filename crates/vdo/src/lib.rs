@@ -41,37 +41,77 @@
 //! drop(running);
 //! ```
 //!
+//! [`nonblock`] provides an async version of [`RunningStream`] that yields frames as a
+//! `Stream`. Requires the `async` feature to be active.
+//!
+//! [`encode`] provides an encoder for frames supplied by the application, e.g. composited or
+//! synthetic images.
+//!
+//! `synthetic` provides an in-process implementation of VDO that allows applications to be tested
+//! on hosts without a camera. Requires the `synthetic` feature to be active.
+//!
 //! # Known Issues
 //!
 //! - Image rotation may vary between platforms. Check the `rotation` property in stream info.
 //! - Some formats (RGB, PLANAR_RGB) may produce upside-down images on certain platforms.
 
-mod map;
+pub mod annexb;
+pub mod clock;
+pub mod image;
+pub mod map;
+pub mod mp4;
+pub mod rtp;
+#[cfg(feature = "synthetic")]
+pub mod synthetic;
+#[cfg(all(
+    feature = "synthetic",
+    not(any(target_arch = "x86_64", target_os = "macos"))
+))]
+compile_error!("The `synthetic` feature replaces libvdo and must not be enabled for devices");
 use std::{
     fmt::{Debug, Display},
-    ptr,
+    marker::PhantomData,
+    os::fd::BorrowedFd,
+    sync::Arc,
 };
 
+pub use channel::Channel;
+pub use event::{StreamEvent, StreamItem};
 use glib_sys::GError;
 use gobject_sys::{g_object_unref, GObject};
 pub use map::{CStringPtr, Map};
+pub use restart::RestartingStream;
+pub use snapshot::{snapshot, Snapshot};
 use vdo_sys::{VdoBuffer, VdoBufferStrategy, VdoStream};
-pub use vdo_sys::{VdoFormat, VdoFrameType, VdoRateControlMode, VdoRateControlPriority};
+pub use vdo_sys::{
+    VdoFormat, VdoFrameType, VdoRateControlMode, VdoRateControlPriority, VdoZipStreamProfile,
+};
 
 /// Macro for calling VDO functions that take a GError** parameter.
 /// Returns a tuple of `(result, Option<Error>)`.
 macro_rules! try_func {
     ($func:path, $($arg:expr),+ $(,)?) => {{
-        let mut error: *mut GError = ptr::null_mut();
+        let mut error: *mut glib_sys::GError = std::ptr::null_mut();
         let success = $func($( $arg ),+, &mut error);
         if error.is_null() {
             (success, None)
         } else {
-            (success, Some(Error::Vdo(VdoError::from_gerror(error))))
+            (
+                success,
+                Some($crate::Error::Vdo($crate::VdoError::from_gerror(error))),
+            )
         }
     }};
 }
 
+mod channel;
+pub mod encode;
+mod event;
+#[cfg(feature = "async")]
+pub mod nonblock;
+mod restart;
+mod snapshot;
+
 /// Error type for VDO operations.
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -81,12 +121,24 @@ pub enum Error {
     NullPointer,
     #[error("Missing error data from VDO library")]
     MissingVdoError,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Invalid bitstream: {0}")]
+    Bitstream(&'static str),
+    #[error("Invalid image: {0}")]
+    Image(&'static str),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(&'static str),
+    /// A JSON value could not be converted to a [`Map`].
+    #[error("{0}")]
+    Json(String),
 }
 
 /// Error from the VDO library.
 pub struct VdoError {
     code: i32,
     message: String,
+    explanation: Option<String>,
 }
 
 impl VdoError {
@@ -95,6 +147,7 @@ impl VdoError {
             return VdoError {
                 code: 0,
                 message: String::new(),
+                explanation: None,
             };
         }
 
@@ -116,6 +169,7 @@ impl VdoError {
         VdoError {
             code: g_error.code,
             message,
+            explanation: None,
         }
     }
 
@@ -157,11 +211,21 @@ impl VdoError {
     pub fn message(&self) -> &str {
         &self.message
     }
+
+    /// Describes what the channel supports when [`StreamBuilder::build()`] fails because of the
+    /// configuration of the stream.
+    pub fn explanation(&self) -> Option<&str> {
+        self.explanation.as_deref()
+    }
 }
 
 impl Display for VdoError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({}): {}", self.code_name(), self.code, self.message)
+        write!(f, "{} ({}): {}", self.code_name(), self.code, self.message)?;
+        if let Some(explanation) = &self.explanation {
+            write!(f, " ({explanation})")?;
+        }
+        Ok(())
     }
 }
 
@@ -171,6 +235,7 @@ impl Debug for VdoError {
             .field("code", &self.code)
             .field("code_name", &self.code_name())
             .field("message", &self.message)
+            .field("explanation", &self.explanation)
             .finish()
     }
 }
@@ -211,6 +276,12 @@ pub struct StreamBuilder {
     resolution: Resolution,
     framerate: u32,
     gop_length: u32,
+    bitrate: u32,
+    rate_control_mode: VdoRateControlMode,
+    rate_control_priority: VdoRateControlPriority,
+    zipstream_profile: VdoZipStreamProfile,
+    compression: Option<u32>,
+    rotation: Option<u32>,
 }
 
 impl Default for StreamBuilder {
@@ -222,6 +293,12 @@ impl Default for StreamBuilder {
             resolution: Resolution::Native,
             framerate: 0,
             gop_length: 0,
+            bitrate: 0,
+            rate_control_mode: VdoRateControlMode::VDO_RATE_CONTROL_MODE_NONE,
+            rate_control_priority: VdoRateControlPriority::VDO_RATE_CONTROL_PRIORITY_NONE,
+            zipstream_profile: VdoZipStreamProfile::VDO_ZIPSTREAM_PROFILE_NONE,
+            compression: None,
+            rotation: None,
         }
     }
 }
@@ -246,11 +323,32 @@ impl StreamBuilder {
     }
 
     /// Default: [`Resolution::Native`]
+    ///
+    /// See [`StreamBuilder::fit_resolution()`] to use a resolution that the channel supports.
     pub fn resolution(mut self, resolution: Resolution) -> Self {
         self.resolution = resolution;
         self
     }
 
+    /// Replaces the resolution with one that the channel supports for the format.
+    ///
+    /// An exact resolution is kept if it is supported. Otherwise the smallest supported
+    /// resolution that is at least as large in both dimensions is used, or the largest supported
+    /// resolution if none is. [`Resolution::Native`] is always kept.
+    ///
+    /// Returns an error if the [`Channel`] cannot be queried or supports no resolution for the
+    /// format.
+    pub fn fit_resolution(mut self) -> Result<Self, Error> {
+        let Resolution::Exact { width, height } = self.resolution else {
+            return Ok(self);
+        };
+        let supported = Channel::get(self.channel)?.resolutions(Some(self.format))?;
+        self.resolution = channel::fit_resolution(&supported, width, height).ok_or(
+            Error::InvalidArgument("the channel supports no resolution for the format"),
+        )?;
+        Ok(self)
+    }
+
     /// If 0, the camera's default framerate is used.
     pub fn framerate(mut self, framerate: u32) -> Self {
         self.framerate = framerate;
@@ -275,11 +373,120 @@ impl StreamBuilder {
         self
     }
 
+    /// Target bitrate in bits per second. If 0 (default), the encoder decides.
+    ///
+    /// Only valid for H.264 and H.265. Required by the constant, maximum and average
+    /// [rate control modes](StreamBuilder::rate_control_mode), where it is the target, the
+    /// upper bound and the long-term average respectively.
+    pub fn bitrate(mut self, bits_per_second: u32) -> Self {
+        self.bitrate = bits_per_second;
+        self
+    }
+
+    /// Default: `VDO_RATE_CONTROL_MODE_NONE`, i.e. the default of the channel.
+    ///
+    /// Only valid for H.264 and H.265.
+    pub fn rate_control_mode(mut self, mode: VdoRateControlMode) -> Self {
+        self.rate_control_mode = mode;
+        self
+    }
+
+    /// What to sacrifice when the bitrate cannot be met.
+    ///
+    /// Default: `VDO_RATE_CONTROL_PRIORITY_NONE`, i.e. the default of the channel. Only valid for
+    /// H.264 and H.265.
+    pub fn rate_control_priority(mut self, priority: VdoRateControlPriority) -> Self {
+        self.rate_control_priority = priority;
+        self
+    }
+
+    /// Zipstream reduces the bitrate by lowering the quality of regions without motion or
+    /// details.
+    ///
+    /// Default: `VDO_ZIPSTREAM_PROFILE_NONE`, i.e. the default of the channel. Only valid for
+    /// H.264 and H.265.
+    pub fn zipstream_profile(mut self, profile: VdoZipStreamProfile) -> Self {
+        self.zipstream_profile = profile;
+        self
+    }
+
+    /// Compression level from 0 (highest quality) to 100 (smallest frames).
+    ///
+    /// If not set, the default of the channel is used. Only valid for encoded formats.
+    pub fn compression(mut self, compression: u32) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Rotation of the image in degrees; one of 0, 90, 180 or 270.
+    ///
+    /// If not set, the rotation of the channel is used. Note that a rotation of 90 or 270 degrees
+    /// swaps the width and height of the frames.
+    pub fn rotation(mut self, degrees: u32) -> Self {
+        self.rotation = Some(degrees);
+        self
+    }
+
+    /// Checks the combination of options that VDO would otherwise reject with a less specific
+    /// error, or silently ignore.
+    fn validate(&self) -> std::result::Result<(), Error> {
+        let is_h26x =
+            [VdoFormat::VDO_FORMAT_H264, VdoFormat::VDO_FORMAT_H265].contains(&self.format);
+        let is_encoded = is_h26x || self.format == VdoFormat::VDO_FORMAT_JPEG;
+        let uses_rate_control = self.bitrate > 0
+            || self.rate_control_mode != VdoRateControlMode::VDO_RATE_CONTROL_MODE_NONE
+            || self.rate_control_priority != VdoRateControlPriority::VDO_RATE_CONTROL_PRIORITY_NONE;
+        if uses_rate_control && !is_h26x {
+            return Err(Error::InvalidArgument(
+                "bitrate and rate control require H.264 or H.265",
+            ));
+        }
+        if self.bitrate == 0
+            && [
+                VdoRateControlMode::VDO_RATE_CONTROL_MODE_CBR,
+                VdoRateControlMode::VDO_RATE_CONTROL_MODE_MBR,
+                VdoRateControlMode::VDO_RATE_CONTROL_MODE_ABR,
+            ]
+            .contains(&self.rate_control_mode)
+        {
+            return Err(Error::InvalidArgument(
+                "CBR, MBR and ABR rate control require a bitrate",
+            ));
+        }
+        if self.zipstream_profile != VdoZipStreamProfile::VDO_ZIPSTREAM_PROFILE_NONE && !is_h26x {
+            return Err(Error::InvalidArgument("Zipstream requires H.264 or H.265"));
+        }
+        if let Some(compression) = self.compression {
+            if !is_encoded {
+                return Err(Error::InvalidArgument(
+                    "compression requires an encoded format",
+                ));
+            }
+            if compression > 100 {
+                return Err(Error::InvalidArgument(
+                    "compression must be between 0 and 100",
+                ));
+            }
+        }
+        if let Some(rotation) = self.rotation {
+            if ![0, 90, 180, 270].contains(&rotation) {
+                return Err(Error::InvalidArgument(
+                    "rotation must be 0, 90, 180 or 270 degrees",
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Builds the stream.
     ///
     /// Returns an error if the stream could not be created (e.g., invalid format
-    /// for the platform, or camera not available).
+    /// for the platform, or camera not available). When the failure can be
+    /// explained by querying the [`Channel`], [`VdoError::explanation()`]
+    /// describes what the channel supports. Inconsistent options are reported as
+    /// [`Error::InvalidArgument`] before VDO is contacted.
     pub fn build(self) -> std::result::Result<Stream, Error> {
+        self.validate()?;
         let mut map = Map::new();
         map.set_u32(c"channel", self.channel);
         map.set_u32(c"format", self.format.0 as u32);
@@ -293,9 +500,27 @@ impl StreamBuilder {
         if self.gop_length > 0 {
             map.set_u32(c"gop_length", self.gop_length);
         }
+        if self.bitrate > 0 {
+            map.set_u32(c"bitrate", self.bitrate);
+        }
+        if self.rate_control_mode != VdoRateControlMode::VDO_RATE_CONTROL_MODE_NONE {
+            map.set_u32(c"rc.mode", self.rate_control_mode.0 as u32);
+        }
+        if self.rate_control_priority != VdoRateControlPriority::VDO_RATE_CONTROL_PRIORITY_NONE {
+            map.set_u32(c"rc.prio", self.rate_control_priority.0 as u32);
+        }
+        if self.zipstream_profile != VdoZipStreamProfile::VDO_ZIPSTREAM_PROFILE_NONE {
+            map.set_u32(c"zip.profile", self.zipstream_profile.0 as u32);
+        }
+        if let Some(compression) = self.compression {
+            map.set_u32(c"compression", compression);
+        }
+        if let Some(rotation) = self.rotation {
+            map.set_u32(c"rotation", rotation);
+        }
         map.set_u32(c"buffer.count", self.buffer_count);
-        // Always use INFINITE strategy; EXPLICIT is not exposed because it
-        // requires unsafe application-managed buffer allocation.
+        // Always use INFINITE strategy; EXPLICIT requires application-managed
+        // buffers and is only used by `encode::Encoder`, which manages them.
         map.set_u32(
             c"buffer.strategy",
             VdoBufferStrategy::VDO_BUFFER_STRATEGY_INFINITE.0,
@@ -305,7 +530,15 @@ impl StreamBuilder {
             unsafe { try_func!(vdo_sys::vdo_stream_new, map.as_ptr(), None) };
 
         if stream_raw.is_null() {
-            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+            return Err(match maybe_error {
+                Some(Error::Vdo(mut e)) => {
+                    e.explanation =
+                        channel::explain_unsupported(self.channel, self.format, &self.resolution);
+                    Error::Vdo(e)
+                }
+                Some(e) => e,
+                None => Error::MissingVdoError,
+            });
         }
 
         debug_assert!(
@@ -392,6 +625,40 @@ impl Stream {
         self.started = true;
         Ok(RunningStream { stream: self })
     }
+
+    fn set_framerate(&self, framerate: f64) -> std::result::Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_stream_set_framerate, self.raw, framerate) };
+        into_unit(success, maybe_error)
+    }
+
+    fn force_key_frame(&self) -> std::result::Result<(), Error> {
+        let (success, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_stream_force_key_frame, self.raw) };
+        into_unit(success, maybe_error)
+    }
+
+    fn set_settings(&self, settings: &Map) -> std::result::Result<(), Error> {
+        let (success, maybe_error) = unsafe {
+            try_func!(
+                vdo_sys::vdo_stream_set_settings,
+                self.raw,
+                settings.as_ptr()
+            )
+        };
+        into_unit(success, maybe_error)
+    }
+}
+
+fn into_unit(
+    success: glib_sys::gboolean,
+    maybe_error: Option<Error>,
+) -> std::result::Result<(), Error> {
+    if success == glib_sys::GFALSE {
+        return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+    }
+    debug_assert!(maybe_error.is_none(), "VDO reported success AND an error");
+    Ok(())
 }
 
 impl Drop for Stream {
@@ -426,10 +693,49 @@ impl RunningStream {
             return Err(maybe_error.unwrap_or(Error::MissingVdoError));
         }
 
-        Ok(StreamBuffer {
-            raw: buffer_ptr,
-            stream: &self.stream,
-        })
+        // SAFETY: The buffer was just returned by this stream.
+        Ok(unsafe { self.buffer_from_raw(buffer_ptr) })
+    }
+
+    /// # Safety
+    ///
+    /// `raw` must be a buffer from this stream, with ownership transferred to the returned value.
+    pub(crate) unsafe fn buffer_from_raw(&self, raw: *mut VdoBuffer) -> StreamBuffer<'_> {
+        StreamBuffer {
+            raw,
+            stream: self.stream.raw,
+            _owner: None,
+            recycle: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Like [`Stream::info()`].
+    pub fn info(&self) -> std::result::Result<Map, Error> {
+        self.stream.info()
+    }
+
+    /// Changes the framerate of the running stream.
+    ///
+    /// Useful for reducing the load without restarting the stream. The new framerate must not
+    /// exceed that of the channel.
+    pub fn set_framerate(&self, framerate: f64) -> std::result::Result<(), Error> {
+        self.stream.set_framerate(framerate)
+    }
+
+    /// Requests that the next frame of an encoded stream is a key frame.
+    ///
+    /// Useful e.g. when a new client connects and needs a frame to start decoding from.
+    pub fn force_key_frame(&self) -> std::result::Result<(), Error> {
+        self.stream.force_key_frame()
+    }
+
+    /// Updates the settings of the running stream.
+    ///
+    /// Only the keys in `settings` are changed. Which settings can be changed while the stream
+    /// is running depends on the format and the platform.
+    pub fn set_settings(&self, settings: &Map) -> std::result::Result<(), Error> {
+        self.stream.set_settings(settings)
     }
 }
 
@@ -439,7 +745,8 @@ impl RunningStream {
 /// metadata (size, timestamp, frame type, etc.) is accessed directly on this type.
 ///
 /// The buffer borrows from the [`RunningStream`] that produced it and is
-/// automatically unreferenced when dropped.
+/// automatically unreferenced when dropped. Buffers from an async stream instead
+/// keep the stream alive and have the lifetime `'static`.
 ///
 /// # Buffer Validity
 ///
@@ -447,7 +754,13 @@ impl RunningStream {
 /// unreferenced on drop.
 pub struct StreamBuffer<'a> {
     raw: *mut VdoBuffer,
-    stream: &'a Stream,
+    stream: *mut VdoStream,
+    // Keeps the stream alive when the buffer does not borrow it.
+    _owner: Option<Arc<dyn Send + Sync>>,
+    // Returns the buffer to the stream instead of releasing it, as required by the EXPLICIT
+    // buffer strategy.
+    recycle: bool,
+    _marker: PhantomData<&'a Stream>,
 }
 
 impl StreamBuffer<'_> {
@@ -469,10 +782,10 @@ impl StreamBuffer<'_> {
         Ok(slice)
     }
 
-    /// Returns a copy of the frame data, excluding the header if one is present.
+    /// Returns the frame data, excluding the header if one is present.
     ///
     /// Use [`as_slice()`](StreamBuffer::as_slice) for a raw view of the whole buffer.
-    pub fn data_copy(&self) -> std::result::Result<Vec<u8>, Error> {
+    pub fn data(&self) -> std::result::Result<&[u8], Error> {
         let data = unsafe { vdo_sys::vdo_buffer_get_data(self.raw) };
         if data.is_null() {
             return Err(Error::NullPointer);
@@ -488,7 +801,12 @@ impl StreamBuffer<'_> {
         // which is fully initialized at allocation time.
         let slice =
             unsafe { std::slice::from_raw_parts((data as *const u8).add(offset), size - offset) };
-        Ok(slice.to_vec())
+        Ok(slice)
+    }
+
+    /// Returns a copy of the frame data, excluding the header if one is present.
+    pub fn data_copy(&self) -> std::result::Result<Vec<u8>, Error> {
+        self.data().map(<[u8]>::to_vec)
     }
 
     pub fn frame_type(&self) -> VdoFrameType {
@@ -501,10 +819,17 @@ impl StreamBuffer<'_> {
     }
 
     /// Timestamp in microseconds since boot.
+    ///
+    /// Use [`clock::ClockOffset`] to convert it to the wall-clock time.
     pub fn timestamp(&self) -> u64 {
         unsafe { vdo_sys::vdo_frame_get_timestamp(self.raw) }
     }
 
+    /// Timestamp in microseconds since the Unix epoch, or 0 if not provided by VDO.
+    pub fn utc_timestamp(&self) -> u64 {
+        unsafe { vdo_sys::vdo_frame_get_utc_timestamp(self.raw) }
+    }
+
     pub fn custom_timestamp_us(&self) -> i64 {
         unsafe { vdo_sys::vdo_frame_get_custom_timestamp(self.raw) }
     }
@@ -524,20 +849,77 @@ impl StreamBuffer<'_> {
         }
     }
 
+    /// Returns `true` if the frame can be decoded without preceding frames.
+    pub fn is_key(&self) -> bool {
+        unsafe { vdo_sys::vdo_frame_is_key(self.raw) != glib_sys::GFALSE }
+    }
+
+    /// Returns a copy of the extra information attached to the frame, or `None` if there is none.
+    ///
+    /// The content depends on the platform and the stream settings, and may e.g. include
+    /// information about the exposure and the region of interest.
+    pub fn extra_info(&self) -> Option<Map> {
+        let raw = unsafe { vdo_sys::vdo_frame_get_extra_info(self.raw) };
+        if raw.is_null() {
+            return None;
+        }
+        // SAFETY: The map is owned by the frame, so a copy is made instead of taking ownership.
+        Some(unsafe { Map::from_raw(vdo_sys::vdo_map_clone(raw)) })
+    }
+
+    /// The opaque pointer given when the buffer was allocated, which is null for buffers
+    /// allocated by VDO.
+    pub fn opaque(&self) -> *mut std::ffi::c_void {
+        unsafe { vdo_sys::vdo_frame_get_opaque(self.raw) }
+    }
+
     pub fn is_last_buffer(&self) -> bool {
         unsafe { vdo_sys::vdo_frame_get_is_last_buffer(self.raw) != glib_sys::GFALSE }
     }
+
+    /// Returns the file descriptor backing the buffer, or `None` if it has none.
+    ///
+    /// Together with [`offset()`](StreamBuffer::offset) this allows handing the frame to other
+    /// APIs, such as larod, without copying it. The descriptor is owned by the stream.
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        let fd = unsafe { vdo_sys::vdo_buffer_get_fd(self.raw) };
+        if fd < 0 {
+            return None;
+        }
+        // SAFETY: The descriptor is owned by the stream and stays open at least as long as the
+        // buffer is referenced.
+        Some(unsafe { BorrowedFd::borrow_raw(fd) })
+    }
+
+    /// Offset in bytes of the frame data from the start of [`fd()`](StreamBuffer::fd).
+    pub fn offset(&self) -> i64 {
+        unsafe { vdo_sys::vdo_buffer_get_offset(self.raw) }
+    }
+}
+
+impl StreamBuffer<'_> {
+    /// Releases the buffer from its borrow of the stream without unreferencing it.
+    ///
+    /// The caller must keep the stream alive for as long as it uses the returned buffer.
+    pub(crate) fn into_raw(mut self) -> *mut VdoBuffer {
+        let raw = self.raw;
+        // Forgetting the buffer must not leak the stream it keeps alive.
+        drop(self._owner.take());
+        std::mem::forget(self);
+        raw
+    }
 }
 
 impl Drop for StreamBuffer<'_> {
     fn drop(&mut self) {
-        let (success, maybe_error) = unsafe {
-            try_func!(
-                vdo_sys::vdo_stream_buffer_unref,
-                self.stream.raw,
-                &mut self.raw
-            )
-        };
+        if self.recycle {
+            match encode::enqueue(self.stream, self.raw) {
+                Ok(()) => return,
+                Err(err) => log::error!("Failed to enqueue buffer: {}", err),
+            }
+        }
+        let (success, maybe_error) =
+            unsafe { try_func!(vdo_sys::vdo_stream_buffer_unref, self.stream, &mut self.raw) };
         if success == glib_sys::GFALSE || maybe_error.is_some() {
             match maybe_error {
                 Some(err) => log::error!("Failed to unref buffer: {}", err),
@@ -558,18 +940,21 @@ mod unit_tests {
         let err = VdoError {
             code: vdo_sys::VDO_ERROR_NOT_FOUND.0 as i32,
             message: "test".to_string(),
+            explanation: None,
         };
         expect!["VDO_ERROR_NOT_FOUND"].assert_eq(err.code_name());
 
         let err = VdoError {
             code: vdo_sys::VDO_ERROR_NOT_SUPPORTED.0 as i32,
             message: "test".to_string(),
+            explanation: None,
         };
         expect!["VDO_ERROR_NOT_SUPPORTED"].assert_eq(err.code_name());
 
         let err = VdoError {
             code: 9999,
             message: "test".to_string(),
+            explanation: None,
         };
         expect!["VDO_ERROR_UNKNOWN"].assert_eq(err.code_name());
 
@@ -578,6 +963,7 @@ mod unit_tests {
         let err = VdoError {
             code: -1,
             message: "test".to_string(),
+            explanation: None,
         };
         expect!["VDO_ERROR_UNKNOWN"].assert_eq(err.code_name());
     }
@@ -587,8 +973,16 @@ mod unit_tests {
         let err = VdoError {
             code: vdo_sys::VDO_ERROR_BUSY.0 as i32,
             message: "Resource is busy".to_string(),
+            explanation: None,
         };
         expect!["VDO_ERROR_BUSY (7): Resource is busy"].assert_eq(&format!("{err}"));
+
+        let err = VdoError {
+            code: vdo_sys::VDO_ERROR_NOT_SUPPORTED.0 as i32,
+            message: "Unsupported format".to_string(),
+            explanation: Some("Channel 0 does not support the format H.265".to_string()),
+        };
+        expect!["VDO_ERROR_NOT_SUPPORTED (5): Unsupported format (Channel 0 does not support the format H.265)"].assert_eq(&format!("{err}"));
     }
 
     #[test]
@@ -633,7 +1027,7 @@ mod unit_tests {
 
     #[test]
     fn vdo_error_from_null() {
-        let err = VdoError::from_gerror(ptr::null_mut());
+        let err = VdoError::from_gerror(std::ptr::null_mut());
         assert_eq!(err.code(), 0);
         assert!(err.message().is_empty());
     }
@@ -643,6 +1037,7 @@ mod unit_tests {
         let vdo_err = VdoError {
             code: 1,
             message: "test".to_string(),
+            explanation: None,
         };
         let err: Error = Error::from(vdo_err);
         match err {
@@ -663,9 +1058,78 @@ mod unit_tests {
         let vdo = Error::Vdo(VdoError {
             code: 1,
             message: "test".to_string(),
+            explanation: None,
         });
         expect!["VDO_ERROR_NOT_FOUND (1): test"].assert_eq(&format!("{vdo}"));
     }
+
+    fn invalid_argument(builder: StreamBuilder) -> String {
+        match builder.validate() {
+            Err(Error::InvalidArgument(reason)) => reason.to_string(),
+            other => panic!("expected invalid argument, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn default_builder_is_valid() {
+        StreamBuilder::new().validate().unwrap();
+    }
+
+    #[test]
+    fn encoding_options_are_valid_for_h26x() {
+        StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_H265)
+            .bitrate(2_000_000)
+            .rate_control_mode(VdoRateControlMode::VDO_RATE_CONTROL_MODE_MBR)
+            .rate_control_priority(VdoRateControlPriority::VDO_RATE_CONTROL_PRIORITY_QUALITY)
+            .zipstream_profile(VdoZipStreamProfile::VDO_ZIPSTREAM_PROFILE_STORAGE)
+            .compression(30)
+            .rotation(180)
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn rate_control_requires_h26x() {
+        expect!["bitrate and rate control require H.264 or H.265"].assert_eq(&invalid_argument(
+            StreamBuilder::new()
+                .format(VdoFormat::VDO_FORMAT_JPEG)
+                .bitrate(1_000_000),
+        ));
+        expect!["Zipstream requires H.264 or H.265"].assert_eq(&invalid_argument(
+            StreamBuilder::new()
+                .format(VdoFormat::VDO_FORMAT_YUV)
+                .zipstream_profile(VdoZipStreamProfile::VDO_ZIPSTREAM_PROFILE_LIVE),
+        ));
+    }
+
+    #[test]
+    fn constrained_rate_control_requires_bitrate() {
+        expect!["CBR, MBR and ABR rate control require a bitrate"].assert_eq(&invalid_argument(
+            StreamBuilder::new().rate_control_mode(VdoRateControlMode::VDO_RATE_CONTROL_MODE_CBR),
+        ));
+        StreamBuilder::new()
+            .rate_control_mode(VdoRateControlMode::VDO_RATE_CONTROL_MODE_VBR)
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn compression_is_bounded() {
+        expect!["compression must be between 0 and 100"]
+            .assert_eq(&invalid_argument(StreamBuilder::new().compression(101)));
+        expect!["compression requires an encoded format"].assert_eq(&invalid_argument(
+            StreamBuilder::new()
+                .format(VdoFormat::VDO_FORMAT_YUV)
+                .compression(50),
+        ));
+    }
+
+    #[test]
+    fn rotation_is_a_right_angle() {
+        expect!["rotation must be 0, 90, 180 or 270 degrees"]
+            .assert_eq(&invalid_argument(StreamBuilder::new().rotation(45)));
+    }
 }
 
 // These tests require the VDO shared library (libvdo.so) and actual camera hardware.
@@ -1030,6 +1494,8 @@ mod tests {
         std::hint::black_box(buffer.custom_timestamp_us());
         std::hint::black_box(buffer.header_size());
         std::hint::black_box(buffer.is_last_buffer());
+        std::hint::black_box(buffer.fd());
+        std::hint::black_box(buffer.offset());
 
         drop(buffer);
         drop(running);
@@ -1271,4 +1737,52 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn running_stream_can_be_adjusted() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        init_logger();
+        let running = StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_H264)
+            .resolution(Resolution::Exact {
+                width: 640,
+                height: 360,
+            })
+            .framerate(30)
+            .gop_length(300)
+            .build()?
+            .start()?;
+
+        // Skip past the key frame that starts the stream.
+        for _ in 0..5 {
+            running.next_buffer()?;
+        }
+        running.force_key_frame()?;
+        let found = (0..10).any(|_| {
+            running.next_buffer().is_ok_and(|b| {
+                b.frame_type() == VdoFrameType::VDO_FRAME_TYPE_H264_IDR
+                    || b.frame_type() == VdoFrameType::VDO_FRAME_TYPE_H264_I
+            })
+        });
+        assert!(found, "No key frame after forcing one");
+
+        running.set_framerate(10.0)?;
+        running.next_buffer()?;
+        Ok(())
+    }
+
+    #[test]
+    fn utc_timestamp_matches_clock_offset() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        init_logger();
+        let running = StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_H264)
+            .build()?
+            .start()?;
+        let buffer = running.next_buffer()?;
+        assert!(buffer.is_key(), "Stream should start with a key frame");
+        let from_buffer =
+            clock::ClockOffset::from_buffer(&buffer).ok_or("Frame has no UTC timestamp")?;
+        let now = clock::ClockOffset::now();
+        assert!((from_buffer.as_micros() - now.as_micros()).abs() < 100_000);
+        Ok(())
+    }
 }
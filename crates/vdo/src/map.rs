@@ -1,15 +1,24 @@
 //! Key-value map for VDO settings and a GLib-allocated C string type.
+//!
+//! Besides typed getters and setters, the entries of a [`Map`] can be iterated as [`Value`]s and,
+//! with the `json` feature, converted to and from JSON objects.
 
+#[cfg(feature = "json")]
+use std::collections::BTreeMap;
 use std::{
-    ffi::{c_char, c_void, CStr},
-    fmt,
+    ffi::{c_char, c_void, CStr, CString},
+    fmt, mem,
     ops::Deref,
     ptr::{self, NonNull},
 };
 
-use glib::translate::{from_glib, IntoGlib};
+use glib::{
+    translate::{from_glib, from_glib_full, IntoGlib, ToGlibPtr},
+    variant::ToVariant,
+    Variant, VariantClass,
+};
 use gobject_sys::{g_object_unref, GObject};
-use vdo_sys::VdoMap;
+use vdo_sys::{VdoMap, VdoPair32i, VdoPair32u, VdoQuad32i, VdoQuad32u};
 
 /// An owned pointer to a C string allocated by GLib.
 ///
@@ -123,6 +132,94 @@ impl Map {
         Some(unsafe { CStringPtr::from_ptr(ptr) })
     }
 
+    pub fn set_i64(&mut self, key: &CStr, value: i64) {
+        unsafe { vdo_sys::vdo_map_set_int64(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_i64(&self, key: &CStr, default: i64) -> i64 {
+        unsafe { vdo_sys::vdo_map_get_int64(self.raw, key.as_ptr(), default) }
+    }
+
+    pub fn set_u64(&mut self, key: &CStr, value: u64) {
+        unsafe { vdo_sys::vdo_map_set_uint64(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_u64(&self, key: &CStr, default: u64) -> u64 {
+        unsafe { vdo_sys::vdo_map_get_uint64(self.raw, key.as_ptr(), default) }
+    }
+
+    pub fn set_f64(&mut self, key: &CStr, value: f64) {
+        unsafe { vdo_sys::vdo_map_set_double(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_f64(&self, key: &CStr, default: f64) -> f64 {
+        unsafe { vdo_sys::vdo_map_get_double(self.raw, key.as_ptr(), default) }
+    }
+
+    /// Sets a pair such as a position or a fraction.
+    pub fn set_pair_i32(&mut self, key: &CStr, value: [i32; 2]) {
+        // SAFETY: The pair is a union of two 32-bit integers.
+        let value = unsafe { mem::transmute::<[i32; 2], VdoPair32i>(value) };
+        unsafe { vdo_sys::vdo_map_set_pair32i(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_pair_i32(&self, key: &CStr, default: [i32; 2]) -> [i32; 2] {
+        // SAFETY: The pair is a union of two 32-bit integers.
+        unsafe {
+            let default = mem::transmute::<[i32; 2], VdoPair32i>(default);
+            let value = vdo_sys::vdo_map_get_pair32i(self.raw, key.as_ptr(), default);
+            mem::transmute::<VdoPair32i, [i32; 2]>(value)
+        }
+    }
+
+    /// Sets a pair such as a resolution or a fraction.
+    pub fn set_pair_u32(&mut self, key: &CStr, value: [u32; 2]) {
+        // SAFETY: The pair is a union of two 32-bit integers.
+        let value = unsafe { mem::transmute::<[u32; 2], VdoPair32u>(value) };
+        unsafe { vdo_sys::vdo_map_set_pair32u(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_pair_u32(&self, key: &CStr, default: [u32; 2]) -> [u32; 2] {
+        // SAFETY: The pair is a union of two 32-bit integers.
+        unsafe {
+            let default = mem::transmute::<[u32; 2], VdoPair32u>(default);
+            let value = vdo_sys::vdo_map_get_pair32u(self.raw, key.as_ptr(), default);
+            mem::transmute::<VdoPair32u, [u32; 2]>(value)
+        }
+    }
+
+    /// Sets a quad such as a rectangle as `[x, y, w, h]`.
+    pub fn set_quad_i32(&mut self, key: &CStr, value: [i32; 4]) {
+        // SAFETY: The quad is a union of four 32-bit integers.
+        let value = unsafe { mem::transmute::<[i32; 4], VdoQuad32i>(value) };
+        unsafe { vdo_sys::vdo_map_set_quad32i(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_quad_i32(&self, key: &CStr, default: [i32; 4]) -> [i32; 4] {
+        // SAFETY: The quad is a union of four 32-bit integers.
+        unsafe {
+            let default = mem::transmute::<[i32; 4], VdoQuad32i>(default);
+            let value = vdo_sys::vdo_map_get_quad32i(self.raw, key.as_ptr(), default);
+            mem::transmute::<VdoQuad32i, [i32; 4]>(value)
+        }
+    }
+
+    /// Sets a quad such as a rectangle as `[x, y, w, h]` or a range as `[min, target, max, _]`.
+    pub fn set_quad_u32(&mut self, key: &CStr, value: [u32; 4]) {
+        // SAFETY: The quad is a union of four 32-bit integers.
+        let value = unsafe { mem::transmute::<[u32; 4], VdoQuad32u>(value) };
+        unsafe { vdo_sys::vdo_map_set_quad32u(self.raw, key.as_ptr(), value) }
+    }
+
+    pub fn get_quad_u32(&self, key: &CStr, default: [u32; 4]) -> [u32; 4] {
+        // SAFETY: The quad is a union of four 32-bit integers.
+        unsafe {
+            let default = mem::transmute::<[u32; 4], VdoQuad32u>(default);
+            let value = vdo_sys::vdo_map_get_quad32u(self.raw, key.as_ptr(), default);
+            mem::transmute::<VdoQuad32u, [u32; 4]>(value)
+        }
+    }
+
     pub fn set_bool(&mut self, key: &CStr, value: bool) {
         unsafe { vdo_sys::vdo_map_set_boolean(self.raw, key.as_ptr(), value.into_glib()) }
     }
@@ -137,6 +234,133 @@ impl Map {
         }
     }
 
+    /// Sets `key` to `value`, choosing the VDO type from the variant of `value`.
+    ///
+    /// [`Value::List`]s of two or four integers are stored as pairs and quads respectively;
+    /// other lists are stored as generic variants.
+    pub fn set(&mut self, key: &CStr, value: &Value) {
+        match value {
+            Value::Bool(v) => self.set_bool(key, *v),
+            Value::Byte(v) => unsafe { vdo_sys::vdo_map_set_byte(self.raw, key.as_ptr(), *v) },
+            Value::I16(v) => unsafe { vdo_sys::vdo_map_set_int16(self.raw, key.as_ptr(), *v) },
+            Value::U16(v) => unsafe { vdo_sys::vdo_map_set_uint16(self.raw, key.as_ptr(), *v) },
+            Value::I32(v) => self.set_i32(key, *v),
+            Value::U32(v) => self.set_u32(key, *v),
+            Value::I64(v) => self.set_i64(key, *v),
+            Value::U64(v) => self.set_u64(key, *v),
+            Value::Double(v) => self.set_f64(key, *v),
+            Value::String(v) => match CString::new(v.as_str()) {
+                Ok(v) => self.set_string(key, &v),
+                Err(_) => self.set_variant(key, &value.to_variant()),
+            },
+            Value::List(items) => match (items.as_slice(), value.as_u32s(), value.as_i32s()) {
+                ([_, _], Some(v), _) => self.set_pair_u32(key, [v[0], v[1]]),
+                ([_, _], None, Some(v)) => self.set_pair_i32(key, [v[0], v[1]]),
+                ([_, _, _, _], Some(v), _) => self.set_quad_u32(key, [v[0], v[1], v[2], v[3]]),
+                ([_, _, _, _], None, Some(v)) => self.set_quad_i32(key, [v[0], v[1], v[2], v[3]]),
+                _ => self.set_variant(key, &value.to_variant()),
+            },
+            Value::Variant(v) => self.set_variant(key, v),
+        }
+    }
+
+    /// Returns the value of `key`, or `None` if the map has no such key.
+    pub fn get(&self, key: &CStr) -> Option<Value> {
+        let key = key.to_str().ok()?;
+        glib::VariantDict::new(Some(&self.to_variant()))
+            .lookup_value(key, None)
+            .map(|v| Value::from_variant(&v))
+    }
+
+    fn set_variant(&mut self, key: &CStr, value: &Variant) {
+        // There is no setter for arbitrary variants, so merge a map with only this entry.
+        let dict = glib::VariantDict::new(None);
+        dict.insert_value(&key.to_string_lossy(), value);
+        if let Some(single) = Map::from_variant(&dict.end()) {
+            self.merge(&single);
+        }
+    }
+
+    pub fn contains(&self, key: &CStr) -> bool {
+        unsafe { from_glib(vdo_sys::vdo_map_contains(self.raw, key.as_ptr())) }
+    }
+
+    pub fn remove(&mut self, key: &CStr) {
+        unsafe { vdo_sys::vdo_map_remove(self.raw, key.as_ptr()) }
+    }
+
+    pub fn clear(&mut self) {
+        unsafe { vdo_sys::vdo_map_clear(self.raw) }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        unsafe { vdo_sys::vdo_map_size(self.raw) }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unsafe { from_glib(vdo_sys::vdo_map_empty(self.raw)) }
+    }
+
+    /// Copies all entries of `other` into this map, replacing existing values.
+    pub fn merge(&mut self, other: &Map) {
+        unsafe { vdo_sys::vdo_map_merge(self.raw, other.raw) }
+    }
+
+    /// Returns a new map with the entries whose keys start with `prefix`.
+    pub fn filter_prefix(&self, prefix: &CStr) -> Map {
+        // SAFETY: The function returns a new map with ownership transferred.
+        unsafe { Map::from_raw(vdo_sys::vdo_map_filter_prefix(self.raw, prefix.as_ptr())) }
+    }
+
+    /// Returns the map as a `a{sv}` dictionary.
+    pub fn to_variant(&self) -> Variant {
+        unsafe {
+            let variant = vdo_sys::vdo_map_to_variant(self.raw);
+            // Take ownership of the reference regardless of whether it is floating.
+            glib::ffi::g_variant_take_ref(variant as *mut _);
+            from_glib_full(variant as *mut _)
+        }
+    }
+
+    /// Creates a map from a `a{sv}` dictionary, or returns `None` if `variant` has another type.
+    pub fn from_variant(variant: &Variant) -> Option<Self> {
+        if variant.type_() != glib::VariantTy::VARDICT {
+            return None;
+        }
+        let raw = unsafe {
+            vdo_sys::vdo_map_new_from_variant(
+                ToGlibPtr::<*const glib::ffi::GVariant>::to_glib_none(variant).0 as *mut _,
+            )
+        };
+        if raw.is_null() {
+            return None;
+        }
+        Some(Self { raw })
+    }
+
+    /// Returns the entries of the map, ordered by key.
+    pub fn entries(&self) -> Vec<(String, Value)> {
+        let mut entries: Vec<_> = self
+            .to_variant()
+            .iter()
+            .filter_map(|entry| {
+                let key = entry.child_value(0).str()?.to_string();
+                let value = entry.child_value(1).as_variant()?;
+                Some((key, Value::from_variant(&value)))
+            })
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Iterates over the entries of the map, ordered by key.
+    ///
+    /// The entries are copied when the iteration starts.
+    pub fn iter(&self) -> impl Iterator<Item = (String, Value)> {
+        self.entries().into_iter()
+    }
+
     /// Dumps the map contents to stdout. Intended for debugging only;
     /// may expose sensitive configuration values in production logs.
     pub fn dump(&self) {
@@ -161,6 +385,19 @@ impl fmt::Debug for Map {
     }
 }
 
+impl Clone for Map {
+    fn clone(&self) -> Self {
+        // SAFETY: The function returns a new map with ownership transferred.
+        unsafe { Self::from_raw(vdo_sys::vdo_map_clone(self.raw)) }
+    }
+}
+
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        unsafe { from_glib(vdo_sys::vdo_map_equals(self.raw, other.raw)) }
+    }
+}
+
 // SAFETY: We hold exclusive ownership of the raw pointer and VdoMap
 // does not require access from a specific thread.
 unsafe impl Send for Map {}
@@ -170,3 +407,335 @@ impl Drop for Map {
         unsafe { g_object_unref(self.raw as *mut GObject) }
     }
 }
+
+/// The value of an entry in a [`Map`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Byte(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    Double(f64),
+    String(String),
+    /// A tuple or an array, such as a pair or a quad.
+    List(Vec<Value>),
+    /// A value of a type without a more specific representation, such as a nested dictionary.
+    Variant(Variant),
+}
+
+impl Value {
+    /// Converts a variant, unboxing it if it is of the type `v`.
+    pub fn from_variant(variant: &Variant) -> Self {
+        match variant.classify() {
+            VariantClass::Boolean => variant.get().map(Self::Bool),
+            VariantClass::Byte => variant.get().map(Self::Byte),
+            VariantClass::Int16 => variant.get().map(Self::I16),
+            VariantClass::Uint16 => variant.get().map(Self::U16),
+            VariantClass::Int32 => variant.get().map(Self::I32),
+            VariantClass::Uint32 => variant.get().map(Self::U32),
+            VariantClass::Int64 => variant.get().map(Self::I64),
+            VariantClass::Uint64 => variant.get().map(Self::U64),
+            VariantClass::Double => variant.get().map(Self::Double),
+            VariantClass::String => variant.str().map(|s| Self::String(s.to_string())),
+            VariantClass::Variant => variant.as_variant().map(|v| Self::from_variant(&v)),
+            VariantClass::Tuple => Some(Self::List(
+                variant.iter().map(|v| Self::from_variant(&v)).collect(),
+            )),
+            VariantClass::Array if !variant.type_().element().is_dict_entry() => Some(Self::List(
+                variant.iter().map(|v| Self::from_variant(&v)).collect(),
+            )),
+            _ => None,
+        }
+        .unwrap_or_else(|| Self::Variant(variant.clone()))
+    }
+
+    /// Converts the value to a variant; lists become tuples.
+    pub fn to_variant(&self) -> Variant {
+        match self {
+            Self::Bool(v) => v.to_variant(),
+            Self::Byte(v) => v.to_variant(),
+            Self::I16(v) => v.to_variant(),
+            Self::U16(v) => v.to_variant(),
+            Self::I32(v) => v.to_variant(),
+            Self::U32(v) => v.to_variant(),
+            Self::I64(v) => v.to_variant(),
+            Self::U64(v) => v.to_variant(),
+            Self::Double(v) => v.to_variant(),
+            Self::String(v) => v.to_variant(),
+            Self::List(items) => Variant::tuple_from_iter(items.iter().map(Value::to_variant)),
+            Self::Variant(v) => v.clone(),
+        }
+    }
+
+    fn as_u32s(&self) -> Option<Vec<u32>> {
+        let Self::List(items) = self else {
+            return None;
+        };
+        items
+            .iter()
+            .map(|v| match v {
+                Self::U32(v) => Some(*v),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn as_i32s(&self) -> Option<Vec<i32>> {
+        let Self::List(items) = self else {
+            return None;
+        };
+        items
+            .iter()
+            .map(|v| match v {
+                Self::I32(v) => Some(*v),
+                Self::U32(v) => i32::try_from(*v).ok(),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "json")]
+impl Map {
+    /// Returns the entries of the map as a JSON object.
+    ///
+    /// See [`Value::to_json()`] for how the values are converted.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(self.iter().map(|(k, v)| (k, v.to_json())).collect())
+    }
+
+    /// Creates a map from a JSON object.
+    ///
+    /// See [`Value::from_json()`] for how the values are converted.
+    pub fn from_json(json: &serde_json::Value) -> Result<Self, crate::Error> {
+        let entries = json
+            .as_object()
+            .ok_or_else(|| crate::Error::Json(format!("Expected an object but got {json}")))?
+            .iter()
+            .map(|(k, v)| {
+                let key = CString::new(k.as_str())
+                    .map_err(|_| crate::Error::Json(format!("Key {k:?} contains a nul byte")))?;
+                let value = Value::from_json(v).ok_or_else(|| {
+                    crate::Error::Json(format!("Unsupported value for key {k:?}: {v}"))
+                })?;
+                Ok((key, value))
+            })
+            .collect::<Result<BTreeMap<_, _>, crate::Error>>()?;
+        let mut map = Map::new();
+        for (key, value) in entries {
+            map.set(&key, &value);
+        }
+        Ok(map)
+    }
+}
+
+#[cfg(feature = "json")]
+impl Value {
+    /// Converts the value to JSON.
+    ///
+    /// Numbers that are not finite become `null` and values without a more specific
+    /// representation become strings in the GVariant text format.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::Value as Json;
+        match self {
+            Self::Bool(v) => Json::from(*v),
+            Self::Byte(v) => Json::from(*v),
+            Self::I16(v) => Json::from(*v),
+            Self::U16(v) => Json::from(*v),
+            Self::I32(v) => Json::from(*v),
+            Self::U32(v) => Json::from(*v),
+            Self::I64(v) => Json::from(*v),
+            Self::U64(v) => Json::from(*v),
+            Self::Double(v) => Json::from(*v),
+            Self::String(v) => Json::from(v.as_str()),
+            Self::List(items) => Json::Array(items.iter().map(Value::to_json).collect()),
+            Self::Variant(v) => Json::from(v.print(false).as_str()),
+        }
+    }
+
+    /// Converts JSON to a value, or returns `None` for `null` and objects.
+    ///
+    /// Since JSON does not preserve the integer types used by VDO, integers become [`Value::U32`]
+    /// or [`Value::I32`] if they fit and [`Value::U64`] or [`Value::I64`] otherwise.
+    pub fn from_json(json: &serde_json::Value) -> Option<Self> {
+        use serde_json::Value as Json;
+        match json {
+            Json::Bool(v) => Some(Self::Bool(*v)),
+            Json::Number(n) => {
+                if let Some(v) = n.as_u64() {
+                    Some(u32::try_from(v).map_or(Self::U64(v), Self::U32))
+                } else if let Some(v) = n.as_i64() {
+                    Some(i32::try_from(v).map_or(Self::I64(v), Self::I32))
+                } else {
+                    n.as_f64().map(Self::Double)
+                }
+            }
+            Json::String(v) => Some(Self::String(v.clone())),
+            Json::Array(items) => items
+                .iter()
+                .map(Value::from_json)
+                .collect::<Option<_>>()
+                .map(Self::List),
+            Json::Null | Json::Object(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip_through_variants() {
+        let values = [
+            Value::Bool(true),
+            Value::Byte(7),
+            Value::I16(-16),
+            Value::U16(16),
+            Value::I32(-32),
+            Value::U32(32),
+            Value::I64(-64),
+            Value::U64(64),
+            Value::Double(0.5),
+            Value::String("yuv".to_string()),
+            Value::List(vec![Value::U32(1920), Value::U32(1080)]),
+        ];
+        for value in values {
+            assert_eq!(Value::from_variant(&value.to_variant()), value);
+        }
+    }
+
+    #[test]
+    fn boxed_variants_are_unboxed() {
+        let boxed = Variant::from_variant(&42u32.to_variant());
+        assert_eq!(Value::from_variant(&boxed), Value::U32(42));
+    }
+
+    #[test]
+    fn dictionaries_are_kept_as_variants() {
+        let dict = glib::VariantDict::new(None);
+        dict.insert_value("width", &640u32.to_variant());
+        let variant = dict.end();
+        assert_eq!(Value::from_variant(&variant), Value::Variant(variant));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_numbers_get_the_narrowest_type() {
+        use serde_json::json;
+        assert_eq!(Value::from_json(&json!(1080)), Some(Value::U32(1080)));
+        assert_eq!(Value::from_json(&json!(-3)), Some(Value::I32(-3)));
+        assert_eq!(
+            Value::from_json(&json!(u64::MAX)),
+            Some(Value::U64(u64::MAX))
+        );
+        assert_eq!(
+            Value::from_json(&json!(i64::MIN)),
+            Some(Value::I64(i64::MIN))
+        );
+        assert_eq!(Value::from_json(&json!(0.5)), Some(Value::Double(0.5)));
+        assert_eq!(
+            Value::from_json(&json!([1, -1])),
+            Some(Value::List(vec![Value::U32(1), Value::I32(-1)]))
+        );
+        assert_eq!(Value::from_json(&json!(null)), None);
+        assert_eq!(Value::from_json(&json!([{}])), None);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn values_convert_to_json() {
+        use serde_json::json;
+        assert_eq!(Value::I16(-2).to_json(), json!(-2));
+        assert_eq!(Value::Double(f64::NAN).to_json(), json!(null));
+        assert_eq!(
+            Value::List(vec![Value::U32(4), Value::String("a".to_string())]).to_json(),
+            json!([4, "a"])
+        );
+        assert_eq!(
+            Value::Variant(glib::VariantDict::new(None).end()).to_json(),
+            json!("{}")
+        );
+    }
+}
+
+// These tests require the VDO library and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_pairs_and_quads_round_trip() {
+        let mut map = Map::new();
+        map.set_f64(c"double", 0.25);
+        map.set_i64(c"i64", -1 << 40);
+        map.set_u64(c"u64", 1 << 40);
+        map.set_pair_i32(c"pair_i32", [-1, 2]);
+        map.set_pair_u32(c"pair_u32", [1920, 1080]);
+        map.set_quad_i32(c"quad_i32", [-1, -2, 3, 4]);
+        map.set_quad_u32(c"quad_u32", [1, 2, 3, 4]);
+        assert_eq!(map.get_f64(c"double", 0.0), 0.25);
+        assert_eq!(map.get_i64(c"i64", 0), -1 << 40);
+        assert_eq!(map.get_u64(c"u64", 0), 1 << 40);
+        assert_eq!(map.get_pair_i32(c"pair_i32", [0; 2]), [-1, 2]);
+        assert_eq!(map.get_pair_u32(c"pair_u32", [0; 2]), [1920, 1080]);
+        assert_eq!(map.get_quad_i32(c"quad_i32", [0; 4]), [-1, -2, 3, 4]);
+        assert_eq!(map.get_quad_u32(c"quad_u32", [0; 4]), [1, 2, 3, 4]);
+        assert_eq!(map.get_pair_u32(c"missing", [5, 6]), [5, 6]);
+        assert_eq!(map.len(), 7);
+    }
+
+    #[test]
+    fn maps_can_be_combined_and_compared() {
+        let mut a = Map::new();
+        a.set_u32(c"image.width", 640);
+        a.set_u32(c"image.height", 480);
+        a.set_string(c"name", c"a");
+        let mut b = Map::new();
+        b.set_string(c"name", c"b");
+
+        let image = a.filter_prefix(c"image.");
+        assert_eq!(image.len(), 2);
+        assert!(!image.contains(c"name"));
+
+        let mut merged = a.clone();
+        merged.merge(&b);
+        assert_ne!(merged, a);
+        assert_eq!(merged.get(c"name"), Some(Value::String("b".to_string())));
+
+        merged.remove(c"name");
+        a.remove(c"name");
+        assert_eq!(merged, a);
+        merged.clear();
+        assert!(merged.is_empty());
+    }
+
+    #[test]
+    fn entries_round_trip_through_variants() {
+        let mut map = Map::new();
+        map.set_u32(c"width", 640);
+        map.set_pair_u32(c"resolution", [640, 480]);
+        map.set(c"list", &Value::List(vec![Value::Bool(true)]));
+        let keys: Vec<_> = map.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, ["list", "resolution", "width"]);
+        assert_eq!(map.get(c"width"), Some(Value::U32(640)));
+        assert_eq!(Map::from_variant(&map.to_variant()), Some(map));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn maps_round_trip_through_json() -> Result<(), crate::Error> {
+        let json = serde_json::json!({"width": 640, "offset": -3, "name": "yuv"});
+        let map = Map::from_json(&json)?;
+        assert_eq!(map.get_u32(c"width", 0), 640);
+        assert_eq!(map.get_i32(c"offset", 0), -3);
+        assert_eq!(map.to_json(), json);
+        assert!(Map::from_json(&serde_json::json!([1])).is_err());
+        Ok(())
+    }
+}
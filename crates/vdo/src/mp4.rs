@@ -0,0 +1,652 @@
+//! Fragmented MP4 muxing of H.264 and H.265 streams.
+//!
+//! [`Muxer`] writes an initialization segment, `ftyp` and `moov`, followed by one fragment, `moof`
+//! and `mdat`, per group of pictures. Since every fragment is self-contained, a file that is cut
+//! short, e.g. by a power loss, can still be played up to the last complete fragment.
+//!
+//! The muxer only needs the frame data and timestamps, so recorded frames can be muxed on a host
+//! using [`Muxer::push()`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::{fs::File, io::BufWriter};
+//!
+//! use vdo::{annexb::Codec, mp4::Muxer, StreamBuilder, VdoFormat};
+//!
+//! let stream = StreamBuilder::new()
+//!     .format(VdoFormat::VDO_FORMAT_H264)
+//!     .build()?
+//!     .start()?;
+//! let file = BufWriter::new(File::create("/var/spool/storage/SD_DISK/clip.mp4")?);
+//! let mut muxer = Muxer::new(Codec::H264, file);
+//! for _ in 0..300 {
+//!     muxer.push_buffer(&stream.next_buffer()?)?;
+//! }
+//! muxer.finish()?;
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use std::io::Write;
+
+use log::debug;
+
+use crate::{
+    annexb::{nal_units, Codec, CodecParameters, ParameterSets},
+    Error, StreamBuffer,
+};
+
+/// The timescale of the track; VDO timestamps are in microseconds.
+const TIMESCALE: u32 = 1_000_000;
+const TRACK_ID: u32 = 1;
+/// The duration of the last frame when there is no preceding frame to measure, i.e. 30 fps.
+const DEFAULT_DURATION: u32 = TIMESCALE / 30;
+
+const SAMPLE_IS_SYNC: u32 = 0x0200_0000;
+const SAMPLE_IS_NOT_SYNC: u32 = 0x0101_0000;
+
+const IDENTITY_MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+/// Writes H.264 or H.265 frames as a fragmented MP4 file.
+///
+/// Frames before the first key frame are dropped, since they cannot be decoded. The parameter
+/// sets of the first key frame are written to the initialization segment, so they must not
+/// change during the recording; start a new file when e.g. the resolution changes.
+pub struct Muxer<W: Write> {
+    writer: W,
+    parameter_sets: ParameterSets,
+    /// The parameter sets written to the initialization segment.
+    initial_parameter_sets: Option<ParameterSets>,
+    /// The frames of the fragment that is being collected.
+    frames: Vec<Frame>,
+    /// The timestamp of the first frame, which is the zero point of the track.
+    start: u64,
+    sequence_number: u32,
+    last_duration: u32,
+}
+
+struct Frame {
+    /// The NAL units of the frame, each prefixed by its length.
+    data: Vec<u8>,
+    timestamp: u64,
+    keyframe: bool,
+}
+
+impl<W: Write> Muxer<W> {
+    pub fn new(codec: Codec, writer: W) -> Self {
+        Self {
+            writer,
+            parameter_sets: ParameterSets::new(codec),
+            initial_parameter_sets: None,
+            frames: Vec::new(),
+            start: 0,
+            sequence_number: 0,
+            last_duration: DEFAULT_DURATION,
+        }
+    }
+
+    /// Adds a frame in the Annex B format with a timestamp in microseconds.
+    ///
+    /// A key frame ends the fragment being collected, which is then written.
+    pub fn push(&mut self, frame: &[u8], timestamp: u64, keyframe: bool) -> Result<(), Error> {
+        self.parameter_sets.update(frame);
+        match &self.initial_parameter_sets {
+            None if !keyframe || !self.parameter_sets.is_complete() => {
+                debug!("Dropping frame that precedes the first key frame");
+                return Ok(());
+            }
+            None => {
+                self.write_init_segment()?;
+                self.initial_parameter_sets = Some(self.parameter_sets.clone());
+                self.start = timestamp;
+            }
+            Some(initial) if initial != &self.parameter_sets => {
+                return Err(Error::Bitstream(
+                    "the parameter sets changed during the recording",
+                ));
+            }
+            Some(_) => {}
+        }
+        if keyframe && !self.frames.is_empty() {
+            self.write_fragment(Some(timestamp))?;
+        }
+        self.frames.push(Frame {
+            data: length_prefixed(self.parameter_sets.codec(), frame),
+            timestamp,
+            keyframe,
+        });
+        Ok(())
+    }
+
+    /// Adds a frame from a stream, using its timestamp and key flag.
+    ///
+    /// Both IDR and I frames are key frames, see [`StreamBuffer::is_key()`].
+    pub fn push_buffer(&mut self, buffer: &StreamBuffer<'_>) -> Result<(), Error> {
+        self.push(buffer.data()?, buffer.timestamp(), buffer.is_key())
+    }
+
+    /// Writes the remaining frames and returns the writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if !self.frames.is_empty() {
+            self.write_fragment(None)?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    fn write_init_segment(&mut self) -> Result<(), Error> {
+        let parameters = self.parameter_sets.parameters()?;
+        let width = u16::try_from(parameters.width)
+            .map_err(|_| Error::Bitstream("width does not fit in an MP4 sample entry"))?;
+        let height = u16::try_from(parameters.height)
+            .map_err(|_| Error::Bitstream("height does not fit in an MP4 sample entry"))?;
+
+        let mut out = Vec::new();
+        write_box(&mut out, b"ftyp", |b| {
+            b.extend_from_slice(b"iso5");
+            put_u32(b, 512);
+            b.extend_from_slice(b"iso5iso6mp41");
+        });
+        write_box(&mut out, b"moov", |b| {
+            write_full_box(b, b"mvhd", 0, 0, |b| {
+                put_u32s(b, &[0, 0, 1000, 0, 0x0001_0000]);
+                put_u16(b, 0x0100);
+                put_u16(b, 0);
+                put_u32s(b, &[0, 0]);
+                put_u32s(b, &IDENTITY_MATRIX);
+                put_u32s(b, &[0; 6]);
+                put_u32(b, TRACK_ID + 1);
+            });
+            write_box(b, b"trak", |b| {
+                // Flags: track enabled and in movie.
+                write_full_box(b, b"tkhd", 0, 3, |b| {
+                    put_u32s(b, &[0, 0, TRACK_ID, 0, 0, 0, 0]);
+                    put_u16s(b, &[0, 0, 0, 0]);
+                    put_u32s(b, &IDENTITY_MATRIX);
+                    put_u32(b, u32::from(width) << 16);
+                    put_u32(b, u32::from(height) << 16);
+                });
+                write_box(b, b"mdia", |b| {
+                    write_full_box(b, b"mdhd", 0, 0, |b| {
+                        put_u32s(b, &[0, 0, TIMESCALE, 0]);
+                        // Language `und`, packed as three 5-bit letters.
+                        put_u16s(b, &[0x55C4, 0]);
+                    });
+                    write_full_box(b, b"hdlr", 0, 0, |b| {
+                        put_u32(b, 0);
+                        b.extend_from_slice(b"vide");
+                        put_u32s(b, &[0; 3]);
+                        b.extend_from_slice(b"VideoHandler\0");
+                    });
+                    write_box(b, b"minf", |b| {
+                        write_full_box(b, b"vmhd", 0, 1, |b| put_u16s(b, &[0; 4]));
+                        write_box(b, b"dinf", |b| {
+                            write_full_box(b, b"dref", 0, 0, |b| {
+                                put_u32(b, 1);
+                                // Flags: the media data is in the same file.
+                                write_full_box(b, b"url ", 0, 1, |_| {});
+                            });
+                        });
+                        write_box(b, b"stbl", |b| {
+                            write_full_box(b, b"stsd", 0, 0, |b| {
+                                put_u32(b, 1);
+                                write_sample_entry(
+                                    b,
+                                    &self.parameter_sets,
+                                    &parameters,
+                                    width,
+                                    height,
+                                );
+                            });
+                            write_full_box(b, b"stts", 0, 0, |b| put_u32(b, 0));
+                            write_full_box(b, b"stsc", 0, 0, |b| put_u32(b, 0));
+                            write_full_box(b, b"stsz", 0, 0, |b| put_u32s(b, &[0, 0]));
+                            write_full_box(b, b"stco", 0, 0, |b| put_u32(b, 0));
+                        });
+                    });
+                });
+            });
+            write_box(b, b"mvex", |b| {
+                write_full_box(b, b"trex", 0, 0, |b| {
+                    put_u32s(b, &[TRACK_ID, 1, 0, 0, 0]);
+                });
+            });
+        });
+        self.writer.write_all(&out)?;
+        Ok(())
+    }
+
+    /// Writes the collected frames as a fragment.
+    ///
+    /// `next` is the timestamp of the frame following the fragment, if known, which determines
+    /// the duration of the last frame.
+    fn write_fragment(&mut self, next: Option<u64>) -> Result<(), Error> {
+        let frames = std::mem::take(&mut self.frames);
+        self.sequence_number += 1;
+        let mut durations = Vec::with_capacity(frames.len());
+        for (i, frame) in frames.iter().enumerate() {
+            let next = frames.get(i + 1).map(|f| f.timestamp).or(next);
+            if let Some(next) = next {
+                self.last_duration =
+                    u32::try_from(next.saturating_sub(frame.timestamp)).unwrap_or(u32::MAX);
+            }
+            durations.push(self.last_duration);
+        }
+
+        let mut moof = Vec::new();
+        let mut data_offset_position = 0;
+        write_box(&mut moof, b"moof", |b| {
+            write_full_box(b, b"mfhd", 0, 0, |b| put_u32(b, self.sequence_number));
+            write_box(b, b"traf", |b| {
+                // Flags: offsets are relative to the start of the moof.
+                write_full_box(b, b"tfhd", 0, 0x02_0000, |b| put_u32(b, TRACK_ID));
+                write_full_box(b, b"tfdt", 1, 0, |b| {
+                    put_u64(b, frames[0].timestamp.saturating_sub(self.start));
+                });
+                // Flags: data offset, sample duration, sample size and sample flags present.
+                write_full_box(b, b"trun", 0, 0x0701, |b| {
+                    put_u32(b, frames.len() as u32);
+                    data_offset_position = b.len();
+                    put_u32(b, 0);
+                    for (frame, duration) in frames.iter().zip(&durations) {
+                        let flags = if frame.keyframe {
+                            SAMPLE_IS_SYNC
+                        } else {
+                            SAMPLE_IS_NOT_SYNC
+                        };
+                        put_u32s(b, &[*duration, frame.data.len() as u32, flags]);
+                    }
+                });
+            });
+        });
+        // The samples start right after the header of the mdat.
+        let data_offset = moof.len() as u32 + 8;
+        moof[data_offset_position..data_offset_position + 4]
+            .copy_from_slice(&data_offset.to_be_bytes());
+
+        let mdat_size = 8 + frames.iter().map(|f| f.data.len()).sum::<usize>();
+        let mdat_size = u32::try_from(mdat_size)
+            .map_err(|_| Error::Bitstream("fragment is too large for an mdat box"))?;
+        self.writer.write_all(&moof)?;
+        self.writer.write_all(&mdat_size.to_be_bytes())?;
+        self.writer.write_all(b"mdat")?;
+        for frame in &frames {
+            self.writer.write_all(&frame.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Converts a frame in the Annex B format to length prefixed NAL units.
+///
+/// Parameter sets are left out since they are stored in the sample entry.
+fn length_prefixed(codec: Codec, frame: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(frame.len());
+    for nal in nal_units(codec, frame).filter(|n| !n.is_parameter_set()) {
+        put_u32(&mut out, nal.data().len() as u32);
+        out.extend_from_slice(nal.data());
+    }
+    out
+}
+
+fn write_sample_entry(
+    b: &mut Vec<u8>,
+    sets: &ParameterSets,
+    parameters: &CodecParameters,
+    width: u16,
+    height: u16,
+) {
+    let kind = match parameters.codec {
+        Codec::H264 => b"avc1",
+        Codec::H265 => b"hvc1",
+    };
+    write_box(b, kind, |b| {
+        b.extend_from_slice(&[0; 6]);
+        // Data reference index.
+        put_u16(b, 1);
+        put_u16s(b, &[0, 0]);
+        put_u32s(b, &[0; 3]);
+        put_u16s(b, &[width, height]);
+        // 72 dpi horizontally and vertically.
+        put_u32s(b, &[0x0048_0000, 0x0048_0000, 0]);
+        // Frame count.
+        put_u16(b, 1);
+        // Compressor name.
+        b.extend_from_slice(&[0; 32]);
+        put_u16s(b, &[0x0018, 0xFFFF]);
+        match parameters.codec {
+            Codec::H264 => write_box(b, b"avcC", |b| write_avc_config(b, sets, parameters)),
+            Codec::H265 => write_box(b, b"hvcC", |b| write_hevc_config(b, sets, parameters)),
+        }
+    });
+}
+
+/// Writes an `AVCDecoderConfigurationRecord` as defined by ISO/IEC 14496-15.
+fn write_avc_config(b: &mut Vec<u8>, sets: &ParameterSets, parameters: &CodecParameters) {
+    let sps = sets.sps().unwrap_or_default();
+    let pps = sets.pps().unwrap_or_default();
+    b.extend_from_slice(&[
+        1,
+        parameters.profile,
+        parameters.compatibility as u8,
+        parameters.level,
+        // 4 byte NAL unit lengths.
+        0xFF,
+        // One SPS.
+        0xE1,
+    ]);
+    put_nal(b, sps);
+    b.push(1);
+    put_nal(b, pps);
+    if matches!(parameters.profile, 100 | 110 | 122 | 144) {
+        b.extend_from_slice(&[
+            0xFC | parameters.chroma_format,
+            0xF8 | parameters.bit_depth_luma.saturating_sub(8),
+            0xF8 | parameters.bit_depth_chroma.saturating_sub(8),
+            0,
+        ]);
+    }
+}
+
+/// Writes an `HEVCDecoderConfigurationRecord` as defined by ISO/IEC 14496-15.
+fn write_hevc_config(b: &mut Vec<u8>, sets: &ParameterSets, parameters: &CodecParameters) {
+    b.push(1);
+    b.push((parameters.profile_space << 6) | (u8::from(parameters.tier) << 5) | parameters.profile);
+    put_u32(b, parameters.compatibility);
+    b.extend_from_slice(&parameters.constraint_flags.to_be_bytes()[2..]);
+    b.push(parameters.level);
+    // No minimum spatial segmentation and unknown parallelism.
+    put_u16(b, 0xF000);
+    b.push(0xFC);
+    b.push(0xFC | parameters.chroma_format);
+    b.push(0xF8 | parameters.bit_depth_luma.saturating_sub(8));
+    b.push(0xF8 | parameters.bit_depth_chroma.saturating_sub(8));
+    // Unknown frame rate and number of temporal layers, and 4 byte NAL unit lengths.
+    put_u16(b, 0);
+    b.push(0x03);
+    let arrays = [(32, sets.vps()), (33, sets.sps()), (34, sets.pps())];
+    b.push(arrays.len() as u8);
+    for (nal_type, nal) in arrays {
+        // The array is complete, i.e. there are no parameter sets of this type in the samples.
+        b.push(0x80 | nal_type);
+        put_u16(b, 1);
+        put_nal(b, nal.unwrap_or_default());
+    }
+}
+
+/// Appends a box with the content written by `content`.
+fn write_box(b: &mut Vec<u8>, kind: &[u8; 4], content: impl FnOnce(&mut Vec<u8>)) {
+    let start = b.len();
+    put_u32(b, 0);
+    b.extend_from_slice(kind);
+    content(b);
+    let size = (b.len() - start) as u32;
+    b[start..start + 4].copy_from_slice(&size.to_be_bytes());
+}
+
+/// Appends a box that starts with a version and flags.
+fn write_full_box(
+    b: &mut Vec<u8>,
+    kind: &[u8; 4],
+    version: u8,
+    flags: u32,
+    content: impl FnOnce(&mut Vec<u8>),
+) {
+    write_box(b, kind, |b| {
+        put_u32(b, (u32::from(version) << 24) | flags);
+        content(b);
+    });
+}
+
+fn put_nal(b: &mut Vec<u8>, nal: &[u8]) {
+    put_u16(b, nal.len() as u16);
+    b.extend_from_slice(nal);
+}
+
+fn put_u16(b: &mut Vec<u8>, value: u16) {
+    b.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u16s(b: &mut Vec<u8>, values: &[u16]) {
+    values.iter().for_each(|&v| put_u16(b, v));
+}
+
+fn put_u32(b: &mut Vec<u8>, value: u32) {
+    b.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_u32s(b: &mut Vec<u8>, values: &[u32]) {
+    values.iter().for_each(|&v| put_u32(b, v));
+}
+
+fn put_u64(b: &mut Vec<u8>, value: u64) {
+    b.extend_from_slice(&value.to_be_bytes());
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    const H264_SPS: [u8; 12] = [
+        0x67, 0x64, 0x00, 0x28, 0xAC, 0xCA, 0x80, 0x78, 0x02, 0x27, 0xE5, 0x40,
+    ];
+    const H264_PPS: [u8; 4] = [0x68, 0xEE, 0x3C, 0x80];
+    const H264_IDR: [u8; 5] = [0x65, 0x88, 0x84, 0x00, 0x10];
+    const H264_P: [u8; 3] = [0x41, 0x9A, 0x02];
+    const H265_VPS: [u8; 3] = [0x40, 0x01, 0x0C];
+    const H265_SPS: [u8; 42] = [
+        0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x03, 0x00, 0x78, 0xA0, 0x03, 0xC0, 0x80, 0x10, 0xE5, 0x96, 0x66, 0x69, 0x24, 0xCA, 0xE0,
+        0x10, 0x00, 0x00, 0x03, 0x00, 0x10, 0x00, 0x00, 0x03, 0x01, 0xE0, 0x80,
+    ];
+    const H265_PPS: [u8; 3] = [0x44, 0x01, 0xC1];
+    const H265_IDR: [u8; 4] = [0x26, 0x01, 0xAF, 0x10];
+
+    fn annexb(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|n| [&[0, 0, 0, 1], *n].concat())
+            .collect()
+    }
+
+    fn h264_keyframe() -> Vec<u8> {
+        annexb(&[&H264_SPS, &H264_PPS, &H264_IDR])
+    }
+
+    /// Returns the type and content of the boxes in `data`.
+    fn boxes(mut data: &[u8]) -> Vec<(String, &[u8])> {
+        let mut boxes = Vec::new();
+        while !data.is_empty() {
+            let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+            boxes.push((
+                String::from_utf8_lossy(&data[4..8]).into_owned(),
+                &data[8..size],
+            ));
+            data = &data[size..];
+        }
+        boxes
+    }
+
+    /// Returns the content of the first box at `path`, skipping the fields that precede the
+    /// children of boxes such as `stsd`.
+    fn find<'a>(data: &'a [u8], path: &[&str]) -> &'a [u8] {
+        let mut data = data;
+        for (i, kind) in path.iter().enumerate() {
+            let skip = match path.get(i.wrapping_sub(1)) {
+                Some(&"stsd") | Some(&"dref") => 8,
+                Some(&"avc1") | Some(&"hvc1") => 78,
+                _ => 0,
+            };
+            data = boxes(&data[skip..])
+                .into_iter()
+                .find(|(k, _)| k == kind)
+                .unwrap_or_else(|| panic!("no {kind} box"))
+                .1;
+        }
+        data
+    }
+
+    fn be32(data: &[u8], offset: usize) -> u32 {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn mux(codec: Codec, frames: &[(&[u8], u64, bool)]) -> Vec<u8> {
+        let mut muxer = Muxer::new(codec, Vec::new());
+        for (frame, timestamp, keyframe) in frames {
+            muxer.push(frame, *timestamp, *keyframe).unwrap();
+        }
+        muxer.finish().unwrap()
+    }
+
+    #[test]
+    fn init_segment_describes_h264_stream() {
+        let out = mux(Codec::H264, &[(&h264_keyframe(), 0, true)]);
+        assert_eq!(
+            boxes(&out).iter().map(|b| b.0.as_str()).collect::<Vec<_>>(),
+            ["ftyp", "moov", "moof", "mdat"]
+        );
+        let tkhd = find(&out, &["moov", "trak", "tkhd"]);
+        assert_eq!((be32(tkhd, 76), be32(tkhd, 80)), (1920 << 16, 1080 << 16));
+        let mdhd = find(&out, &["moov", "trak", "mdia", "mdhd"]);
+        assert_eq!(be32(mdhd, 12), 1_000_000);
+        let avcc = find(
+            &out,
+            &[
+                "moov", "trak", "mdia", "minf", "stbl", "stsd", "avc1", "avcC",
+            ],
+        );
+        let expected = [
+            &[1, 0x64, 0x00, 0x28, 0xFF, 0xE1, 0, 12][..],
+            &H264_SPS,
+            &[1, 0, 4],
+            &H264_PPS,
+            &[0xFD, 0xF8, 0xF8, 0],
+        ]
+        .concat();
+        assert_eq!(avcc, expected);
+    }
+
+    #[test]
+    fn init_segment_describes_h265_stream() {
+        let frame = annexb(&[&H265_VPS, &H265_SPS, &H265_PPS, &H265_IDR]);
+        let out = mux(Codec::H265, &[(&frame, 0, true)]);
+        let hvcc = find(
+            &out,
+            &[
+                "moov", "trak", "mdia", "minf", "stbl", "stsd", "hvc1", "hvcC",
+            ],
+        );
+        assert_eq!(
+            &hvcc[..13],
+            &[1, 0x01, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 120]
+        );
+        assert_eq!(&hvcc[16..22], &[0xFD, 0xF8, 0xF8, 0, 0, 0x03]);
+        assert_eq!(hvcc[22], 3);
+        assert_eq!(&hvcc[23..28], &[0xA0, 0, 1, 0, 3]);
+        assert_eq!(&hvcc[28..31], &H265_VPS);
+        let mdat = find(&out, &["mdat"]);
+        assert_eq!(mdat, [&[0, 0, 0, 4][..], &H265_IDR].concat());
+    }
+
+    #[test]
+    fn fragments_start_at_keyframes() {
+        let key = h264_keyframe();
+        let p = annexb(&[&H264_P]);
+        let out = mux(
+            Codec::H264,
+            &[
+                (&key, 1000, true),
+                (&p, 34333, false),
+                (&p, 67667, false),
+                (&key, 101000, true),
+                (&p, 134333, false),
+            ],
+        );
+        let top: Vec<_> = boxes(&out);
+        assert_eq!(
+            top.iter().map(|b| b.0.as_str()).collect::<Vec<_>>(),
+            ["ftyp", "moov", "moof", "mdat", "moof", "mdat"]
+        );
+
+        let moof = top[2].1;
+        assert_eq!(be32(find(moof, &["mfhd"]), 4), 1);
+        let tfdt = find(moof, &["traf", "tfdt"]);
+        assert_eq!(&tfdt[4..12], &0u64.to_be_bytes());
+        let trun = find(moof, &["traf", "trun"]);
+        assert_eq!(be32(trun, 4), 3);
+        let samples: Vec<_> = (0..3)
+            .map(|i| [0, 4, 8].map(|o| be32(trun, 12 + 12 * i + o)))
+            .collect();
+        assert_eq!(
+            samples,
+            [
+                [33333, 4 + 5, SAMPLE_IS_SYNC],
+                [33334, 4 + 3, SAMPLE_IS_NOT_SYNC],
+                [33333, 4 + 3, SAMPLE_IS_NOT_SYNC]
+            ]
+        );
+        assert_eq!(
+            top[3].1,
+            [
+                &[0, 0, 0, 5][..],
+                &H264_IDR,
+                &[0, 0, 0, 3],
+                &H264_P,
+                &[0, 0, 0, 3],
+                &H264_P
+            ]
+            .concat()
+        );
+
+        let moof = top[4].1;
+        assert_eq!(be32(find(moof, &["mfhd"]), 4), 2);
+        let tfdt = find(moof, &["traf", "tfdt"]);
+        assert_eq!(&tfdt[4..12], &100_000u64.to_be_bytes());
+        let trun = find(moof, &["traf", "trun"]);
+        assert_eq!(be32(trun, 4), 2);
+        // The last frame is assumed to be as long as the one before it.
+        assert_eq!((be32(trun, 12), be32(trun, 24)), (33333, 33333));
+    }
+
+    #[test]
+    fn data_offset_points_at_the_samples() {
+        let out = mux(Codec::H264, &[(&h264_keyframe(), 0, true)]);
+        let moof_start = out.len() - (8 + 4 + 5) - boxes(&out)[2].1.len() - 8;
+        let trun = find(boxes(&out)[2].1, &["traf", "trun"]);
+        let data_offset = be32(trun, 8) as usize;
+        assert_eq!(&out[moof_start + data_offset..][..4], &[0, 0, 0, 5]);
+        assert_eq!(&out[moof_start + data_offset + 4..], &H264_IDR);
+    }
+
+    #[test]
+    fn frames_before_the_first_keyframe_are_dropped() {
+        let p = annexb(&[&H264_P]);
+        let idr_without_parameter_sets = annexb(&[&H264_IDR]);
+        let out = mux(
+            Codec::H264,
+            &[
+                (&p, 0, false),
+                (&idr_without_parameter_sets, 10, true),
+                (&h264_keyframe(), 20, true),
+            ],
+        );
+        let top = boxes(&out);
+        assert_eq!(top.len(), 4);
+        assert_eq!(be32(find(top[2].1, &["traf", "trun"]), 4), 1);
+        assert!(mux(Codec::H264, &[(&p, 0, false)]).is_empty());
+    }
+
+    #[test]
+    fn changed_parameter_sets_are_rejected() {
+        let mut muxer = Muxer::new(Codec::H264, Vec::new());
+        muxer.push(&h264_keyframe(), 0, true).unwrap();
+        let changed = annexb(&[&H264_SPS, &[0x68, 0xCE, 0x3C, 0x80], &H264_IDR]);
+        assert!(matches!(
+            muxer.push(&changed, 1, true),
+            Err(Error::Bitstream(_))
+        ));
+    }
+}
@@ -0,0 +1,237 @@
+//! Async wrapper around VDO streams
+//!
+//! The file descriptors of the stream are registered with the tokio reactor, so the stream must
+//! be converted, and polled, from within a tokio runtime with IO enabled.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use futures_lite::StreamExt;
+//! use vdo::{StreamBuilder, VdoFormat};
+//!
+//! # async fn run() -> Result<(), vdo::Error> {
+//! let mut frames = StreamBuilder::new()
+//!     .format(VdoFormat::VDO_FORMAT_H264)
+//!     .build()?
+//!     .start()?
+//!     .into_async()?;
+//! while let Some(buffer) = frames.next().await {
+//!     println!("Frame size: {} bytes", buffer?.size());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    marker::PhantomData,
+    os::fd::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+use log::debug;
+use tokio::io::unix::AsyncFd;
+
+use crate::{Error, Map, RunningStream, Stream, StreamBuffer, StreamItem};
+
+/// A stream shared between an [`AsyncStream`] and the buffers it has produced.
+struct SharedStream(Stream);
+
+// SAFETY: Only the raw pointer is used through shared references, to unref buffers, which VDO
+// allows from any thread.
+unsafe impl Sync for SharedStream {}
+
+/// A file descriptor owned by VDO.
+struct VdoFd(RawFd);
+
+impl AsRawFd for VdoFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl RunningStream {
+    /// Converts the stream into one that yields frames asynchronously.
+    ///
+    /// Must be called from within a tokio runtime that has IO enabled.
+    pub fn into_async(self) -> Result<AsyncStream, Error> {
+        let stream = self.stream;
+        Ok(AsyncStream {
+            buffers: AsyncFd::new(VdoFd(stream.fd()?))?,
+            events: AsyncFd::new(VdoFd(stream.event_fd()?))?,
+            stream: Arc::new(SharedStream(stream)),
+            stopped: false,
+        })
+    }
+}
+
+/// A running video stream that yields frame buffers asynchronously.
+///
+/// Created using [`RunningStream::into_async()`]. The stream ends when VDO reports that it has
+/// been stopped or closed, e.g. because the settings of the channel changed. Use
+/// [`AsyncStream::items()`] to also receive the events of the stream. Drop this value to
+/// stop the stream; buffers that are still alive keep the underlying stream open until they are
+/// dropped.
+pub struct AsyncStream {
+    // Declared before `stream` so that the descriptors are deregistered before they are closed.
+    buffers: AsyncFd<VdoFd>,
+    events: AsyncFd<VdoFd>,
+    stream: Arc<SharedStream>,
+    stopped: bool,
+}
+
+impl AsyncStream {
+    /// Like [`RunningStream::set_framerate()`].
+    pub fn set_framerate(&self, framerate: f64) -> Result<(), Error> {
+        self.stream.0.set_framerate(framerate)
+    }
+
+    /// Like [`RunningStream::force_key_frame()`].
+    pub fn force_key_frame(&self) -> Result<(), Error> {
+        self.stream.0.force_key_frame()
+    }
+
+    /// Like [`RunningStream::set_settings()`].
+    pub fn set_settings(&self, settings: &Map) -> Result<(), Error> {
+        self.stream.0.set_settings(settings)
+    }
+
+    fn next_buffer(&self) -> Result<StreamBuffer<'static>, Error> {
+        let raw = self.stream.0.raw;
+        let (buffer_ptr, maybe_error) = unsafe { try_func!(vdo_sys::vdo_stream_get_buffer, raw) };
+        if buffer_ptr.is_null() {
+            return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+        }
+        Ok(StreamBuffer {
+            raw: buffer_ptr,
+            stream: raw,
+            _owner: Some(Arc::clone(&self.stream) as Arc<dyn Send + Sync>),
+            recycle: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns a stream of both the frames and the events of this stream.
+    ///
+    /// Unlike this stream, which only uses the events to end when the stream is stopped, it
+    /// yields every event. It ends after yielding an event that
+    /// [ends the stream](crate::StreamEvent::is_terminal).
+    pub fn items(&mut self) -> AsyncItems<'_> {
+        AsyncItems(self)
+    }
+
+    fn poll_item(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<StreamItem<'static>, Error>>> {
+        if self.stopped {
+            return Poll::Ready(None);
+        }
+        // Events take precedence over frames, like in `RunningStream::next_item()`.
+        match self.poll_event(cx) {
+            Ok(true) => return Poll::Ready(Some(self.next_event())),
+            Ok(false) => {}
+            Err(e) => return Poll::Ready(Some(Err(e))),
+        }
+        loop {
+            let mut guard = match ready!(self.buffers.poll_read_ready(cx)) {
+                Ok(guard) => guard,
+                Err(e) => return Poll::Ready(Some(Err(e.into()))),
+            };
+            // The readiness reported by tokio is edge triggered and may be stale, so check that
+            // a buffer is available to avoid blocking in `vdo_stream_get_buffer`.
+            if !is_readable(guard.get_inner()) {
+                guard.clear_ready();
+                continue;
+            }
+            drop(guard);
+            // The reactor may not have noticed an event that arrived together with the frame,
+            // such as the stream being stopped, which would make retrieving the frame fail.
+            if is_readable(self.events.get_ref()) {
+                return Poll::Ready(Some(self.next_event()));
+            }
+            return Poll::Ready(Some(self.next_buffer().map(StreamItem::Frame)));
+        }
+    }
+
+    /// Returns `true` if an event is pending.
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Result<bool, Error> {
+        while let Poll::Ready(guard) = self.events.poll_read_ready(cx) {
+            let mut guard = guard?;
+            if is_readable(guard.get_inner()) {
+                return Ok(true);
+            }
+            guard.clear_ready();
+        }
+        Ok(false)
+    }
+
+    fn next_event(&mut self) -> Result<StreamItem<'static>, Error> {
+        let event = self.stream.0.next_event()?;
+        self.stopped = event.is_terminal();
+        Ok(StreamItem::Event(event))
+    }
+}
+
+impl futures_lite::Stream for AsyncStream {
+    type Item = Result<StreamBuffer<'static>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match ready!(self.poll_item(cx)) {
+                Some(Ok(StreamItem::Frame(buffer))) => return Poll::Ready(Some(Ok(buffer))),
+                Some(Ok(StreamItem::Event(event))) => debug!("Received stream event {event:?}"),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// The frames and events of an [`AsyncStream`].
+///
+/// Created using [`AsyncStream::items()`].
+pub struct AsyncItems<'a>(&'a mut AsyncStream);
+
+impl futures_lite::Stream for AsyncItems<'_> {
+    type Item = Result<StreamItem<'static>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_item(cx)
+    }
+}
+
+/// Returns `true` if `fd` can be read from without blocking.
+fn is_readable(fd: &VdoFd) -> bool {
+    crate::event::poll_readable([fd.0], 0).is_ok_and(|[readable]| readable)
+}
+
+// These tests require a camera and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use futures_lite::StreamExt;
+
+    use super::*;
+    use crate::{Resolution, StreamBuilder, VdoFormat};
+
+    #[tokio::test]
+    async fn yields_buffers_that_outlive_the_stream() -> Result<(), Error> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut frames = StreamBuilder::new()
+            .format(VdoFormat::VDO_FORMAT_YUV)
+            .resolution(Resolution::Exact {
+                width: 640,
+                height: 360,
+            })
+            .build()?
+            .start()?
+            .into_async()?;
+        let first = frames.next().await.expect("stream ended")?;
+        let second = frames.next().await.expect("stream ended")?;
+        assert!(second.sequence_number() > first.sequence_number());
+        drop(frames);
+        assert!(first.size() > 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! Automatic recreation of streams that are stopped by the system.
+
+use std::{thread, time::Duration};
+
+use log::{debug, warn};
+
+use crate::{Error, RunningStream, StreamBuffer, StreamBuilder, StreamItem};
+
+/// A stream that is recreated whenever it stops, for long-running capture services.
+///
+/// Streams are stopped by the system e.g. when the capture mode of the camera changes. This
+/// wrapper notices this, from the events of the stream or from errors when retrieving frames,
+/// and starts a new stream from the same [`StreamBuilder`].
+///
+/// # Example
+///
+/// ```no_run
+/// use vdo::{RestartingStream, StreamBuilder, VdoFormat};
+///
+/// let mut stream = RestartingStream::new(StreamBuilder::new().format(VdoFormat::VDO_FORMAT_H264));
+/// loop {
+///     let buffer = stream.next_buffer()?;
+///     println!("Frame size: {} bytes", buffer.size());
+/// }
+/// # Ok::<(), vdo::Error>(())
+/// ```
+pub struct RestartingStream {
+    builder: StreamBuilder,
+    retry_delay: Duration,
+    stream: Option<RunningStream>,
+    restarts: u32,
+}
+
+impl RestartingStream {
+    /// Creates the wrapper; the stream is started on the first call to
+    /// [`next_buffer()`](RestartingStream::next_buffer).
+    pub fn new(builder: StreamBuilder) -> Self {
+        Self {
+            builder,
+            retry_delay: Duration::from_secs(1),
+            stream: None,
+            restarts: 0,
+        }
+    }
+
+    /// Default: 1 s. The time to wait before retrying when a stream cannot be started.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Returns the current stream, if one is running.
+    pub fn stream(&self) -> Option<&RunningStream> {
+        self.stream.as_ref()
+    }
+
+    /// The number of times the stream has been recreated.
+    pub fn restarts(&self) -> u32 {
+        self.restarts
+    }
+
+    /// Blocks until a new frame is available and returns it, restarting the stream as needed.
+    ///
+    /// Only errors that a restart cannot fix, i.e. invalid or unsupported configurations such as
+    /// [`Error::InvalidArgument`] and VDO errors with the code `VDO_ERROR_NOT_SUPPORTED`, are
+    /// returned. Other errors when starting the stream are retried after the
+    /// [retry delay](RestartingStream::retry_delay).
+    pub fn next_buffer(&mut self) -> Result<StreamBuffer<'_>, Error> {
+        let raw = loop {
+            let Some(stream) = &self.stream else {
+                self.start()?;
+                continue;
+            };
+            // The buffer is released from its borrow so that the stream can be replaced below.
+            let event = match stream.next_item() {
+                Ok(StreamItem::Frame(buffer)) => break buffer.into_raw(),
+                Ok(StreamItem::Event(event)) => Ok(event),
+                Err(e) => Err(e),
+            };
+            match event {
+                Ok(event) if !event.is_terminal() => debug!("Received stream event {event:?}"),
+                Ok(event) => {
+                    warn!("Stream ended with {event:?}, restarting");
+                    self.stop();
+                }
+                Err(e) => {
+                    warn!("Could not get buffer: {e}, restarting");
+                    self.stop();
+                }
+            }
+        };
+        let stream = self
+            .stream
+            .as_ref()
+            .expect("buffer was taken from the stream");
+        // SAFETY: The buffer was just taken from this stream, which has not been replaced since.
+        Ok(unsafe { stream.buffer_from_raw(raw) })
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        match self.builder.clone().build().and_then(|s| s.start()) {
+            Ok(stream) => self.stream = Some(stream),
+            Err(e) if is_fatal(&e) => return Err(e),
+            Err(e) => {
+                warn!("Could not start stream: {e}, retrying");
+                thread::sleep(self.retry_delay);
+            }
+        }
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.stream = None;
+        self.restarts += 1;
+    }
+}
+
+/// Returns `true` if `error` is caused by the configuration of the stream rather than by its
+/// current state.
+fn is_fatal(error: &Error) -> bool {
+    match error {
+        Error::InvalidArgument(_) => true,
+        Error::Vdo(e) => [
+            vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+            vdo_sys::VDO_ERROR_INVALID_ARGUMENT,
+        ]
+        .iter()
+        .any(|code| e.code() == code.0 as i32),
+        _ => false,
+    }
+}
+
+// These tests require a camera and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VdoFormat;
+
+    #[test]
+    fn yields_frames_without_restarting() -> Result<(), Error> {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut stream =
+            RestartingStream::new(StreamBuilder::new().format(VdoFormat::VDO_FORMAT_YUV));
+        for _ in 0..5 {
+            assert!(stream.next_buffer()?.size() > 0);
+        }
+        assert_eq!(stream.restarts(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn returns_invalid_configurations() {
+        let mut stream = RestartingStream::new(StreamBuilder::new().rotation(45));
+        assert!(matches!(
+            stream.next_buffer(),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+}
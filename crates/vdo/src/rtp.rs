@@ -0,0 +1,532 @@
+//! RTP packetization of H.264, H.265 and JPEG frames.
+//!
+//! [`Packetizer`] splits frames into RTP packets according to RFC 6184 (H.264, packetization
+//! mode 1), RFC 7798 (H.265) and RFC 2435 (JPEG). Each packet is returned complete with its RTP
+//! header, ready to be sent e.g. over UDP. The marker bit is set on the last packet of each frame
+//! and timestamps are converted to the 90 kHz clock used by all three formats.
+//!
+//! The packetizer only needs the frame data and timestamps, so recorded frames can be packetized
+//! on a host using [`Packetizer::packetize()`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::net::UdpSocket;
+//!
+//! use vdo::{
+//!     rtp::{Packetizer, PayloadFormat},
+//!     StreamBuilder, VdoFormat,
+//! };
+//!
+//! let socket = UdpSocket::bind("0.0.0.0:0")?;
+//! socket.connect("192.0.2.1:5004")?;
+//! let stream = StreamBuilder::new()
+//!     .format(VdoFormat::VDO_FORMAT_H264)
+//!     .build()?
+//!     .start()?;
+//! let mut packetizer = Packetizer::new(PayloadFormat::H264, 96, 0x1234_5678);
+//! loop {
+//!     for packet in packetizer.packetize_buffer(&stream.next_buffer()?)? {
+//!         socket.send(&packet)?;
+//!     }
+//! }
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use vdo_sys::VdoFormat;
+
+use crate::{
+    annexb::{nal_units, Codec},
+    Error, StreamBuffer,
+};
+
+/// The clock rate of the RTP timestamps of all supported formats.
+pub const CLOCK_RATE: u32 = 90_000;
+
+const HEADER_SIZE: usize = 12;
+const DEFAULT_MTU: usize = 1400;
+
+const H264_FU_A: u8 = 28;
+const H265_FU: u8 = 49;
+
+/// The size of the headers of RFC 2435 that precede the data in every packet.
+const JPEG_HEADER_SIZE: usize = 8;
+const JPEG_RESTART_HEADER_SIZE: usize = 4;
+/// The Q value signaling that the quantization tables are sent in the first packet of a frame.
+const JPEG_DYNAMIC_Q: u8 = 255;
+
+const INVALID_JPEG: &str = "invalid JPEG image";
+const UNSUPPORTED_SAMPLING: &str = "only 4:2:2 and 4:2:0 JPEG images are supported";
+const JPEG_TOO_LARGE: &str = "JPEG dimensions exceed 2040 pixels, the limit of RFC 2435";
+
+/// The payload formats supported by [`Packetizer`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PayloadFormat {
+    H264,
+    H265,
+    Jpeg,
+}
+
+impl PayloadFormat {
+    /// Returns the payload format for frames of `format`, or `None` if it is not supported.
+    pub fn from_format(format: VdoFormat) -> Option<Self> {
+        match format {
+            VdoFormat::VDO_FORMAT_H264 => Some(Self::H264),
+            VdoFormat::VDO_FORMAT_H265 => Some(Self::H265),
+            VdoFormat::VDO_FORMAT_JPEG => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+/// Splits frames into RTP packets.
+///
+/// RFC 3550 recommends that the SSRC, the initial sequence number and the timestamp offset are
+/// chosen randomly.
+#[derive(Clone, Debug)]
+pub struct Packetizer {
+    format: PayloadFormat,
+    payload_type: u8,
+    ssrc: u32,
+    sequence_number: u16,
+    timestamp_offset: u32,
+    mtu: usize,
+}
+
+impl Packetizer {
+    /// Creates a packetizer using the dynamic or static `payload_type` negotiated for the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `payload_type` does not fit in 7 bits.
+    pub fn new(format: PayloadFormat, payload_type: u8, ssrc: u32) -> Self {
+        assert!(payload_type < 128, "payload type must fit in 7 bits");
+        Self {
+            format,
+            payload_type,
+            ssrc,
+            sequence_number: 0,
+            timestamp_offset: 0,
+            mtu: DEFAULT_MTU,
+        }
+    }
+
+    /// Sets the sequence number of the next packet.
+    pub fn sequence_number(mut self, sequence_number: u16) -> Self {
+        self.sequence_number = sequence_number;
+        self
+    }
+
+    /// Sets the value added to all RTP timestamps.
+    pub fn timestamp_offset(mut self, offset: u32) -> Self {
+        self.timestamp_offset = offset;
+        self
+    }
+
+    /// Sets the maximum size of a packet, including the RTP header, in bytes.
+    ///
+    /// The default is 1400 bytes, which leaves room for IP and UDP headers and some tunneling
+    /// overhead on a typical Ethernet link.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mtu` leaves no room for payload data in the packets of any supported format.
+    pub fn mtu(mut self, mtu: usize) -> Self {
+        assert!(mtu > HEADER_SIZE + 132 + JPEG_HEADER_SIZE + JPEG_RESTART_HEADER_SIZE);
+        self.mtu = mtu;
+        self
+    }
+
+    /// Returns the sequence number of the next packet.
+    pub fn next_sequence_number(&self) -> u16 {
+        self.sequence_number
+    }
+
+    /// Converts a timestamp in microseconds to an RTP timestamp.
+    pub fn rtp_timestamp(&self, timestamp: u64) -> u32 {
+        let ticks = u128::from(timestamp) * u128::from(CLOCK_RATE) / 1_000_000;
+        (ticks as u32).wrapping_add(self.timestamp_offset)
+    }
+
+    /// Splits a frame into packets.
+    ///
+    /// H.264 and H.265 frames must be in the Annex B format and JPEG frames must be baseline
+    /// JPEG images with the standard Huffman tables, as produced by VDO. The timestamp is in
+    /// microseconds.
+    pub fn packetize(&mut self, frame: &[u8], timestamp: u64) -> Result<Vec<Vec<u8>>, Error> {
+        let payloads = match self.format {
+            PayloadFormat::H264 => self.nal_payloads(Codec::H264, frame),
+            PayloadFormat::H265 => self.nal_payloads(Codec::H265, frame),
+            PayloadFormat::Jpeg => self.jpeg_payloads(frame)?,
+        };
+        let timestamp = self.rtp_timestamp(timestamp);
+        let last = payloads.len().saturating_sub(1);
+        Ok(payloads
+            .into_iter()
+            .enumerate()
+            .map(|(i, payload)| self.packet(i == last, timestamp, &payload))
+            .collect())
+    }
+
+    /// Splits the frame of a stream into packets, using its timestamp.
+    pub fn packetize_buffer(&mut self, buffer: &StreamBuffer<'_>) -> Result<Vec<Vec<u8>>, Error> {
+        self.packetize(buffer.data()?, buffer.timestamp())
+    }
+
+    fn packet(&mut self, marker: bool, timestamp: u32, payload: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(HEADER_SIZE + payload.len());
+        packet.push(0x80);
+        packet.push((u8::from(marker) << 7) | self.payload_type);
+        packet.extend_from_slice(&self.sequence_number.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.sequence_number = self.sequence_number.wrapping_add(1);
+        packet
+    }
+
+    /// Splits the NAL units of a frame into single NAL unit packets and fragmentation units.
+    fn nal_payloads(&self, codec: Codec, frame: &[u8]) -> Vec<Vec<u8>> {
+        let max = self.mtu - HEADER_SIZE;
+        let mut payloads = Vec::new();
+        for nal in nal_units(codec, frame) {
+            let data = nal.data();
+            if data.len() <= max {
+                payloads.push(data.to_vec());
+                continue;
+            }
+            let header_size = codec.header_size();
+            let (fu_indicator, fu_type): (Vec<u8>, u8) = match codec {
+                // Keep the forbidden and NRI bits of the NAL unit.
+                Codec::H264 => (vec![(data[0] & 0xE0) | H264_FU_A], data[0] & 0x1F),
+                // Keep the forbidden bit, the layer id and the temporal id of the NAL unit.
+                Codec::H265 => (
+                    vec![(data[0] & 0x81) | (H265_FU << 1), data[1]],
+                    nal.nal_type(),
+                ),
+            };
+            let chunk_size = max - fu_indicator.len() - 1;
+            let chunks: Vec<_> = data[header_size..].chunks(chunk_size).collect();
+            let last = chunks.len() - 1;
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let start = if i == 0 { 0x80 } else { 0 };
+                let end = if i == last { 0x40 } else { 0 };
+                let mut payload = fu_indicator.clone();
+                payload.push(start | end | fu_type);
+                payload.extend_from_slice(chunk);
+                payloads.push(payload);
+            }
+        }
+        payloads
+    }
+
+    fn jpeg_payloads(&self, frame: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let jpeg = Jpeg::parse(frame)?;
+        let mut quantization = vec![0, 0];
+        quantization.extend_from_slice(&((jpeg.tables.len() * 64) as u16).to_be_bytes());
+        for table in &jpeg.tables {
+            quantization.extend_from_slice(table);
+        }
+        let restart_header = jpeg.restart_interval.map(|interval| {
+            let mut header = interval.to_be_bytes().to_vec();
+            // The packets are not aligned to restart intervals, so set the first and last bits
+            // and the maximum restart count.
+            header.extend_from_slice(&[0xFF, 0xFF]);
+            header
+        });
+        let type_ = jpeg.type_ + if restart_header.is_some() { 64 } else { 0 };
+
+        let mut payloads = Vec::new();
+        let mut offset = 0;
+        while offset < jpeg.scan.len() || payloads.is_empty() {
+            let mut payload = Vec::with_capacity(self.mtu - HEADER_SIZE);
+            payload.push(0);
+            payload.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            payload.extend_from_slice(&[
+                type_,
+                JPEG_DYNAMIC_Q,
+                jpeg.width_blocks,
+                jpeg.height_blocks,
+            ]);
+            if let Some(header) = &restart_header {
+                payload.extend_from_slice(header);
+            }
+            if offset == 0 {
+                payload.extend_from_slice(&quantization);
+            }
+            let size = (self.mtu - HEADER_SIZE - payload.len()).min(jpeg.scan.len() - offset);
+            payload.extend_from_slice(&jpeg.scan[offset..offset + size]);
+            offset += size;
+            payloads.push(payload);
+        }
+        if offset >= 1 << 24 {
+            return Err(Error::Bitstream(
+                "JPEG scan data exceeds the 24 bit offset of RFC 2435",
+            ));
+        }
+        Ok(payloads)
+    }
+}
+
+/// The parts of a JPEG image that are sent by RFC 2435.
+struct Jpeg<'a> {
+    /// 0 for 4:2:2 and 1 for 4:2:0.
+    type_: u8,
+    width_blocks: u8,
+    height_blocks: u8,
+    /// The quantization tables of the luma and chroma components, in zigzag order.
+    tables: Vec<&'a [u8]>,
+    restart_interval: Option<u16>,
+    /// The entropy coded data.
+    scan: &'a [u8],
+}
+
+impl<'a> Jpeg<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let invalid = || Error::Bitstream(INVALID_JPEG);
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err(invalid());
+        }
+        let mut tables: [Option<&[u8]>; 4] = [None; 4];
+        let mut frame = None;
+        let mut restart_interval = None;
+        let mut rest = &data[2..];
+        loop {
+            let [0xFF, marker, size_high, size_low, ..] = *rest else {
+                return Err(invalid());
+            };
+            let size = usize::from(u16::from_be_bytes([size_high, size_low]));
+            let segment = rest.get(4..2 + size).ok_or_else(invalid)?;
+            rest = &rest[2 + size..];
+            match marker {
+                // Define quantization tables.
+                0xDB => {
+                    let mut segment = segment;
+                    while let [pq_tq, ref table @ ..] = *segment {
+                        if pq_tq >> 4 != 0 {
+                            return Err(Error::Bitstream(
+                                "16 bit JPEG quantization tables are not supported",
+                            ));
+                        }
+                        let table = table.get(..64).ok_or_else(invalid)?;
+                        *tables
+                            .get_mut(usize::from(pq_tq & 0x0F))
+                            .ok_or_else(invalid)? = Some(table);
+                        segment = &segment[65..];
+                    }
+                }
+                // Baseline start of frame.
+                0xC0 => frame = Some(segment),
+                // Other start of frame markers.
+                0xC1..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                    return Err(Error::Bitstream("only baseline JPEG images are supported"));
+                }
+                // Define restart interval.
+                0xDD => {
+                    let &[high, low] = segment else {
+                        return Err(invalid());
+                    };
+                    restart_interval = Some(u16::from_be_bytes([high, low])).filter(|&i| i > 0);
+                }
+                // Start of scan.
+                0xDA => break,
+                _ => {}
+            }
+        }
+        let frame = frame.ok_or_else(invalid)?;
+        let [_precision, h_high, h_low, w_high, w_low, 3, ref components @ ..] = *frame else {
+            return Err(Error::Bitstream(
+                "only JPEG images with three components are supported",
+            ));
+        };
+        let component = |i: usize| -> Result<(u8, u8), Error> {
+            let c = components.get(3 * i..3 * i + 3).ok_or_else(invalid)?;
+            Ok((c[1], c[2]))
+        };
+        let (luma_sampling, luma_table) = component(0)?;
+        let type_ = match luma_sampling {
+            0x21 => 0,
+            0x22 => 1,
+            _ => return Err(Error::Bitstream(UNSUPPORTED_SAMPLING)),
+        };
+        let (cb_sampling, chroma_table) = component(1)?;
+        let (cr_sampling, cr_table) = component(2)?;
+        if cb_sampling != 0x11 || cr_sampling != 0x11 || cr_table != chroma_table {
+            return Err(Error::Bitstream(UNSUPPORTED_SAMPLING));
+        }
+        let tables = [luma_table, chroma_table]
+            .into_iter()
+            .map(|t| {
+                tables
+                    .get(usize::from(t))
+                    .copied()
+                    .flatten()
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let height = u16::from_be_bytes([h_high, h_low]);
+        let width = u16::from_be_bytes([w_high, w_low]);
+        let width_blocks =
+            u8::try_from(width.div_ceil(8)).map_err(|_| Error::Bitstream(JPEG_TOO_LARGE))?;
+        let height_blocks =
+            u8::try_from(height.div_ceil(8)).map_err(|_| Error::Bitstream(JPEG_TOO_LARGE))?;
+
+        // The scan starts after the start of scan segment and ends with the end of image marker.
+        let scan = rest.strip_suffix(&[0xFF, 0xD9]).unwrap_or(rest);
+        Ok(Self {
+            type_,
+            width_blocks,
+            height_blocks,
+            tables,
+            restart_interval,
+            scan,
+        })
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    fn header(packet: &[u8]) -> (bool, u8, u16, u32, u32) {
+        (
+            packet[1] & 0x80 != 0,
+            packet[1] & 0x7F,
+            u16::from_be_bytes([packet[2], packet[3]]),
+            u32::from_be_bytes(packet[4..8].try_into().unwrap()),
+            u32::from_be_bytes(packet[8..12].try_into().unwrap()),
+        )
+    }
+
+    /// A minimal 4:2:0 JPEG with a restart interval, the given size and scan data.
+    fn jpeg(width: u16, height: u16, restart_interval: u16, scan: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8];
+        data.extend_from_slice(&[0xFF, 0xDB, 0, 132, 0x00]);
+        data.extend_from_slice(&[1; 64]);
+        data.push(0x01);
+        data.extend_from_slice(&[2; 64]);
+        data.extend_from_slice(&[0xFF, 0xC0, 0, 17, 8]);
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        if restart_interval > 0 {
+            data.extend_from_slice(&[0xFF, 0xDD, 0, 4]);
+            data.extend_from_slice(&restart_interval.to_be_bytes());
+        }
+        data.extend_from_slice(&[0xFF, 0xC4, 0, 3, 0]);
+        data.extend_from_slice(&[0xFF, 0xDA, 0, 12, 3, 1, 0, 2, 0x11, 3, 0x11, 0, 63, 0]);
+        data.extend_from_slice(scan);
+        data.extend_from_slice(&[0xFF, 0xD9]);
+        data
+    }
+
+    #[test]
+    fn timestamps_use_90_khz_clock() {
+        let packetizer = Packetizer::new(PayloadFormat::H264, 96, 1).timestamp_offset(10);
+        assert_eq!(packetizer.rtp_timestamp(0), 10);
+        assert_eq!(packetizer.rtp_timestamp(1_000_000), 90_010);
+        assert_eq!(packetizer.rtp_timestamp(33_333), 3_009);
+        let packetizer = packetizer.timestamp_offset(u32::MAX);
+        assert_eq!(packetizer.rtp_timestamp(100), 8);
+    }
+
+    #[test]
+    fn small_nal_units_are_sent_as_is() {
+        let mut packetizer =
+            Packetizer::new(PayloadFormat::H264, 96, 0xDEADBEEF).sequence_number(65535);
+        let frame = [0, 0, 0, 1, 0x67, 1, 2, 0, 0, 1, 0x65, 3, 4];
+        let packets = packetizer.packetize(&frame, 1_000_000).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(header(&packets[0]), (false, 96, 65535, 90_000, 0xDEADBEEF));
+        assert_eq!(header(&packets[1]), (true, 96, 0, 90_000, 0xDEADBEEF));
+        assert_eq!(packets[0][0], 0x80);
+        assert_eq!(&packets[0][12..], &[0x67, 1, 2]);
+        assert_eq!(&packets[1][12..], &[0x65, 3, 4]);
+        assert_eq!(packetizer.next_sequence_number(), 1);
+    }
+
+    #[test]
+    fn large_h264_nal_units_are_fragmented() {
+        let mut packetizer = Packetizer::new(PayloadFormat::H264, 96, 1).mtu(200);
+        let nal: Vec<u8> = std::iter::once(0x65)
+            .chain((0..400).map(|i| (i % 251) as u8 + 1))
+            .collect();
+        let frame = [&[0, 0, 0, 1][..], &nal].concat();
+        let packets = packetizer.packetize(&frame, 0).unwrap();
+        assert_eq!(packets.len(), 3);
+        assert!(packets.iter().all(|p| p.len() <= 200));
+        let fu_headers: Vec<_> = packets.iter().map(|p| (p[12], p[13])).collect();
+        assert_eq!(fu_headers, [(0x7C, 0x85), (0x7C, 0x05), (0x7C, 0x45)]);
+        assert_eq!(
+            packets.iter().map(|p| header(p).0).collect::<Vec<_>>(),
+            [false, false, true]
+        );
+        let reassembled: Vec<u8> = packets.iter().flat_map(|p| p[14..].to_vec()).collect();
+        assert_eq!(reassembled, nal[1..]);
+    }
+
+    #[test]
+    fn large_h265_nal_units_are_fragmented() {
+        let mut packetizer = Packetizer::new(PayloadFormat::H265, 97, 1).mtu(200);
+        let nal: Vec<u8> = [0x26, 0x01].into_iter().chain([0xAB; 300]).collect();
+        let frame = [&[0, 0, 1][..], &nal].concat();
+        let packets = packetizer.packetize(&frame, 0).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(&packets[0][12..15], &[0x62, 0x01, 0x93]);
+        assert_eq!(&packets[1][12..15], &[0x62, 0x01, 0x53]);
+        assert_eq!(packets[0].len(), 200);
+        assert_eq!(packets[1].len(), 12 + 3 + 300 - 185);
+        assert!(header(&packets[1]).0);
+    }
+
+    #[test]
+    fn jpeg_is_split_with_quantization_tables_first() {
+        let mut packetizer = Packetizer::new(PayloadFormat::Jpeg, 26, 1).mtu(300);
+        let scan: Vec<u8> = (0..600).map(|i| (i % 200) as u8).collect();
+        let packets = packetizer
+            .packetize(&jpeg(1920, 1080, 0, &scan), 0)
+            .unwrap();
+        assert_eq!(packets.len(), 3);
+        // Type 1 (4:2:0), Q 255, 240x135 blocks.
+        assert_eq!(&packets[0][12..20], &[0, 0, 0, 0, 1, 255, 240, 135]);
+        assert_eq!(&packets[0][20..24], &[0, 0, 0, 128]);
+        assert_eq!(&packets[0][24..88], &[1; 64]);
+        assert_eq!(&packets[0][88..152], &[2; 64]);
+        let first = 300 - 12 - 8 - 132;
+        assert_eq!(&packets[0][152..], &scan[..first]);
+        assert_eq!(&packets[1][12..16], &(first as u32).to_be_bytes());
+        assert_eq!(&packets[1][20..], &scan[first..first + 280]);
+        assert_eq!(&packets[2][20..], &scan[first + 280..]);
+        assert_eq!(
+            packets.iter().map(|p| header(p).0).collect::<Vec<_>>(),
+            [false, false, true]
+        );
+    }
+
+    #[test]
+    fn jpeg_restart_interval_adds_restart_header() {
+        let mut packetizer = Packetizer::new(PayloadFormat::Jpeg, 26, 1);
+        let packets = packetizer
+            .packetize(&jpeg(640, 360, 40, &[1, 2, 3]), 0)
+            .unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(&packets[0][16..20], &[65, 255, 80, 45]);
+        assert_eq!(&packets[0][20..24], &[0, 40, 0xFF, 0xFF]);
+        assert_eq!(&packets[0][24 + 132..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn unsupported_jpeg_is_rejected() {
+        let mut packetizer = Packetizer::new(PayloadFormat::Jpeg, 26, 1);
+        assert!(packetizer.packetize(&[0xFF, 0xD8, 0xFF], 0).is_err());
+        assert!(packetizer.packetize(&jpeg(2048, 1080, 0, &[]), 0).is_err());
+        let mut progressive = jpeg(640, 360, 0, &[]);
+        let sof = progressive
+            .windows(2)
+            .position(|w| w == [0xFF, 0xC0])
+            .unwrap();
+        progressive[sof + 1] = 0xC2;
+        assert!(packetizer.packetize(&progressive, 0).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Capturing of single frames without managing a stream.
+
+use gobject_sys::{g_object_unref, GObject};
+use vdo_sys::{VdoBuffer, VdoFormat, VdoFrameType};
+
+use crate::{Error, Map};
+
+/// Captures a single frame using the given stream settings.
+///
+/// `settings` accepts the same keys as those set by [`StreamBuilder`](crate::StreamBuilder),
+/// e.g. `channel`, `format`, `width` and `height`. This is cheaper than creating and starting a
+/// stream for a single frame, making it suitable for periodic JPEG captures.
+///
+/// # Example
+///
+/// ```no_run
+/// use vdo::{Map, VdoFormat};
+///
+/// let mut settings = Map::new();
+/// settings.set_u32(c"format", VdoFormat::VDO_FORMAT_JPEG.0 as u32);
+/// settings.set_u32(c"width", 1920);
+/// settings.set_u32(c"height", 1080);
+/// let snapshot = vdo::snapshot(&settings)?;
+/// std::fs::write("snapshot.jpg", snapshot.data()).expect("Failed to write snapshot");
+/// # Ok::<(), vdo::Error>(())
+/// ```
+pub fn snapshot(settings: &Map) -> Result<Snapshot, Error> {
+    let (raw, maybe_error) = unsafe { try_func!(vdo_sys::vdo_stream_snapshot, settings.as_ptr()) };
+    if raw.is_null() {
+        return Err(maybe_error.unwrap_or(Error::MissingVdoError));
+    }
+    debug_assert!(
+        maybe_error.is_none(),
+        "vdo_stream_snapshot returned a buffer AND an error"
+    );
+    // Ensures the buffer is released also when copying fails.
+    let buffer = OwnedBuffer(raw);
+    buffer.copy()
+}
+
+/// Returns the format of frames of type `frame_type`, or `VDO_FORMAT_NONE` if it is unknown.
+fn format_of(frame_type: VdoFrameType) -> VdoFormat {
+    match frame_type {
+        VdoFrameType::VDO_FRAME_TYPE_H264_SPS
+        | VdoFrameType::VDO_FRAME_TYPE_H264_PPS
+        | VdoFrameType::VDO_FRAME_TYPE_H264_SEI
+        | VdoFrameType::VDO_FRAME_TYPE_H264_IDR
+        | VdoFrameType::VDO_FRAME_TYPE_H264_I
+        | VdoFrameType::VDO_FRAME_TYPE_H264_P
+        | VdoFrameType::VDO_FRAME_TYPE_H264_B => VdoFormat::VDO_FORMAT_H264,
+        VdoFrameType::VDO_FRAME_TYPE_H265_SPS
+        | VdoFrameType::VDO_FRAME_TYPE_H265_PPS
+        | VdoFrameType::VDO_FRAME_TYPE_H265_VPS
+        | VdoFrameType::VDO_FRAME_TYPE_H265_SEI
+        | VdoFrameType::VDO_FRAME_TYPE_H265_IDR
+        | VdoFrameType::VDO_FRAME_TYPE_H265_I
+        | VdoFrameType::VDO_FRAME_TYPE_H265_P
+        | VdoFrameType::VDO_FRAME_TYPE_H265_B => VdoFormat::VDO_FORMAT_H265,
+        VdoFrameType::VDO_FRAME_TYPE_AV1_KEY
+        | VdoFrameType::VDO_FRAME_TYPE_AV1_INTER
+        | VdoFrameType::VDO_FRAME_TYPE_AV1_BIDI => VdoFormat::VDO_FORMAT_AV1,
+        VdoFrameType::VDO_FRAME_TYPE_JPEG => VdoFormat::VDO_FORMAT_JPEG,
+        VdoFrameType::VDO_FRAME_TYPE_YUV => VdoFormat::VDO_FORMAT_YUV,
+        VdoFrameType::VDO_FRAME_TYPE_RAW => VdoFormat::VDO_FORMAT_RAW,
+        VdoFrameType::VDO_FRAME_TYPE_RGBA => VdoFormat::VDO_FORMAT_RGBA,
+        VdoFrameType::VDO_FRAME_TYPE_RGB => VdoFormat::VDO_FORMAT_RGB,
+        VdoFrameType::VDO_FRAME_TYPE_PLANAR_RGB => VdoFormat::VDO_FORMAT_PLANAR_RGB,
+        VdoFrameType::VDO_FRAME_TYPE_AVIF => VdoFormat::VDO_FORMAT_AVIF,
+        _ => VdoFormat::VDO_FORMAT_NONE,
+    }
+}
+
+/// A single frame captured using [`snapshot()`].
+///
+/// The frame data is copied out of VDO, so the snapshot holds no VDO resources.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    format: VdoFormat,
+    data: Vec<u8>,
+    frame_type: VdoFrameType,
+    timestamp: u64,
+}
+
+impl Snapshot {
+    /// The format of the captured frame, derived from its [frame type](Snapshot::frame_type).
+    ///
+    /// `VDO_FORMAT_NONE` if the frame type does not identify a format.
+    pub fn format(&self) -> VdoFormat {
+        self.format
+    }
+
+    pub fn frame_type(&self) -> VdoFrameType {
+        self.frame_type
+    }
+
+    /// The size of the frame data in bytes.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Timestamp in microseconds, from the same clock as [`StreamBuffer::timestamp()`](crate::StreamBuffer::timestamp).
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The frame data, excluding the header if one is present.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// A buffer that is not associated with a stream.
+struct OwnedBuffer(*mut VdoBuffer);
+
+impl OwnedBuffer {
+    fn copy(&self) -> Result<Snapshot, Error> {
+        let data = unsafe { vdo_sys::vdo_buffer_get_data(self.0) };
+        if data.is_null() {
+            return Err(Error::NullPointer);
+        }
+        let header_size = unsafe { vdo_sys::vdo_frame_get_header_size(self.0) };
+        let offset = usize::try_from(header_size).unwrap_or(0);
+        let size = unsafe { vdo_sys::vdo_frame_get_size(self.0) };
+        if offset > size {
+            return Err(Error::Image("header is larger than the frame"));
+        }
+        // SAFETY: offset..size lies within the buffer, which is fully initialized.
+        let slice =
+            unsafe { std::slice::from_raw_parts((data as *const u8).add(offset), size - offset) };
+        let frame_type = unsafe { vdo_sys::vdo_frame_get_frame_type(self.0) };
+        Ok(Snapshot {
+            format: format_of(frame_type),
+            data: slice.to_vec(),
+            frame_type,
+            timestamp: unsafe { vdo_sys::vdo_frame_get_timestamp(self.0) },
+        })
+    }
+}
+
+impl Drop for OwnedBuffer {
+    fn drop(&mut self) {
+        unsafe { g_object_unref(self.0 as *mut GObject) };
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+
+    #[test]
+    fn formats_follow_frame_types() {
+        assert_eq!(
+            format_of(VdoFrameType::VDO_FRAME_TYPE_H264_P),
+            VdoFormat::VDO_FORMAT_H264
+        );
+        assert_eq!(
+            format_of(VdoFrameType::VDO_FRAME_TYPE_H265_IDR),
+            VdoFormat::VDO_FORMAT_H265
+        );
+        assert_eq!(
+            format_of(VdoFrameType::VDO_FRAME_TYPE_JPEG),
+            VdoFormat::VDO_FORMAT_JPEG
+        );
+        assert_eq!(
+            format_of(VdoFrameType::VDO_FRAME_TYPE_NONE),
+            VdoFormat::VDO_FORMAT_NONE
+        );
+    }
+}
+
+// These tests require a camera and therefore must run on a device.
+#[cfg(not(any(target_arch = "x86_64", target_os = "macos")))]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jpeg_snapshot_has_magic_bytes() -> Result<(), Error> {
+        let mut settings = Map::new();
+        settings.set_u32(c"format", VdoFormat::VDO_FORMAT_JPEG.0 as u32);
+        settings.set_u32(c"width", 640);
+        settings.set_u32(c"height", 360);
+        let snapshot = snapshot(&settings)?;
+        assert_eq!(snapshot.format(), VdoFormat::VDO_FORMAT_JPEG);
+        assert_eq!(&snapshot.data()[..2], &[0xFF, 0xD8]);
+        assert_eq!(snapshot.size(), snapshot.data().len());
+        Ok(())
+    }
+}
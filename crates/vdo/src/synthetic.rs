@@ -0,0 +1,432 @@
+//! A synthetic, in-process implementation of VDO for testing on hosts without a camera.
+//!
+//! When the `synthetic` feature is active this crate defines the functions of `libvdo` that it
+//! uses itself, so the rest of the crate, and code built on it, runs unmodified on e.g. x86_64
+//! CI runners. The synthetic backend provides a single channel, `0`, that supports:
+//!
+//! - YUV (NV12), RGB and planar RGB frames with a moving test pattern.
+//! - JPEG and H.264 (constrained baseline) frames showing a flat gray image. H.264 streams start
+//!   with a key frame that carries the parameter sets, followed by predicted frames, and honour
+//!   [`gop_length`](crate::StreamBuilder::gop_length) and
+//!   [`force_key_frame()`](crate::RunningStream::force_key_frame).
+//! - Encoding of application-supplied frames using [`Encoder`](crate::encode::Encoder), which
+//!   produces the same kind of frames as a stream of the same format.
+//!
+//! Frames are paced by the framerate of the stream, 30 fps unless set, and are stamped with
+//! sequence numbers and the monotonic and UTC clocks like on a device. Settings that a device
+//! would reject, such as odd or oversized resolutions and unknown channels, are rejected with
+//! the same kind of [`VdoError`](crate::VdoError). The functions in this module inject failures
+//! that are otherwise hard to provoke, such as streams being stopped by the system.
+//!
+//! The feature must not be enabled when building for a device, since the definitions would
+//! shadow those of `libvdo`.
+//!
+//! # Example
+//!
+//! ```
+//! use vdo::{StreamBuilder, StreamEvent, StreamItem, VdoFormat};
+//!
+//! let stream = StreamBuilder::new()
+//!     .format(VdoFormat::VDO_FORMAT_YUV)
+//!     .framerate(100)
+//!     .build()?
+//!     .start()?;
+//! assert!(stream.next_buffer()?.size() > 0);
+//!
+//! vdo::synthetic::stop_streams();
+//! assert!(matches!(
+//!     stream.next_item()?,
+//!     StreamItem::Event(StreamEvent::Stopped)
+//! ));
+//! # Ok::<(), vdo::Error>(())
+//! ```
+
+use std::{
+    any::Any,
+    ffi::{c_void, CString},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use glib_sys::{gpointer, GError, GQuark};
+use gobject_sys::GObject;
+
+mod channel;
+mod map;
+mod media;
+mod stream;
+
+/// Stops all running streams, as the system does e.g. when the capture mode of the camera
+/// changes.
+///
+/// Each stream receives a [`StreamEvent::Stopped`](crate::StreamEvent::Stopped) and fails to
+/// deliver any more frames. This affects every stream in the process, so tests that use it should
+/// not run concurrently with other tests that stream.
+pub fn stop_streams() {
+    stream::stop_all();
+}
+
+/// Makes the creation of new streams fail with `VDO_ERROR_BUSY` while `unavailable` is `true`.
+///
+/// Like [`stop_streams()`], this affects the whole process.
+pub fn set_unavailable(unavailable: bool) {
+    UNAVAILABLE.store(unavailable, Ordering::SeqCst);
+}
+
+static UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+
+fn is_unavailable() -> bool {
+    UNAVAILABLE.load(Ordering::SeqCst)
+}
+
+fn state_quark() -> GQuark {
+    unsafe { glib_sys::g_quark_from_static_string(c"vdo-synthetic-state".as_ptr()) }
+}
+
+type State = Box<dyn Any + Send + Sync>;
+
+unsafe extern "C" fn drop_state(state: gpointer) {
+    drop(Box::from_raw(state as *mut State));
+}
+
+/// Creates a plain `GObject` that owns `state`, so that it can be unreferenced like the objects
+/// of `libvdo`. The state is dropped when the object is finalized.
+fn new_object<T: Any + Send + Sync>(state: T) -> *mut GObject {
+    unsafe {
+        let object = gobject_sys::g_object_new_with_properties(
+            gobject_sys::G_TYPE_OBJECT,
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null(),
+        );
+        let state: Box<State> = Box::new(Box::new(state));
+        gobject_sys::g_object_set_qdata_full(
+            object,
+            state_quark(),
+            Box::into_raw(state) as gpointer,
+            Some(drop_state),
+        );
+        object
+    }
+}
+
+/// Returns the state of an object created by [`new_object()`].
+///
+/// # Safety
+///
+/// `object` must be a live object created by [`new_object()`], and the returned reference must
+/// not outlive it.
+///
+/// # Panics
+///
+/// Panics if the state is not a `T`, i.e. if an object of another kind was passed to a function.
+unsafe fn state<'a, T: Any>(object: *const c_void) -> &'a T {
+    let state = gobject_sys::g_object_get_qdata(object as *mut GObject, state_quark());
+    assert!(
+        !state.is_null(),
+        "expected an object of the synthetic backend"
+    );
+    (*(state as *const State))
+        .downcast_ref()
+        .unwrap_or_else(|| panic!("expected a {}", std::any::type_name::<T>()))
+}
+
+/// An owned reference to an object.
+struct Object(*mut GObject);
+
+// SAFETY: GObject reference counting is thread safe and the state of the objects is `Sync`.
+unsafe impl Send for Object {}
+
+impl Object {
+    /// Transfers the reference to the caller.
+    fn into_raw<T>(self) -> *mut T {
+        let raw = self.0;
+        std::mem::forget(self);
+        raw as *mut T
+    }
+}
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        unsafe { gobject_sys::g_object_unref(self.0) };
+    }
+}
+
+/// Reports an error like `libvdo` does, returning `value` for convenience.
+///
+/// # Safety
+///
+/// `error` must be null or point to a null `GError` pointer.
+unsafe fn fail<T>(
+    error: *mut *mut GError,
+    code: vdo_sys::_bindgen_ty_24,
+    message: &str,
+    value: T,
+) -> T {
+    if !error.is_null() {
+        let message = CString::new(message).unwrap_or_default();
+        let domain = glib_sys::g_quark_from_static_string(c"vdo-error-quark".as_ptr());
+        *error = glib_sys::g_error_new_literal(domain, code.0 as i32, message.as_ptr());
+    }
+    value
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use std::{
+        sync::{Mutex, MutexGuard},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        annexb::Codec, clock::ClockOffset, encode::EncoderBuilder, image::ImageLayout, mp4::Muxer,
+        Channel, Error, Resolution, RestartingStream, StreamBuilder, StreamEvent, StreamItem,
+        VdoFormat, VdoFrameType,
+    };
+
+    // Serializes the tests since failures are injected in all streams of the process.
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn serial() -> MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn builder(format: VdoFormat) -> StreamBuilder {
+        StreamBuilder::new()
+            .format(format)
+            .resolution(Resolution::Exact {
+                width: 640,
+                height: 360,
+            })
+            .framerate(200)
+    }
+
+    #[test]
+    fn yuv_frames_are_paced_and_stamped() -> Result<(), Error> {
+        let _serial = serial();
+        let stream = builder(VdoFormat::VDO_FORMAT_YUV).build()?.start()?;
+        let layout = ImageLayout::from_info(&stream.info()?)?;
+        let first = stream.next_buffer()?;
+        let second = stream.next_buffer()?;
+        assert_eq!(first.frame_type(), VdoFrameType::VDO_FRAME_TYPE_YUV);
+        assert_eq!(first.size(), 640 * 360 * 3 / 2);
+        assert_eq!(second.sequence_number(), first.sequence_number() + 1);
+        assert!(second.timestamp() > first.timestamp());
+        let offset = ClockOffset::from_buffer(&first).expect("frame should have a UTC timestamp");
+        assert!((offset.as_micros() - ClockOffset::now().as_micros()).abs() < 100_000);
+        let y = first
+            .image(&layout)?
+            .y()
+            .expect("NV12 should have a Y plane");
+        assert_ne!(y.row(0), y.row(1));
+        Ok(())
+    }
+
+    #[test]
+    fn h264_frames_can_be_muxed() -> Result<(), Error> {
+        let _serial = serial();
+        let stream = builder(VdoFormat::VDO_FORMAT_H264)
+            .gop_length(4)
+            .build()?
+            .start()?;
+        let mut muxer = Muxer::new(Codec::H264, Vec::new());
+        let mut key_frames = 0;
+        for _ in 0..9 {
+            let buffer = stream.next_buffer()?;
+            if buffer.is_key() {
+                assert_eq!(buffer.frame_type(), VdoFrameType::VDO_FRAME_TYPE_H264_IDR);
+                key_frames += 1;
+            }
+            muxer.push_buffer(&buffer)?;
+        }
+        assert_eq!(key_frames, 3);
+        assert!(muxer.finish()?.len() > 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn i_frames_start_fragments() -> Result<(), Error> {
+        let _serial = serial();
+        let stream = builder(VdoFormat::VDO_FORMAT_H264)
+            .gop_length(4)
+            .build()?
+            .start()?;
+        let mut muxer = Muxer::new(Codec::H264, Vec::new());
+        for _ in 0..9 {
+            let buffer = stream.next_buffer()?;
+            if buffer.is_key() {
+                // Some platforms start groups of pictures with I frames rather than IDR frames.
+                // SAFETY: The buffer is a valid frame that is not used concurrently.
+                unsafe {
+                    vdo_sys::vdo_frame_set_frame_type(
+                        buffer.raw,
+                        VdoFrameType::VDO_FRAME_TYPE_H264_I,
+                    )
+                };
+            }
+            muxer.push_buffer(&buffer)?;
+        }
+        let mp4 = muxer.finish()?;
+        assert_eq!(mp4.windows(4).filter(|w| w == b"moof").count(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn jpeg_snapshot_is_an_image() -> Result<(), Error> {
+        let _serial = serial();
+        let mut settings = crate::Map::new();
+        settings.set_u32(c"format", VdoFormat::VDO_FORMAT_JPEG.0 as u32);
+        settings.set_u32(c"width", 320);
+        settings.set_u32(c"height", 240);
+        let snapshot = crate::snapshot(&settings)?;
+        assert_eq!(snapshot.frame_type(), VdoFrameType::VDO_FRAME_TYPE_JPEG);
+        assert_eq!(snapshot.format(), VdoFormat::VDO_FORMAT_JPEG);
+        assert_eq!(&snapshot.data()[..2], &[0xFF, 0xD8]);
+        assert_eq!(&snapshot.data()[snapshot.size() - 2..], &[0xFF, 0xD9]);
+        Ok(())
+    }
+
+    #[test]
+    fn unsupported_configurations_are_explained() -> Result<(), Error> {
+        let _serial = serial();
+        let err = builder(VdoFormat::VDO_FORMAT_H265)
+            .build()
+            .expect_err("H.265 should not be supported");
+        let Error::Vdo(e) = &err else {
+            panic!("Expected Error::Vdo, got: {err:?}");
+        };
+        assert_eq!(e.code_name(), "VDO_ERROR_NOT_SUPPORTED");
+        assert!(
+            e.explanation()
+                .is_some_and(|r| r.contains("supported formats are")),
+            "{e:?}"
+        );
+        let err = builder(VdoFormat::VDO_FORMAT_YUV)
+            .channel(3)
+            .build()
+            .expect_err("channel should not exist");
+        assert!(
+            err.to_string().contains("available channels are [0]"),
+            "{err}"
+        );
+        assert!(Channel::get(0)?
+            .supported_formats()
+            .contains(&VdoFormat::VDO_FORMAT_YUV));
+        Ok(())
+    }
+
+    #[test]
+    fn builders_fit_the_resolution_to_the_channel() -> Result<(), Error> {
+        let _serial = serial();
+        let fitted = builder(VdoFormat::VDO_FORMAT_YUV)
+            .resolution(Resolution::Exact {
+                width: 800,
+                height: 600,
+            })
+            .fit_resolution()?;
+        assert_eq!(
+            fitted.resolution,
+            Resolution::Exact {
+                width: 1280,
+                height: 720
+            }
+        );
+        fitted.build()?;
+        let err = builder(VdoFormat::VDO_FORMAT_H265)
+            .fit_resolution()
+            .err()
+            .expect("H.265 should not be supported");
+        assert!(matches!(err, Error::InvalidArgument(_)), "{err:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn stopped_streams_report_an_event() -> Result<(), Error> {
+        let _serial = serial();
+        let stream = builder(VdoFormat::VDO_FORMAT_YUV).build()?.start()?;
+        assert!(matches!(stream.next_item()?, StreamItem::Frame(_)));
+        stop_streams();
+        assert_eq!(stream.poll_event()?, Some(StreamEvent::Stopped));
+        assert!(stream.next_buffer().is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_streams_yield_events() -> Result<(), Error> {
+        use futures_lite::StreamExt;
+
+        let _serial = serial();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()?;
+        runtime.block_on(async {
+            let mut stream = builder(VdoFormat::VDO_FORMAT_YUV)
+                .build()?
+                .start()?
+                .into_async()?;
+            let mut items = stream.items();
+            let item = items.next().await.expect("stream ended")?;
+            assert!(matches!(item, StreamItem::Frame(_)));
+            stop_streams();
+            let item = items.next().await.expect("stream ended")?;
+            assert!(matches!(item, StreamItem::Event(StreamEvent::Stopped)));
+            assert!(items.next().await.is_none());
+            assert!(stream.next().await.is_none());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn restarting_stream_recovers() -> Result<(), Error> {
+        let _serial = serial();
+        let mut stream = RestartingStream::new(builder(VdoFormat::VDO_FORMAT_JPEG))
+            .retry_delay(Duration::from_millis(10));
+        stream.next_buffer()?;
+        set_unavailable(true);
+        stop_streams();
+        let unavailable = std::thread::spawn(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            set_unavailable(false);
+        });
+        assert!(stream.next_buffer()?.size() > 0);
+        unavailable.join().unwrap();
+        assert_eq!(stream.restarts(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn restarting_stream_returns_unsupported_configurations() {
+        let _serial = serial();
+        let mut stream = RestartingStream::new(builder(VdoFormat::VDO_FORMAT_H265))
+            .retry_delay(Duration::from_millis(10));
+        let err = stream
+            .next_buffer()
+            .err()
+            .expect("H.265 should not be supported");
+        assert!(
+            matches!(&err, Error::Vdo(e) if e.code_name() == "VDO_ERROR_NOT_SUPPORTED"),
+            "{err:?}"
+        );
+        assert_eq!(stream.restarts(), 0);
+    }
+
+    #[test]
+    fn encoder_encodes_supplied_frames() -> Result<(), Error> {
+        let _serial = serial();
+        let encoder = EncoderBuilder::new(320, 240)
+            .format(VdoFormat::VDO_FORMAT_H264)
+            .buffers(2)
+            .build()?;
+        for i in 0..4 {
+            let mut input = encoder.alloc_input()?;
+            input.write(&vec![128; 320 * 240 * 3 / 2])?;
+            input.set_timestamp(i * 40_000);
+            encoder.encode(input)?;
+            let output = encoder.next_buffer()?;
+            assert_eq!(output.timestamp(), i * 40_000);
+            assert_eq!(output.is_key(), i == 0);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//! The single channel of the synthetic backend.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{c_uint, c_void},
+};
+
+use glib::Variant;
+use glib_sys::{GError, GList};
+use vdo_sys::{VdoChannel, VdoFormat, VdoMap, VdoResolution, VdoResolutionSet};
+
+use super::{fail, map, media::Generator, new_object, state};
+
+/// The number of the only channel.
+pub(super) const ID: u32 = 0;
+
+/// The largest resolution that streams can be created with.
+pub(super) const MAX_RESOLUTION: (u32, u32) = (3840, 2160);
+
+/// The resolutions reported by [`vdo_channel_get_resolutions`].
+///
+/// Streams can be created with any resolution up to [`MAX_RESOLUTION`], like on devices that
+/// scale the image, but these are the ones that are advertised.
+const RESOLUTIONS: [(u32, u32); 4] = [(640, 360), (640, 480), (1280, 720), (1920, 1080)];
+
+/// The framerate of the channel, which is also the default framerate of streams.
+pub(super) const FRAMERATE: u32 = 30;
+
+struct ChannelState {
+    id: u32,
+}
+
+fn new() -> *mut VdoChannel {
+    new_object(ChannelState { id: ID }).cast()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_channel_get(
+    channel_nbr: c_uint,
+    error: *mut *mut GError,
+) -> *mut VdoChannel {
+    if channel_nbr != ID {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NOT_FOUND,
+            &format!("Channel {channel_nbr} does not exist"),
+            std::ptr::null_mut(),
+        );
+    }
+    new()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_channel_get_all(_error: *mut *mut GError) -> *mut GList {
+    glib_sys::g_list_append(std::ptr::null_mut(), new() as *mut c_void)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_channel_get_id(self_: *mut VdoChannel) -> c_uint {
+    state::<ChannelState>(self_.cast()).id
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_channel_get_info(
+    _self: *mut VdoChannel,
+    _error: *mut *mut GError,
+) -> *mut VdoMap {
+    let (width, height) = RESOLUTIONS[RESOLUTIONS.len() - 1];
+    map::new(BTreeMap::from([
+        ("width".to_string(), Variant::from(width)),
+        ("height".to_string(), Variant::from(height)),
+    ]))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_channel_get_settings(
+    _self: *mut VdoChannel,
+    _error: *mut *mut GError,
+) -> *mut VdoMap {
+    map::new(BTreeMap::from([
+        ("rotation".to_string(), Variant::from(0u32)),
+        ("framerate".to_string(), Variant::from(FRAMERATE)),
+    ]))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_channel_get_resolutions(
+    _self: *mut VdoChannel,
+    filter: *mut VdoMap,
+    _error: *mut *mut GError,
+) -> *mut VdoResolutionSet {
+    let format = if filter.is_null() {
+        None
+    } else {
+        map::entries(filter).get("format").and_then(map::integer)
+    };
+    let supported = match format {
+        None => true,
+        Some(format) => i32::try_from(format)
+            .is_ok_and(|format| Generator::new(VdoFormat(format), 2, 2).is_some()),
+    };
+    let resolutions: &[(u32, u32)] = if supported { &RESOLUTIONS } else { &[] };
+
+    // The set is freed using `g_free`, so it must be allocated by GLib.
+    let size = size_of::<VdoResolutionSet>() + size_of_val(resolutions);
+    let set = glib_sys::g_malloc0(size) as *mut VdoResolutionSet;
+    (*set).count = resolutions.len();
+    let entries = (*set).resolutions.as_mut_slice(resolutions.len());
+    for (entry, &(width, height)) in entries.iter_mut().zip(resolutions) {
+        *entry = VdoResolution { width, height };
+    }
+    set
+}
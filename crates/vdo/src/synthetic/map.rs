@@ -0,0 +1,454 @@
+//! Maps, which are dictionaries of variants like in `libvdo`.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{c_char, CStr},
+    sync::Mutex,
+};
+
+use glib::{
+    translate::{from_glib_none, IntoGlib, ToGlibPtr},
+    Variant, VariantClass, VariantDict,
+};
+use glib_sys::{gboolean, GVariant, GFALSE};
+use vdo_sys::{VdoMap, VdoPair32i, VdoPair32u, VdoQuad32i, VdoQuad32u};
+
+use super::{new_object, state};
+
+type Entries = BTreeMap<String, Variant>;
+
+#[derive(Default)]
+struct MapState(Mutex<Entries>);
+
+/// Creates a map with the given entries.
+pub(super) fn new(entries: Entries) -> *mut VdoMap {
+    new_object(MapState(Mutex::new(entries))).cast()
+}
+
+/// Returns a copy of the entries of `map`.
+///
+/// # Safety
+///
+/// `map` must be a live map.
+pub(super) unsafe fn entries(map: *const VdoMap) -> Entries {
+    lock(map).clone()
+}
+
+/// Converts an integer of any width and signedness.
+pub(super) fn integer(value: &Variant) -> Option<i128> {
+    match value.classify() {
+        VariantClass::Byte => value.get::<u8>().map(i128::from),
+        VariantClass::Int16 => value.get::<i16>().map(i128::from),
+        VariantClass::Uint16 => value.get::<u16>().map(i128::from),
+        VariantClass::Int32 => value.get::<i32>().map(i128::from),
+        VariantClass::Uint32 => value.get::<u32>().map(i128::from),
+        VariantClass::Int64 => value.get::<i64>().map(i128::from),
+        VariantClass::Uint64 => value.get::<u64>().map(i128::from),
+        VariantClass::Variant => value.as_variant().and_then(|v| integer(&v)),
+        _ => None,
+    }
+}
+
+/// Returns the value of `name`, if `map` has one.
+///
+/// # Safety
+///
+/// `map` must be a live map and `name` a valid string.
+unsafe fn get(map: *const VdoMap, name: *const c_char) -> Option<Variant> {
+    lock(map).get(key(name)?).cloned()
+}
+
+unsafe fn get_integer<T: TryFrom<i128>>(map: *const VdoMap, name: *const c_char, def: T) -> T {
+    get(map, name)
+        .and_then(|v| integer(&v))
+        .and_then(|v| T::try_from(v).ok())
+        .unwrap_or(def)
+}
+
+/// Returns the integers of a tuple or an array of `N` integers.
+unsafe fn get_integers<T: TryFrom<i128> + Copy, const N: usize>(
+    map: *const VdoMap,
+    name: *const c_char,
+    def: [T; N],
+) -> [T; N] {
+    let Some(value) = get(map, name) else {
+        return def;
+    };
+    if !value.is_container() || value.n_children() != N {
+        return def;
+    }
+    let mut values = def;
+    for (i, slot) in values.iter_mut().enumerate() {
+        match integer(&value.child_value(i)).and_then(|v| T::try_from(v).ok()) {
+            Some(v) => *slot = v,
+            None => return def,
+        }
+    }
+    values
+}
+
+unsafe fn set(map: *mut VdoMap, name: *const c_char, value: Variant) {
+    if let Some(key) = key(name) {
+        lock(map).insert(key.to_string(), value);
+    }
+}
+
+unsafe fn key<'a>(name: *const c_char) -> Option<&'a str> {
+    if name.is_null() {
+        return None;
+    }
+    CStr::from_ptr(name).to_str().ok()
+}
+
+unsafe fn lock<'a>(map: *const VdoMap) -> std::sync::MutexGuard<'a, Entries> {
+    state::<MapState>(map.cast())
+        .0
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+#[no_mangle]
+extern "C" fn vdo_map_new() -> *mut VdoMap {
+    new(Entries::new())
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_new_from_variant(dictionary: *mut GVariant) -> *mut VdoMap {
+    let dictionary: Variant = from_glib_none(dictionary);
+    if dictionary.type_() != glib::VariantTy::VARDICT {
+        return std::ptr::null_mut();
+    }
+    let entries = dictionary
+        .iter()
+        .filter_map(|entry| {
+            let key = entry.child_value(0).str()?.to_string();
+            Some((key, entry.child_value(1).as_variant()?))
+        })
+        .collect();
+    new(entries)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_to_variant(self_: *const VdoMap) -> *mut GVariant {
+    let dict = VariantDict::new(None);
+    for (key, value) in lock(self_).iter() {
+        dict.insert_value(key, value);
+    }
+    dict.end().to_glib_full()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_clone(other: *const VdoMap) -> *mut VdoMap {
+    new(entries(other))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_merge(self_: *mut VdoMap, map: *const VdoMap) {
+    // The entries are copied first, since the maps may be the same.
+    let other = entries(map);
+    lock(self_).extend(other);
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_filter_prefix(
+    self_: *const VdoMap,
+    prefix: *const c_char,
+) -> *mut VdoMap {
+    let prefix = key(prefix).unwrap_or_default();
+    let mut entries = entries(self_);
+    entries.retain(|k, _| k.starts_with(prefix));
+    new(entries)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_equals(self_: *const VdoMap, map: *const VdoMap) -> gboolean {
+    (entries(self_) == entries(map)).into_glib()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_contains(self_: *const VdoMap, name: *const c_char) -> gboolean {
+    get(self_, name).is_some().into_glib()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_remove(self_: *mut VdoMap, name: *const c_char) {
+    if let Some(key) = key(name) {
+        lock(self_).remove(key);
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_clear(self_: *mut VdoMap) {
+    lock(self_).clear();
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_size(self_: *const VdoMap) -> usize {
+    lock(self_).len()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_empty(self_: *const VdoMap) -> gboolean {
+    lock(self_).is_empty().into_glib()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_dump(self_: *const VdoMap) {
+    for (key, value) in lock(self_).iter() {
+        println!("{key}: {}", value.print(true));
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_boolean(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: gboolean,
+) -> gboolean {
+    get(self_, name)
+        .and_then(|v| v.get::<bool>())
+        .map_or(def, |v| v.into_glib())
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_double(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: f64,
+) -> f64 {
+    match get(self_, name) {
+        Some(v) => v
+            .get::<f64>()
+            .or_else(|| integer(&v).map(|i| i as f64))
+            .unwrap_or(def),
+        None => def,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_int32(self_: *const VdoMap, name: *const c_char, def: i32) -> i32 {
+    get_integer(self_, name, def)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_uint32(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: u32,
+) -> u32 {
+    get_integer(self_, name, def)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_int64(self_: *const VdoMap, name: *const c_char, def: i64) -> i64 {
+    get_integer(self_, name, def)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_uint64(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: u64,
+) -> u64 {
+    get_integer(self_, name, def)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_dup_string(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: *const c_char,
+) -> *mut c_char {
+    match get(self_, name).as_ref().and_then(Variant::str) {
+        Some(value) => value.to_glib_full(),
+        None => glib_sys::g_strdup(def),
+    }
+}
+
+// The pairs and quads are unions of arrays and named fields, so they are converted like the
+// safe wrappers do.
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_pair32i(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: VdoPair32i,
+) -> VdoPair32i {
+    let def = std::mem::transmute::<VdoPair32i, [i32; 2]>(def);
+    std::mem::transmute::<[i32; 2], VdoPair32i>(get_integers(self_, name, def))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_pair32u(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: VdoPair32u,
+) -> VdoPair32u {
+    let def = std::mem::transmute::<VdoPair32u, [u32; 2]>(def);
+    std::mem::transmute::<[u32; 2], VdoPair32u>(get_integers(self_, name, def))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_quad32i(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: VdoQuad32i,
+) -> VdoQuad32i {
+    let def = std::mem::transmute::<VdoQuad32i, [i32; 4]>(def);
+    std::mem::transmute::<[i32; 4], VdoQuad32i>(get_integers(self_, name, def))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_get_quad32u(
+    self_: *const VdoMap,
+    name: *const c_char,
+    def: VdoQuad32u,
+) -> VdoQuad32u {
+    let def = std::mem::transmute::<VdoQuad32u, [u32; 4]>(def);
+    std::mem::transmute::<[u32; 4], VdoQuad32u>(get_integers(self_, name, def))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_boolean(self_: *mut VdoMap, name: *const c_char, value: gboolean) {
+    set(self_, name, Variant::from(value != GFALSE));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_byte(self_: *mut VdoMap, name: *const c_char, value: u8) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_int16(self_: *mut VdoMap, name: *const c_char, value: i16) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_uint16(self_: *mut VdoMap, name: *const c_char, value: u16) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_int32(self_: *mut VdoMap, name: *const c_char, value: i32) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_uint32(self_: *mut VdoMap, name: *const c_char, value: u32) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_int64(self_: *mut VdoMap, name: *const c_char, value: i64) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_uint64(self_: *mut VdoMap, name: *const c_char, value: u64) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_double(self_: *mut VdoMap, name: *const c_char, value: f64) {
+    set(self_, name, Variant::from(value));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_string(
+    self_: *mut VdoMap,
+    name: *const c_char,
+    value: *const c_char,
+) {
+    if let Some(value) = key(value) {
+        set(self_, name, Variant::from(value));
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_pair32i(
+    self_: *mut VdoMap,
+    name: *const c_char,
+    value: VdoPair32i,
+) {
+    let [a, b] = std::mem::transmute::<VdoPair32i, [i32; 2]>(value);
+    set(self_, name, Variant::from((a, b)));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_pair32u(
+    self_: *mut VdoMap,
+    name: *const c_char,
+    value: VdoPair32u,
+) {
+    let [a, b] = std::mem::transmute::<VdoPair32u, [u32; 2]>(value);
+    set(self_, name, Variant::from((a, b)));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_quad32i(
+    self_: *mut VdoMap,
+    name: *const c_char,
+    value: VdoQuad32i,
+) {
+    let [a, b, c, d] = std::mem::transmute::<VdoQuad32i, [i32; 4]>(value);
+    set(self_, name, Variant::from((a, b, c, d)));
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_map_set_quad32u(
+    self_: *mut VdoMap,
+    name: *const c_char,
+    value: VdoQuad32u,
+) {
+    let [a, b, c, d] = std::mem::transmute::<VdoQuad32u, [u32; 4]>(value);
+    set(self_, name, Variant::from((a, b, c, d)));
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use crate::{map::Value, Map};
+
+    #[test]
+    fn values_round_trip() {
+        let mut map = Map::new();
+        map.set_u32(c"width", 1920);
+        map.set_string(c"subformat", c"NV12");
+        map.set_pair_i32(c"offset", [-1, 2]);
+        map.set_quad_u32(c"crop", [1, 2, 3, 4]);
+        map.set_bool(c"enabled", true);
+        assert_eq!(map.get_u32(c"width", 0), 1920);
+        assert_eq!(map.get_i64(c"width", 0), 1920);
+        assert_eq!(map.get_f64(c"width", 0.0), 1920.0);
+        assert_eq!(map.get_i32(c"missing", 7), 7);
+        assert_eq!(map.get_string(c"subformat").unwrap().as_c_str(), c"NV12");
+        assert_eq!(map.get_pair_i32(c"offset", [0, 0]), [-1, 2]);
+        assert_eq!(map.get_quad_u32(c"crop", [0; 4]), [1, 2, 3, 4]);
+        assert!(map.get_bool(c"enabled", false));
+        assert_eq!(map.len(), 5);
+    }
+
+    #[test]
+    fn mismatched_types_give_the_default() {
+        let mut map = Map::new();
+        map.set_i32(c"negative", -1);
+        map.set_string(c"text", c"1");
+        assert_eq!(map.get_u32(c"negative", 5), 5);
+        assert_eq!(map.get_u32(c"text", 5), 5);
+        assert_eq!(map.get_pair_u32(c"negative", [5, 5]), [5, 5]);
+    }
+
+    #[test]
+    fn maps_convert_to_and_from_variants() {
+        let mut map = Map::new();
+        map.set_u32(c"format", 3);
+        map.set(c"list", &Value::List(vec![Value::Byte(1); 3]));
+        let copy = Map::from_variant(&map.to_variant()).unwrap();
+        assert_eq!(copy.entries(), map.entries());
+
+        let mut merged = Map::new();
+        merged.set_u32(c"format", 1);
+        merged.set_u32(c"stream.width", 640);
+        merged.merge(&map);
+        assert_eq!(merged.get_u32(c"format", 0), 3);
+        assert_eq!(merged.filter_prefix(c"stream.").len(), 1);
+    }
+}
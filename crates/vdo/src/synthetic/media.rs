@@ -0,0 +1,547 @@
+//! Generation of the frames of synthetic streams.
+//!
+//! Raw frames show a gradient that moves with the sequence number, so that consecutive frames
+//! differ. Encoded frames show a flat gray image, which keeps the encoders trivial while still
+//! producing bitstreams that decoders and the parsers of this crate accept.
+
+use vdo_sys::{VdoFormat, VdoFrameType};
+
+/// A frame produced by a [`Generator`].
+pub(super) struct Frame {
+    pub data: Vec<u8>,
+    pub frame_type: VdoFrameType,
+    pub key: bool,
+}
+
+/// Produces the frames of a stream with a given format and resolution.
+pub(super) struct Generator {
+    width: u32,
+    height: u32,
+    kind: Kind,
+}
+
+enum Kind {
+    Yuv,
+    Rgb,
+    PlanarRgb,
+    // The image never changes, so the frame is only encoded once.
+    Jpeg(Vec<u8>),
+    H264(H264),
+}
+
+impl Generator {
+    /// Returns `None` if `format` is not supported.
+    pub fn new(format: VdoFormat, width: u32, height: u32) -> Option<Self> {
+        let kind = match format {
+            VdoFormat::VDO_FORMAT_YUV => Kind::Yuv,
+            VdoFormat::VDO_FORMAT_RGB => Kind::Rgb,
+            VdoFormat::VDO_FORMAT_PLANAR_RGB => Kind::PlanarRgb,
+            VdoFormat::VDO_FORMAT_JPEG => Kind::Jpeg(jpeg(width, height)),
+            VdoFormat::VDO_FORMAT_H264 => Kind::H264(H264::new(width, height)),
+            _ => return None,
+        };
+        Some(Self {
+            width,
+            height,
+            kind,
+        })
+    }
+
+    /// The size of the buffers needed for the frames, which is also the size of the input frames
+    /// of encoders.
+    pub fn capacity(&self) -> usize {
+        let pixels = self.width as usize * self.height as usize;
+        match self.kind {
+            Kind::Rgb | Kind::PlanarRgb => pixels * 3,
+            Kind::Yuv => pixels * 3 / 2,
+            // Encoders take YUV frames as input, which are larger than the encoded frames apart
+            // from the headers of tiny images.
+            Kind::Jpeg(_) | Kind::H264(_) => pixels * 3 / 2 + 1024,
+        }
+    }
+
+    /// Produces the frame with sequence number `sequence`.
+    ///
+    /// `key` requests a key frame, which raw and JPEG frames always are.
+    pub fn frame(&mut self, sequence: u32, key: bool) -> Frame {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (data, frame_type, key) = match &mut self.kind {
+            Kind::Yuv => (
+                nv12(width, height, sequence),
+                VdoFrameType::VDO_FRAME_TYPE_YUV,
+                true,
+            ),
+            Kind::Rgb => (
+                rgb(width, height, sequence),
+                VdoFrameType::VDO_FRAME_TYPE_RGB,
+                true,
+            ),
+            Kind::PlanarRgb => (
+                planar_rgb(width, height, sequence),
+                VdoFrameType::VDO_FRAME_TYPE_PLANAR_RGB,
+                true,
+            ),
+            Kind::Jpeg(data) => (data.clone(), VdoFrameType::VDO_FRAME_TYPE_JPEG, true),
+            Kind::H264(encoder) if key => (
+                encoder.key_frame(),
+                VdoFrameType::VDO_FRAME_TYPE_H264_IDR,
+                true,
+            ),
+            Kind::H264(encoder) => (
+                encoder.predicted_frame(),
+                VdoFrameType::VDO_FRAME_TYPE_H264_P,
+                false,
+            ),
+        };
+        Frame {
+            data,
+            frame_type,
+            key,
+        }
+    }
+}
+
+/// The intensity of the test pattern at a position.
+fn pattern(x: usize, y: usize, sequence: u32) -> u8 {
+    (x + y + 2 * sequence as usize) as u8
+}
+
+fn nv12(width: usize, height: usize, sequence: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity(width * height * 3 / 2);
+    for y in 0..height {
+        data.extend((0..width).map(|x| pattern(x, y, sequence)));
+    }
+    data.resize(width * height * 3 / 2, 128);
+    data
+}
+
+fn rgb(width: usize, height: usize, sequence: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let value = pattern(x, y, sequence);
+            data.extend_from_slice(&[value, value.wrapping_add(85), value.wrapping_add(170)]);
+        }
+    }
+    data
+}
+
+fn planar_rgb(width: usize, height: usize, sequence: u32) -> Vec<u8> {
+    let mut data = Vec::with_capacity(width * height * 3);
+    for offset in [0u8, 85, 170] {
+        for y in 0..height {
+            data.extend((0..width).map(|x| pattern(x, y, sequence).wrapping_add(offset)));
+        }
+    }
+    data
+}
+
+/// The number of codes of each length, and the symbols in order of their codes, of the
+/// Huffman tables suggested in Annex K of the JPEG standard.
+///
+/// These are the tables assumed by RFC 2435, so images using them can be sent over RTP.
+const DC_LUMA: ([u8; 16], &[u8]) = (
+    [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+    &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+);
+const DC_CHROMA: ([u8; 16], &[u8]) = (
+    [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+    &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+);
+const AC_LUMA: ([u8; 16], &[u8]) = (
+    [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D],
+    &[
+        0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61,
+        0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52,
+        0xD1, 0xF0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25,
+        0x26, 0x27, 0x28, 0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45,
+        0x46, 0x47, 0x48, 0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64,
+        0x65, 0x66, 0x67, 0x68, 0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83,
+        0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99,
+        0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6,
+        0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3,
+        0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8,
+        0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA,
+    ],
+);
+const AC_CHROMA: ([u8; 16], &[u8]) = (
+    [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+    &[
+        0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61,
+        0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33,
+        0x52, 0xF0, 0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18,
+        0x19, 0x1A, 0x26, 0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44,
+        0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63,
+        0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A,
+        0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+        0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4,
+        0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA,
+        0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7,
+        0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8, 0xF9, 0xFA,
+    ],
+);
+
+/// Returns the canonical code of `symbol` in a Huffman table, as `(code, length)`.
+fn huffman_code((counts, symbols): ([u8; 16], &[u8]), symbol: u8) -> (u32, u32) {
+    let mut code = 0;
+    let mut index = 0;
+    for (length, &count) in (1..).zip(&counts) {
+        for _ in 0..count {
+            if symbols[index] == symbol {
+                return (code, length);
+            }
+            code += 1;
+            index += 1;
+        }
+        code <<= 1;
+    }
+    panic!("symbol {symbol:#04x} is not in the table");
+}
+
+/// Encodes a baseline 4:2:0 JPEG image of mid gray.
+///
+/// Every block has a DC difference of zero and no AC coefficients, which decodes to 128 in all
+/// components.
+fn jpeg(width: u32, height: u32) -> Vec<u8> {
+    const EOB: u8 = 0x00;
+    let mut data = vec![0xFF, 0xD8];
+
+    data.extend_from_slice(&[0xFF, 0xDB, 0, 2 + 2 * 65]);
+    for table in 0..2 {
+        data.push(table);
+        data.extend_from_slice(&[16; 64]);
+    }
+
+    data.extend_from_slice(&[0xFF, 0xC0, 0, 17, 8]);
+    data.extend_from_slice(&(height as u16).to_be_bytes());
+    data.extend_from_slice(&(width as u16).to_be_bytes());
+    data.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+
+    let tables = [
+        (0x00, DC_LUMA),
+        (0x10, AC_LUMA),
+        (0x01, DC_CHROMA),
+        (0x11, AC_CHROMA),
+    ];
+    let size = 2 + tables
+        .iter()
+        .map(|(_, (_, symbols))| 17 + symbols.len())
+        .sum::<usize>();
+    data.extend_from_slice(&[0xFF, 0xC4]);
+    data.extend_from_slice(&(size as u16).to_be_bytes());
+    for (class_and_id, (counts, symbols)) in tables {
+        data.push(class_and_id);
+        data.extend_from_slice(&counts);
+        data.extend_from_slice(symbols);
+    }
+
+    data.extend_from_slice(&[0xFF, 0xDA, 0, 12, 3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+    let mut scan = BitWriter::default();
+    let luma = [huffman_code(DC_LUMA, 0), huffman_code(AC_LUMA, EOB)];
+    let chroma = [huffman_code(DC_CHROMA, 0), huffman_code(AC_CHROMA, EOB)];
+    let mcus = width.div_ceil(16) * height.div_ceil(16);
+    for _ in 0..mcus {
+        for block in [luma, luma, luma, luma, chroma, chroma] {
+            for (code, length) in block {
+                scan.bits(code, length);
+            }
+        }
+    }
+    // The last byte is padded with ones, and bytes of ones are followed by a zero byte to tell
+    // them apart from markers.
+    while !scan.is_byte_aligned() {
+        scan.bit(true);
+    }
+    for byte in scan.into_bytes() {
+        data.push(byte);
+        if byte == 0xFF {
+            data.push(0);
+        }
+    }
+
+    data.extend_from_slice(&[0xFF, 0xD9]);
+    data
+}
+
+/// Encodes constrained baseline H.264 of mid gray.
+///
+/// Key frames consist of intra macroblocks that are predicted from their neighbours without a
+/// residual, and predicted frames skip all macroblocks.
+pub(super) struct H264 {
+    width: u32,
+    height: u32,
+    frame_num: u32,
+    idr_pic_id: u32,
+}
+
+const LOG2_MAX_FRAME_NUM: u32 = 4;
+
+impl H264 {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            frame_num: 0,
+            idr_pic_id: 0,
+        }
+    }
+
+    fn macroblocks(&self) -> (u32, u32) {
+        (self.width.div_ceil(16), self.height.div_ceil(16))
+    }
+
+    /// Returns the parameter sets followed by an IDR picture.
+    fn key_frame(&mut self) -> Vec<u8> {
+        let (mbs_wide, mbs_high) = self.macroblocks();
+        let mut data = Vec::new();
+
+        let mut sps = BitWriter::default();
+        sps.bits(66, 8); // profile_idc: Baseline
+        sps.bits(0xC0, 8); // constraint_set0_flag and constraint_set1_flag: Constrained Baseline
+        sps.bits(if mbs_wide * mbs_high <= 8192 { 40 } else { 51 }, 8); // level_idc
+        sps.ue(0); // seq_parameter_set_id
+        sps.ue(LOG2_MAX_FRAME_NUM - 4); // log2_max_frame_num_minus4
+        sps.ue(2); // pic_order_cnt_type: output in decoding order
+        sps.ue(1); // max_num_ref_frames
+        sps.bit(false); // gaps_in_frame_num_value_allowed_flag
+        sps.ue(mbs_wide - 1); // pic_width_in_mbs_minus1
+        sps.ue(mbs_high - 1); // pic_height_in_map_units_minus1
+        sps.bit(true); // frame_mbs_only_flag
+        sps.bit(true); // direct_8x8_inference_flag
+        let (crop_right, crop_bottom) = (mbs_wide * 16 - self.width, mbs_high * 16 - self.height);
+        sps.bit(crop_right > 0 || crop_bottom > 0); // frame_cropping_flag
+        if crop_right > 0 || crop_bottom > 0 {
+            // The offsets are in units of two pixels for 4:2:0.
+            sps.ue(0);
+            sps.ue(crop_right / 2);
+            sps.ue(0);
+            sps.ue(crop_bottom / 2);
+        }
+        sps.bit(false); // vui_parameters_present_flag
+        nal_unit(&mut data, 0x67, sps);
+
+        let mut pps = BitWriter::default();
+        pps.ue(0); // pic_parameter_set_id
+        pps.ue(0); // seq_parameter_set_id
+        pps.bit(false); // entropy_coding_mode_flag: CAVLC
+        pps.bit(false); // bottom_field_pic_order_in_frame_present_flag
+        pps.ue(0); // num_slice_groups_minus1
+        pps.ue(0); // num_ref_idx_l0_default_active_minus1
+        pps.ue(0); // num_ref_idx_l1_default_active_minus1
+        pps.bit(false); // weighted_pred_flag
+        pps.bits(0, 2); // weighted_bipred_idc
+        pps.se(0); // pic_init_qp_minus26
+        pps.se(0); // pic_init_qs_minus26
+        pps.se(0); // chroma_qp_index_offset
+        pps.bit(true); // deblocking_filter_control_present_flag
+        pps.bit(false); // constrained_intra_pred_flag
+        pps.bit(false); // redundant_pic_cnt_present_flag
+        nal_unit(&mut data, 0x68, pps);
+
+        let mut slice = BitWriter::default();
+        slice.ue(0); // first_mb_in_slice
+        slice.ue(7); // slice_type: I, as are all slices of the picture
+        slice.ue(0); // pic_parameter_set_id
+        slice.bits(0, LOG2_MAX_FRAME_NUM); // frame_num
+        slice.ue(self.idr_pic_id % 2); // idr_pic_id, which differs between consecutive IDRs
+        slice.bit(false); // no_output_of_prior_pics_flag
+        slice.bit(false); // long_term_reference_flag
+        slice.se(0); // slice_qp_delta
+        slice.ue(1); // disable_deblocking_filter_idc
+        for _ in 0..mbs_wide * mbs_high {
+            slice.ue(3); // mb_type: I_16x16_2_0_0, DC prediction without coefficients
+            slice.ue(0); // intra_chroma_pred_mode: DC
+            slice.se(0); // mb_qp_delta
+            slice.bit(true); // coeff_token of the luma DC: no coefficients
+        }
+        nal_unit(&mut data, 0x65, slice);
+
+        self.idr_pic_id += 1;
+        self.frame_num = 1;
+        data
+    }
+
+    /// Returns a P picture that repeats the previous picture.
+    fn predicted_frame(&mut self) -> Vec<u8> {
+        let (mbs_wide, mbs_high) = self.macroblocks();
+        let mut data = Vec::new();
+        let mut slice = BitWriter::default();
+        slice.ue(0); // first_mb_in_slice
+        slice.ue(5); // slice_type: P, as are all slices of the picture
+        slice.ue(0); // pic_parameter_set_id
+        slice.bits(self.frame_num, LOG2_MAX_FRAME_NUM); // frame_num
+        slice.bit(false); // num_ref_idx_active_override_flag
+        slice.bit(false); // ref_pic_list_modification_flag_l0
+        slice.bit(false); // adaptive_ref_pic_marking_mode_flag
+        slice.se(0); // slice_qp_delta
+        slice.ue(1); // disable_deblocking_filter_idc
+        slice.ue(mbs_wide * mbs_high); // mb_skip_run
+        nal_unit(&mut data, 0x41, slice);
+
+        self.frame_num = (self.frame_num + 1) % (1 << LOG2_MAX_FRAME_NUM);
+        data
+    }
+}
+
+/// Appends a NAL unit with a start code, inserting emulation prevention bytes into the payload.
+fn nal_unit(data: &mut Vec<u8>, header: u8, mut payload: BitWriter) {
+    // rbsp_trailing_bits
+    payload.bit(true);
+    while !payload.is_byte_aligned() {
+        payload.bit(false);
+    }
+    data.extend_from_slice(&[0, 0, 0, 1, header]);
+    let mut zeros = 0;
+    for byte in payload.into_bytes() {
+        if zeros >= 2 && byte <= 3 {
+            data.push(3);
+            zeros = 0;
+        }
+        data.push(byte);
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+    }
+}
+
+/// Writes bits, most significant first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    // The number of bits used in the last byte, 8 when it is full.
+    used: u32,
+}
+
+impl BitWriter {
+    fn bit(&mut self, bit: bool) {
+        if self.used % 8 == 0 {
+            self.bytes.push(0);
+            self.used = 0;
+        }
+        if bit {
+            *self.bytes.last_mut().expect("a byte was pushed") |= 0x80 >> self.used;
+        }
+        self.used += 1;
+    }
+
+    /// Writes the `length` least significant bits of `value`.
+    fn bits(&mut self, value: u32, length: u32) {
+        for i in (0..length).rev() {
+            self.bit((value >> i) & 1 != 0);
+        }
+    }
+
+    /// Writes an unsigned Exp-Golomb code.
+    fn ue(&mut self, value: u32) {
+        let value = u64::from(value) + 1;
+        let length = 64 - value.leading_zeros();
+        self.bits(0, length - 1);
+        for i in (0..length).rev() {
+            self.bit((value >> i) & 1 != 0);
+        }
+    }
+
+    /// Writes a signed Exp-Golomb code.
+    fn se(&mut self, value: i32) {
+        let mapped = if value > 0 {
+            2 * value.unsigned_abs() - 1
+        } else {
+            2 * value.unsigned_abs()
+        };
+        self.ue(mapped);
+    }
+
+    fn is_byte_aligned(&self) -> bool {
+        self.used % 8 == 0
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use super::*;
+    use crate::{
+        annexb::{nal_units, Codec, CodecParameters},
+        rtp::{Packetizer, PayloadFormat},
+    };
+
+    #[test]
+    fn huffman_tables_are_complete() {
+        for (counts, symbols) in [DC_LUMA, DC_CHROMA, AC_LUMA, AC_CHROMA] {
+            let total: usize = counts.iter().map(|&c| usize::from(c)).sum();
+            assert_eq!(total, symbols.len());
+        }
+        assert_eq!(AC_LUMA.1.len(), 162);
+        assert_eq!(huffman_code(DC_LUMA, 0), (0b00, 2));
+        assert_eq!(huffman_code(AC_LUMA, 0x00), (0b1010, 4));
+        assert_eq!(huffman_code(AC_CHROMA, 0x00), (0b00, 2));
+    }
+
+    #[test]
+    fn exp_golomb_codes() {
+        let mut w = BitWriter::default();
+        w.ue(0);
+        w.ue(3);
+        w.se(-1);
+        w.se(1);
+        w.bits(0, 3);
+        // 1 00100 011 010 000
+        assert_eq!(w.into_bytes(), [0b1001_0001, 0b1010_0000]);
+    }
+
+    #[test]
+    fn emulation_is_prevented() {
+        let mut w = BitWriter::default();
+        w.bits(0x0000_0100, 32);
+        let mut data = Vec::new();
+        nal_unit(&mut data, 0x41, w);
+        assert_eq!(data, [0, 0, 0, 1, 0x41, 0, 0, 3, 1, 0, 0x80]);
+    }
+
+    #[test]
+    fn h264_parameters_match_resolution() {
+        let mut encoder = H264::new(640, 360);
+        let frame = encoder.key_frame();
+        let types: Vec<_> = nal_units(Codec::H264, &frame)
+            .map(|n| n.nal_type())
+            .collect();
+        assert_eq!(types, [7, 8, 5]);
+        let sps = nal_units(Codec::H264, &frame).next().unwrap();
+        let parameters = CodecParameters::from_sps(Codec::H264, sps.data()).unwrap();
+        assert_eq!((parameters.width, parameters.height), (640, 360));
+        assert_eq!(parameters.codec_string(), "avc1.42C028");
+
+        let frame = encoder.predicted_frame();
+        let nal = nal_units(Codec::H264, &frame).next().unwrap();
+        assert!(nal.is_slice() && !nal.is_keyframe());
+    }
+
+    #[test]
+    fn jpeg_can_be_packetized() {
+        let mut generator = Generator::new(vdo_sys::VdoFormat::VDO_FORMAT_JPEG, 320, 240).unwrap();
+        let frame = generator.frame(0, false);
+        assert!(frame.key);
+        let packets = Packetizer::new(PayloadFormat::Jpeg, 26, 1)
+            .packetize(&frame.data, 0)
+            .unwrap();
+        assert_eq!(packets.len(), 1);
+        // Type 1 is 4:2:0 and the dimensions are in units of 8 pixels.
+        assert_eq!(&packets[0][16..20], &[1, 255, 40, 30]);
+    }
+
+    #[test]
+    fn raw_frames_fill_the_buffer() {
+        for format in [
+            VdoFormat::VDO_FORMAT_YUV,
+            VdoFormat::VDO_FORMAT_RGB,
+            VdoFormat::VDO_FORMAT_PLANAR_RGB,
+        ] {
+            let mut generator = Generator::new(format, 64, 48).unwrap();
+            let first = generator.frame(0, false);
+            let second = generator.frame(1, false);
+            assert_eq!(first.data.len(), generator.capacity());
+            assert_ne!(first.data, second.data);
+        }
+    }
+}
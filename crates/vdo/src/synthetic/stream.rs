@@ -0,0 +1,886 @@
+//! Streams, their buffers and snapshots.
+//!
+//! Streams with the INFINITE strategy produce a frame each time the timer of the stream expires,
+//! and the descriptor returned by `vdo_stream_get_fd` is that timer. Streams with the EXPLICIT
+//! strategy are encoders; they produce a frame for each call to `vdo_stream_encode`, into
+//! buffers that have been enqueued, and signal it using an event descriptor.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::c_void,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+};
+
+use glib::{translate::IntoGlib, Variant};
+use glib_sys::{gboolean, gpointer, GError, GFALSE, GTRUE};
+use vdo_sys::{
+    VdoBuffer, VdoBufferFinalizer, VdoBufferStrategy, VdoFormat, VdoFrameType, VdoMap, VdoStream,
+    VdoStreamEvent,
+};
+
+use super::{
+    channel, fail, is_unavailable, map,
+    media::{Frame, Generator},
+    new_object, state, Object,
+};
+use crate::clock;
+
+/// The default number of frames between key frames.
+const GOP_LENGTH: u32 = 32;
+
+/// The streams that have been created and not yet finalized.
+static STREAMS: Mutex<Vec<Weak<Shared>>> = Mutex::new(Vec::new());
+
+pub(super) fn stop_all() {
+    let streams = lock(&STREAMS).clone();
+    for shared in streams.iter().filter_map(Weak::upgrade) {
+        let mut inner = shared.lock();
+        if inner.started && !inner.stopped {
+            inner.stopped = true;
+            shared.push_event(&mut inner, VdoStreamEvent::VDO_STREAM_EVENT_STOPPED);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The configuration of a stream, as derived from its settings.
+struct Config {
+    format: VdoFormat,
+    width: u32,
+    height: u32,
+    framerate: f64,
+    gop_length: u32,
+    explicit: bool,
+}
+
+impl Config {
+    /// Validates the settings like VDO does, returning the error code and message on failure.
+    fn new(
+        settings: &BTreeMap<String, Variant>,
+    ) -> Result<Self, (vdo_sys::_bindgen_ty_24, String)> {
+        let integer = |key: &str, default: u32| -> Result<u32, _> {
+            match settings.get(key) {
+                None => Ok(default),
+                Some(value) => map::integer(value)
+                    .and_then(|v| u32::try_from(v).ok())
+                    .ok_or_else(|| {
+                        (
+                            vdo_sys::VDO_ERROR_INVALID_ARGUMENT,
+                            format!("Invalid value for {key}: {value}"),
+                        )
+                    }),
+            }
+        };
+        let channel = integer("channel", channel::ID)?;
+        if channel != channel::ID {
+            return Err((
+                vdo_sys::VDO_ERROR_NOT_FOUND,
+                format!("Channel {channel} does not exist"),
+            ));
+        }
+        let format = VdoFormat(integer("format", VdoFormat::VDO_FORMAT_H264.0 as u32)? as i32);
+        let width = integer("width", 1920)?;
+        let height = integer("height", 1080)?;
+        let (max_width, max_height) = channel::MAX_RESOLUTION;
+        if width == 0 || height == 0 || width % 2 != 0 || height % 2 != 0 {
+            return Err((
+                vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+                format!("The resolution {width}x{height} is not supported"),
+            ));
+        }
+        if width > max_width || height > max_height {
+            return Err((
+                vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+                format!("The resolution {width}x{height} exceeds {max_width}x{max_height}"),
+            ));
+        }
+        let framerate = match settings.get("framerate") {
+            Some(value) => value
+                .get::<f64>()
+                .or_else(|| map::integer(value).map(|v| v as f64))
+                .unwrap_or(0.0),
+            None => f64::from(channel::FRAMERATE),
+        };
+        if framerate <= 0.0 {
+            return Err((
+                vdo_sys::VDO_ERROR_INVALID_ARGUMENT,
+                "The framerate must be positive".to_string(),
+            ));
+        }
+        let strategy = VdoBufferStrategy(integer(
+            "buffer.strategy",
+            VdoBufferStrategy::VDO_BUFFER_STRATEGY_INFINITE.0,
+        )?);
+        let explicit = strategy == VdoBufferStrategy::VDO_BUFFER_STRATEGY_EXPLICIT;
+        if explicit && ![VdoFormat::VDO_FORMAT_JPEG, VdoFormat::VDO_FORMAT_H264].contains(&format) {
+            return Err((
+                vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+                "Only encoded formats can be used with explicit buffers".to_string(),
+            ));
+        }
+        Ok(Self {
+            format,
+            width,
+            height,
+            framerate,
+            gop_length: integer("gop_length", GOP_LENGTH)?.max(1),
+            explicit,
+        })
+    }
+
+    fn generator(&self) -> Result<Generator, (vdo_sys::_bindgen_ty_24, String)> {
+        Generator::new(self.format, self.width, self.height).ok_or_else(|| {
+            (
+                vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+                format!(
+                    "The format {} is not supported",
+                    crate::channel::format_name(self.format)
+                ),
+            )
+        })
+    }
+
+    fn info(&self) -> BTreeMap<String, Variant> {
+        let pitch = match self.format {
+            VdoFormat::VDO_FORMAT_RGB => self.width * 3,
+            _ => self.width,
+        };
+        let mut info = BTreeMap::from([
+            ("channel".to_string(), Variant::from(channel::ID)),
+            ("format".to_string(), Variant::from(self.format.0 as u32)),
+            ("width".to_string(), Variant::from(self.width)),
+            ("height".to_string(), Variant::from(self.height)),
+            ("pitch".to_string(), Variant::from(pitch)),
+            ("framerate".to_string(), Variant::from(self.framerate)),
+            ("gop_length".to_string(), Variant::from(self.gop_length)),
+            ("rotation".to_string(), Variant::from(0u32)),
+        ]);
+        if self.format == VdoFormat::VDO_FORMAT_YUV {
+            info.insert("subformat".to_string(), Variant::from("NV12"));
+        }
+        info
+    }
+}
+
+struct StreamState {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    config: Config,
+    /// The timer that paces the frames, or for encoders a semaphore counting the encoded frames.
+    frames: OwnedFd,
+    /// A semaphore counting the pending events.
+    events: OwnedFd,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    settings: BTreeMap<String, Variant>,
+    generator: Generator,
+    framerate: f64,
+    started: bool,
+    stopped: bool,
+    sequence: u32,
+    since_key: Option<u32>,
+    events: VecDeque<VdoStreamEvent>,
+    /// Buffers enqueued by the application, waiting to be encoded into.
+    free: VecDeque<Object>,
+    /// Encoded buffers waiting to be retrieved.
+    encoded: VecDeque<Object>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        lock(&self.inner)
+    }
+
+    fn push_event(&self, inner: &mut Inner, event: VdoStreamEvent) {
+        inner.events.push_back(event);
+        signal(&self.events);
+    }
+
+    /// Arms the timer with the framerate, or disarms it if `framerate` is `None`.
+    fn set_timer(&self, framerate: Option<f64>) -> io::Result<()> {
+        let period = framerate.map_or(0, |f| (1e9 / f).max(1.0) as i64);
+        let period = libc::timespec {
+            tv_sec: (period / 1_000_000_000) as libc::time_t,
+            tv_nsec: (period % 1_000_000_000) as libc::c_long,
+        };
+        let spec = libc::itimerspec {
+            it_interval: period,
+            it_value: period,
+        };
+        let ret = unsafe {
+            libc::timerfd_settime(self.frames.as_raw_fd(), 0, &spec, std::ptr::null_mut())
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Produces the next frame into `buffer`.
+    fn produce(&self, inner: &mut Inner, buffer: &BufferState) -> Result<(), String> {
+        let key = match inner.since_key {
+            Some(n) => n + 1 >= self.config.gop_length,
+            None => true,
+        };
+        let Frame {
+            data,
+            frame_type,
+            key,
+        } = inner.generator.frame(inner.sequence, key);
+        buffer.write(&data)?;
+        let mut meta = buffer.lock();
+        meta.frame_type = frame_type;
+        meta.key = key;
+        meta.sequence = inner.sequence;
+        meta.timestamp = clock::now_us(libc::CLOCK_MONOTONIC) as u64;
+        meta.utc_timestamp = clock::now_us(libc::CLOCK_REALTIME) as u64;
+        inner.sequence = inner.sequence.wrapping_add(1);
+        inner.since_key = Some(if key {
+            0
+        } else {
+            inner.since_key.unwrap_or(0) + 1
+        });
+        Ok(())
+    }
+}
+
+/// Creates the file descriptor of a semaphore.
+fn semaphore() -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::eventfd(0, libc::EFD_SEMAPHORE | libc::EFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn timer() -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn signal(fd: &OwnedFd) {
+    let one = 1u64;
+    let written = unsafe { libc::write(fd.as_raw_fd(), (&one as *const u64).cast(), 8) };
+    debug_assert_eq!(written, 8, "expect an eventfd to accept a write");
+}
+
+/// Blocks until `fd`, an eventfd or a timerfd, is readable and reads its counter.
+fn wait(fd: RawFd) -> io::Result<u64> {
+    let mut value = 0u64;
+    loop {
+        let n = unsafe { libc::read(fd, (&mut value as *mut u64).cast(), 8) };
+        if n == 8 {
+            return Ok(value);
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+unsafe fn shared<'a>(stream: *mut VdoStream) -> &'a Arc<Shared> {
+    &state::<StreamState>(stream.cast()).shared
+}
+
+fn new_stream(
+    settings: BTreeMap<String, Variant>,
+) -> Result<Object, (vdo_sys::_bindgen_ty_24, String)> {
+    if is_unavailable() {
+        return Err((
+            vdo_sys::VDO_ERROR_BUSY,
+            "The video service is unavailable".to_string(),
+        ));
+    }
+    let config = Config::new(&settings)?;
+    let generator = config.generator()?;
+    let io = |e: io::Error| (vdo_sys::VDO_ERROR_IO, e.to_string());
+    let frames = if config.explicit {
+        semaphore().map_err(io)?
+    } else {
+        timer().map_err(io)?
+    };
+    let shared = Arc::new(Shared {
+        frames,
+        events: semaphore().map_err(io)?,
+        inner: Mutex::new(Inner {
+            settings,
+            generator,
+            framerate: config.framerate,
+            started: false,
+            stopped: false,
+            sequence: 0,
+            since_key: None,
+            events: VecDeque::new(),
+            free: VecDeque::new(),
+            encoded: VecDeque::new(),
+        }),
+        config,
+    });
+    let mut streams = lock(&STREAMS);
+    streams.retain(|s| s.strong_count() > 0);
+    streams.push(Arc::downgrade(&shared));
+    Ok(Object(new_object(StreamState { shared })))
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_new(
+    settings: *mut VdoMap,
+    _fin: VdoBufferFinalizer,
+    error: *mut *mut GError,
+) -> *mut VdoStream {
+    let settings = if settings.is_null() {
+        BTreeMap::new()
+    } else {
+        map::entries(settings)
+    };
+    match new_stream(settings) {
+        Ok(stream) => stream.into_raw(),
+        Err((code, message)) => fail(error, code, &message, std::ptr::null_mut()),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_get_info(
+    self_: *mut VdoStream,
+    _error: *mut *mut GError,
+) -> *mut VdoMap {
+    let shared = shared(self_);
+    let mut info = shared.config.info();
+    info.insert(
+        "framerate".to_string(),
+        Variant::from(shared.lock().framerate),
+    );
+    map::new(info)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_get_settings(
+    self_: *mut VdoStream,
+    _error: *mut *mut GError,
+) -> *mut VdoMap {
+    map::new(shared(self_).lock().settings.clone())
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_set_settings(
+    self_: *mut VdoStream,
+    settings: *mut VdoMap,
+    error: *mut *mut GError,
+) -> gboolean {
+    let settings = map::entries(settings);
+    if let Some(framerate) = settings.get("framerate") {
+        let framerate = framerate
+            .get::<f64>()
+            .or_else(|| map::integer(framerate).map(|v| v as f64))
+            .unwrap_or(0.0);
+        if vdo_stream_set_framerate(self_, framerate, error) == GFALSE {
+            return GFALSE;
+        }
+    }
+    shared(self_).lock().settings.extend(settings);
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_set_framerate(
+    self_: *mut VdoStream,
+    framerate: f64,
+    error: *mut *mut GError,
+) -> gboolean {
+    if framerate <= 0.0 || !framerate.is_finite() {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_INVALID_ARGUMENT,
+            "The framerate must be positive",
+            GFALSE,
+        );
+    }
+    let shared = shared(self_);
+    let mut inner = shared.lock();
+    inner.framerate = framerate;
+    if inner.started && !shared.config.explicit {
+        if let Err(e) = shared.set_timer(Some(framerate)) {
+            return fail(error, vdo_sys::VDO_ERROR_IO, &e.to_string(), GFALSE);
+        }
+    }
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_force_key_frame(
+    self_: *mut VdoStream,
+    _error: *mut *mut GError,
+) -> gboolean {
+    shared(self_).lock().since_key = None;
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_start(self_: *mut VdoStream, error: *mut *mut GError) -> gboolean {
+    let shared = shared(self_);
+    let mut inner = shared.lock();
+    if inner.stopped {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_CLOSED,
+            "The stream has been stopped",
+            GFALSE,
+        );
+    }
+    if !shared.config.explicit {
+        if let Err(e) = shared.set_timer(Some(inner.framerate)) {
+            return fail(error, vdo_sys::VDO_ERROR_IO, &e.to_string(), GFALSE);
+        }
+    }
+    inner.started = true;
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_stop(self_: *mut VdoStream) {
+    let shared = shared(self_);
+    let mut inner = shared.lock();
+    inner.started = false;
+    if !shared.config.explicit {
+        let _ = shared.set_timer(None);
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_get_fd(self_: *mut VdoStream, _error: *mut *mut GError) -> i32 {
+    shared(self_).frames.as_raw_fd()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_get_event_fd(
+    self_: *mut VdoStream,
+    _error: *mut *mut GError,
+) -> i32 {
+    shared(self_).events.as_raw_fd()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_get_event(
+    self_: *mut VdoStream,
+    error: *mut *mut GError,
+) -> *mut VdoMap {
+    let shared = shared(self_);
+    if let Err(e) = wait(shared.events.as_raw_fd()) {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_IO,
+            &e.to_string(),
+            std::ptr::null_mut(),
+        );
+    }
+    match shared.lock().events.pop_front() {
+        Some(event) => map::new(BTreeMap::from([(
+            "event".to_string(),
+            Variant::from(event.0),
+        )])),
+        None => fail(
+            error,
+            vdo_sys::VDO_ERROR_NO_EVENT,
+            "No event is pending",
+            std::ptr::null_mut(),
+        ),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_get_buffer(
+    self_: *mut VdoStream,
+    error: *mut *mut GError,
+) -> *mut VdoBuffer {
+    let shared = shared(self_);
+    if shared.config.explicit {
+        let mut inner = shared.lock();
+        let Some(buffer) = inner.encoded.pop_front() else {
+            return fail(
+                error,
+                vdo_sys::VDO_ERROR_NO_DATA,
+                "No frame has been encoded",
+                std::ptr::null_mut(),
+            );
+        };
+        // The semaphore counts the encoded buffers, so this does not block.
+        let _ = wait(shared.frames.as_raw_fd());
+        return buffer.into_raw();
+    }
+
+    if !shared.lock().started {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NO_DATA,
+            "The stream is not running",
+            std::ptr::null_mut(),
+        );
+    }
+    // The lock is not held while waiting, so that the stream can be stopped meanwhile.
+    if let Err(e) = wait(shared.frames.as_raw_fd()) {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_IO,
+            &e.to_string(),
+            std::ptr::null_mut(),
+        );
+    }
+    let mut inner = shared.lock();
+    if inner.stopped || !inner.started {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NO_DATA,
+            "The stream has been stopped",
+            std::ptr::null_mut(),
+        );
+    }
+    let buffer = Object(new_buffer(inner.generator.capacity(), std::ptr::null_mut()).cast());
+    match shared.produce(&mut inner, buffer_state(buffer.0.cast())) {
+        Ok(()) => buffer.into_raw(),
+        Err(message) => fail(
+            error,
+            vdo_sys::VDO_ERROR_NO_BUFFER_SPACE,
+            &message,
+            std::ptr::null_mut(),
+        ),
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_buffer_alloc(
+    self_: *mut VdoStream,
+    opaque: gpointer,
+    error: *mut *mut GError,
+) -> *mut VdoBuffer {
+    let shared = shared(self_);
+    if !shared.config.explicit {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+            "Buffers can only be allocated for streams with explicit buffers",
+            std::ptr::null_mut(),
+        );
+    }
+    new_buffer(shared.lock().generator.capacity(), opaque)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_buffer_enqueue(
+    self_: *mut VdoStream,
+    buffer: *mut VdoBuffer,
+    error: *mut *mut GError,
+) -> gboolean {
+    let shared = shared(self_);
+    if !shared.config.explicit {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+            "Buffers can only be enqueued to streams with explicit buffers",
+            GFALSE,
+        );
+    }
+    shared.lock().free.push_back(Object(buffer.cast()));
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_buffer_unref(
+    _self: *mut VdoStream,
+    buffer: *mut *mut VdoBuffer,
+    error: *mut *mut GError,
+) -> gboolean {
+    if buffer.is_null() || (*buffer).is_null() {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_INVALID_ARGUMENT,
+            "No buffer was given",
+            GFALSE,
+        );
+    }
+    drop(Object((*buffer).cast()));
+    *buffer = std::ptr::null_mut();
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_encode(
+    self_: *mut VdoStream,
+    in_buf: *mut *mut VdoBuffer,
+    _settings: *mut VdoMap,
+    error: *mut *mut GError,
+) -> gboolean {
+    let shared = shared(self_);
+    if !shared.config.explicit {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NOT_SUPPORTED,
+            "Only streams with explicit buffers can encode",
+            GFALSE,
+        );
+    }
+    if in_buf.is_null() || (*in_buf).is_null() {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_INVALID_ARGUMENT,
+            "No buffer was given",
+            GFALSE,
+        );
+    }
+    // The input buffer is consumed also on failure.
+    let input = Object((*in_buf).cast());
+    *in_buf = std::ptr::null_mut();
+    let (timestamp, custom_timestamp) = {
+        let meta = buffer_state(input.0.cast()).lock();
+        (meta.timestamp, meta.custom_timestamp)
+    };
+
+    let mut inner = shared.lock();
+    if !inner.started {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NOT_CONTROLLED,
+            "The encoder is not running",
+            GFALSE,
+        );
+    }
+    let Some(output) = inner.free.pop_front() else {
+        return fail(
+            error,
+            vdo_sys::VDO_ERROR_NO_BUFFER_SPACE,
+            "No buffer has been enqueued for the encoded frame",
+            GFALSE,
+        );
+    };
+    let state = buffer_state(output.0.cast());
+    if let Err(message) = shared.produce(&mut inner, state) {
+        inner.free.push_front(output);
+        return fail(error, vdo_sys::VDO_ERROR_NO_BUFFER_SPACE, &message, GFALSE);
+    }
+    {
+        let mut meta = state.lock();
+        meta.timestamp = timestamp;
+        meta.custom_timestamp = custom_timestamp;
+    }
+    inner.encoded.push_back(output);
+    signal(&shared.frames);
+    GTRUE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_stream_snapshot(
+    settings: *mut VdoMap,
+    error: *mut *mut GError,
+) -> *mut VdoBuffer {
+    let mut settings = if settings.is_null() {
+        BTreeMap::new()
+    } else {
+        map::entries(settings)
+    };
+    settings.remove("buffer.strategy");
+    let stream = match new_stream(settings) {
+        Ok(stream) => stream,
+        Err((code, message)) => return fail(error, code, &message, std::ptr::null_mut()),
+    };
+    let shared = shared(stream.0.cast());
+    let mut inner = shared.lock();
+    let buffer = Object(new_buffer(inner.generator.capacity(), std::ptr::null_mut()).cast());
+    match shared.produce(&mut inner, buffer_state(buffer.0.cast())) {
+        Ok(()) => buffer.into_raw(),
+        Err(message) => fail(
+            error,
+            vdo_sys::VDO_ERROR_NO_BUFFER_SPACE,
+            &message,
+            std::ptr::null_mut(),
+        ),
+    }
+}
+
+/// The memory and metadata of a buffer.
+struct BufferState {
+    // Allocated with `Box<[u8]>` and written through `vdo_buffer_get_data`.
+    data: *mut u8,
+    capacity: usize,
+    opaque: usize,
+    meta: Mutex<Meta>,
+}
+
+// SAFETY: The memory is owned by the buffer, and like in VDO the application is responsible for
+// not accessing it concurrently.
+unsafe impl Send for BufferState {}
+unsafe impl Sync for BufferState {}
+
+struct Meta {
+    size: usize,
+    frame_type: VdoFrameType,
+    key: bool,
+    sequence: u32,
+    timestamp: u64,
+    utc_timestamp: u64,
+    custom_timestamp: i64,
+}
+
+impl Default for Meta {
+    fn default() -> Self {
+        Self {
+            size: 0,
+            frame_type: VdoFrameType::VDO_FRAME_TYPE_NONE,
+            key: false,
+            sequence: 0,
+            timestamp: 0,
+            utc_timestamp: 0,
+            custom_timestamp: 0,
+        }
+    }
+}
+
+impl BufferState {
+    fn lock(&self) -> MutexGuard<'_, Meta> {
+        lock(&self.meta)
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), String> {
+        if data.len() > self.capacity {
+            return Err(format!(
+                "The frame of {} bytes exceeds the capacity of {} bytes",
+                data.len(),
+                self.capacity
+            ));
+        }
+        // SAFETY: The memory holds `capacity` bytes.
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), self.data, data.len()) };
+        self.lock().size = data.len();
+        Ok(())
+    }
+}
+
+impl Drop for BufferState {
+    fn drop(&mut self) {
+        let data = std::ptr::slice_from_raw_parts_mut(self.data, self.capacity);
+        // SAFETY: The memory was allocated as a boxed slice of `capacity` bytes.
+        drop(unsafe { Box::from_raw(data) });
+    }
+}
+
+fn new_buffer(capacity: usize, opaque: gpointer) -> *mut VdoBuffer {
+    let data = Box::into_raw(vec![0u8; capacity].into_boxed_slice());
+    new_object(BufferState {
+        data: data.cast(),
+        capacity,
+        opaque: opaque as usize,
+        meta: Mutex::new(Meta::default()),
+    })
+    .cast()
+}
+
+unsafe fn buffer_state<'a>(buffer: *mut VdoBuffer) -> &'a BufferState {
+    state::<BufferState>(buffer as *const c_void)
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_buffer_get_data(self_: *mut VdoBuffer) -> gpointer {
+    buffer_state(self_).data.cast()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_buffer_get_capacity(self_: *mut VdoBuffer) -> usize {
+    buffer_state(self_).capacity
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_buffer_get_fd(_self: *mut VdoBuffer) -> i32 {
+    -1
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_buffer_get_offset(_self: *mut VdoBuffer) -> i64 {
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_opaque(self_: *mut VdoBuffer) -> gpointer {
+    buffer_state(self_).opaque as gpointer
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_size(self_: *mut VdoBuffer) -> usize {
+    buffer_state(self_).lock().size
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_set_size(self_: *mut VdoBuffer, size: usize) {
+    let state = buffer_state(self_);
+    state.lock().size = size.min(state.capacity);
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_header_size(_self: *mut VdoBuffer) -> isize {
+    0
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_frame_type(self_: *mut VdoBuffer) -> VdoFrameType {
+    buffer_state(self_).lock().frame_type
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_set_frame_type(self_: *mut VdoBuffer, type_: VdoFrameType) {
+    buffer_state(self_).lock().frame_type = type_;
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_is_key(self_: *mut VdoBuffer) -> gboolean {
+    buffer_state(self_).lock().key.into_glib()
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_sequence_nbr(self_: *mut VdoBuffer) -> u32 {
+    buffer_state(self_).lock().sequence
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_timestamp(self_: *mut VdoBuffer) -> u64 {
+    buffer_state(self_).lock().timestamp
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_set_timestamp(self_: *mut VdoBuffer, timestamp: u64) {
+    buffer_state(self_).lock().timestamp = timestamp;
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_utc_timestamp(self_: *mut VdoBuffer) -> u64 {
+    buffer_state(self_).lock().utc_timestamp
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_custom_timestamp(self_: *mut VdoBuffer) -> i64 {
+    buffer_state(self_).lock().custom_timestamp
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_set_custom_timestamp(self_: *mut VdoBuffer, timestamp: i64) {
+    buffer_state(self_).lock().custom_timestamp = timestamp;
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_is_last_buffer(_self: *mut VdoBuffer) -> gboolean {
+    GFALSE
+}
+
+#[no_mangle]
+unsafe extern "C" fn vdo_frame_get_extra_info(_self: *mut VdoBuffer) -> *mut VdoMap {
+    std::ptr::null_mut()
+}